@@ -0,0 +1,450 @@
+//! Minecraft Java Edition worlds in the Anvil format, the `.mca` files in a world's
+//! `region` directory, for worlds saved by 1.18 or later.
+//!
+//! A region file covers 32x32 chunks. It starts with 1024 big endian location
+//! entries (first 4 KiB sector in three bytes, sector count in one) and 1024
+//! timestamps, then each chunk as a `u32` length, a compression byte and the
+//! compressed NBT. Every section in the chunk's `sections` list has a block state
+//! palette of `Name` and `Properties` compounds and the palette indices packed
+//! into longs, at least 4 bits each and never spanning two longs.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::ZlibEncoder,
+    Compression,
+};
+
+use crate::{
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, PalettedContainer, Section, WorldHeight, SECTION_HEIGHT, SECTION_VOLUME},
+    coords::ChunkPos,
+    nbt::{self, Compound, Tag},
+    region::REGION_SIZE,
+};
+
+/// Data version written into exported chunks, Minecraft 1.20.1.
+pub const DATA_VERSION: i32 = 3465;
+/// First snapshot storing sections at the root of the chunk (21w43a, before 1.18).
+const MIN_DATA_VERSION: i64 = 2844;
+const SECTOR_SIZE: usize = 4096;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+/// Location and timestamp tables.
+const HEADER_SIZE: usize = 2 * SECTOR_SIZE;
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+const COMPRESSION_NONE: u8 = 3;
+const NAMESPACE: &str = "minecraft:";
+/// Status of chunks done generating, older versions leave out the namespace.
+const FULL_STATUS: &str = "full";
+
+/// How vanilla block names map onto the registry. Names are compared without the
+/// `minecraft:` namespace and properties our blocks don't have are dropped.
+#[derive(Clone, Debug)]
+pub struct BlockMapping {
+    /// Vanilla names that are a different block of ours, like `grass_block` to `grass`.
+    pub import: HashMap<String, String>,
+    /// Our names that are a different vanilla block, like `grass` to `grass_block`.
+    pub export: HashMap<String, String>,
+    /// Used in place of vanilla blocks the registry doesn't have.
+    pub fallback: Block,
+}
+impl BlockMapping {
+    /// Names of blocks we have under another name, unknown blocks becoming air.
+    pub fn vanilla() -> Self {
+        let import = [
+            ("grass_block", "grass"),
+            ("cave_air", "air"),
+            ("void_air", "air"),
+            ("light_blue_stained_glass", "stained_glass"),
+        ];
+        let export = [
+            ("grass", "grass_block"),
+            ("stained_glass", "light_blue_stained_glass"),
+        ];
+        BlockMapping {
+            import: import
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+            export: export
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+            fallback: Block::AIR,
+        }
+    }
+    /// Our state for a vanilla block, `None` if the registry doesn't have the block.
+    pub fn import_state<'a>(
+        &self,
+        registry: &BlockRegistry,
+        name: &str,
+        properties: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Option<Block> {
+        let name = name.strip_prefix(NAMESPACE).unwrap_or(name);
+        let name = self.import.get(name).map_or(name, String::as_str);
+        let mut block = registry.default_state(registry.by_name(name)?);
+        for (key, value) in properties {
+            if let Some(with) = registry.with_property(block, key, value) {
+                block = with;
+            }
+        }
+        Some(block)
+    }
+    /// Our state for a vanilla block state string like `minecraft:oak_log[axis=x]`.
+    pub fn import_name(&self, registry: &BlockRegistry, state: &str) -> Option<Block> {
+        let (name, properties) = match state.split_once('[') {
+            Some((name, rest)) => (name, rest.strip_suffix(']')?),
+            None => (state, ""),
+        };
+        let properties = properties
+            .split(',')
+            .filter_map(|p| p.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()));
+        self.import_state(registry, name.trim(), properties)
+    }
+    /// Vanilla block state string of our state, the way `import_name` reads it.
+    pub fn export_name(&self, registry: &BlockRegistry, block: Block) -> String {
+        let (mut name, properties) = self.export_state(registry, block);
+        if !properties.is_empty() {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            name.push('[');
+            name.push_str(&properties.join(","));
+            name.push(']');
+        }
+        name
+    }
+    /// Our state for a palette entry of `Name` and `Properties` tags, the fallback for
+    /// blocks we don't have, whose names are added to `unknown`.
+    pub(crate) fn import_entry(
+        &self,
+        registry: &BlockRegistry,
+        entry: &Tag,
+        unknown: &mut HashSet<String>,
+    ) -> anyhow::Result<Block> {
+        let entry = entry
+            .as_compound()
+            .context("palette entry isn't a compound")?;
+        let name = entry.string("Name")?;
+        let properties = entry
+            .get("Properties")
+            .and_then(Tag::as_compound)
+            .into_iter()
+            .flat_map(Compound::iter)
+            .filter_map(|(key, value)| Some((key, value.as_str()?)));
+        Ok(self
+            .import_state(registry, name, properties)
+            .unwrap_or_else(|| {
+                unknown.insert(name.to_string());
+                self.fallback
+            }))
+    }
+    /// Palette entry of `Name` and `Properties` tags for our state.
+    pub(crate) fn export_entry(&self, registry: &BlockRegistry, block: Block) -> Tag {
+        let (name, properties) = self.export_state(registry, block);
+        let mut entry = Compound::new();
+        entry.insert("Name", Tag::String(name));
+        if !properties.is_empty() {
+            let mut compound = Compound::new();
+            for (key, value) in properties {
+                compound.insert(key, Tag::String(value));
+            }
+            entry.insert("Properties", Tag::Compound(compound));
+        }
+        Tag::Compound(entry)
+    }
+    /// Namespaced vanilla name and properties of our state.
+    pub fn export_state(
+        &self,
+        registry: &BlockRegistry,
+        block: Block,
+    ) -> (String, Vec<(String, String)>) {
+        let def = registry.def(block);
+        let name = self.export.get(&def.name).unwrap_or(&def.name);
+        let properties = def
+            .properties
+            .iter()
+            .filter_map(|p| {
+                let value = registry.property(block, &p.name)?;
+                Some((p.name.clone(), value.to_string()))
+            })
+            .collect();
+        (format!("{}{}", NAMESPACE, name), properties)
+    }
+}
+/// File name of the region holding the chunk, like `r.-1.0.mca`.
+pub fn region_file_name(pos: ChunkPos) -> String {
+    format!(
+        "r.{}.{}.mca",
+        pos.x.div_euclid(REGION_SIZE),
+        pos.z.div_euclid(REGION_SIZE)
+    )
+}
+/// Every fully generated chunk in the region file. Chunks vanilla hasn't finished
+/// generating are skipped, and vanilla blocks the mapping can't place become its
+/// fallback.
+pub fn read_region(
+    path: &Path,
+    registry: &BlockRegistry,
+    height: WorldHeight,
+    mapping: &BlockMapping,
+) -> anyhow::Result<Vec<(ChunkPos, ChunkBlocks)>> {
+    let data = fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    if data.len() < HEADER_SIZE {
+        bail!("{} is too short for a region file", path.display());
+    }
+    let mut unknown = HashSet::new();
+    let mut chunks = vec![];
+    for index in 0..CHUNKS_PER_REGION {
+        let location = u32::from_be_bytes(data[index * 4..index * 4 + 4].try_into().unwrap());
+        let sector = (location >> 8) as usize;
+        if sector == 0 {
+            continue;
+        }
+        let root = read_chunk_nbt(&data, sector)
+            .with_context(|| format!("chunk {} of {}", index, path.display()))?;
+        let chunk = import_chunk(&root, registry, height, mapping, &mut unknown)
+            .with_context(|| format!("chunk {} of {}", index, path.display()))?;
+        chunks.extend(chunk);
+    }
+    log_unknown(&path.display().to_string(), unknown);
+    Ok(chunks)
+}
+/// Warns once about every vanilla block an import replaced with the fallback.
+pub(crate) fn log_unknown(source: &str, unknown: HashSet<String>) {
+    if !unknown.is_empty() {
+        let mut unknown: Vec<String> = unknown.into_iter().collect();
+        unknown.sort();
+        log::warn!(
+            "{}: replaced blocks we don't have: {}",
+            source,
+            unknown.join(", ")
+        );
+    }
+}
+/// Writes the chunks into a new region file, replacing the file if it exists. All
+/// of them have to be in the same region.
+pub fn write_region<'a>(
+    path: &Path,
+    chunks: impl IntoIterator<Item = (ChunkPos, &'a ChunkBlocks)>,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<()> {
+    let mut header = vec![0; HEADER_SIZE];
+    let mut body = vec![];
+    let mut region = None;
+    for (pos, blocks) in chunks {
+        let key = (pos.x.div_euclid(REGION_SIZE), pos.z.div_euclid(REGION_SIZE));
+        if *region.get_or_insert(key) != key {
+            bail!("chunk {:?} isn't in region {:?}", pos, region.unwrap());
+        }
+        let root = export_chunk(pos, blocks, registry, mapping);
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&nbt::write("", &root))?;
+        let compressed = encoder.finish()?;
+
+        let sector = HEADER_SIZE / SECTOR_SIZE + body.len() / SECTOR_SIZE;
+        body.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+        body.push(COMPRESSION_ZLIB);
+        body.extend_from_slice(&compressed);
+        body.resize(body.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+        let sectors = body.len() / SECTOR_SIZE + HEADER_SIZE / SECTOR_SIZE - sector;
+        if sectors > u8::MAX as usize {
+            bail!("chunk {:?} is too big for a region file", pos);
+        }
+
+        let index = (pos.x.rem_euclid(REGION_SIZE) + pos.z.rem_euclid(REGION_SIZE) * REGION_SIZE)
+            as usize
+            * 4;
+        let location = (sector as u32) << 8 | sectors as u32;
+        header[index..index + 4].copy_from_slice(&location.to_be_bytes());
+    }
+    header.extend_from_slice(&body);
+    fs::write(path, header).with_context(|| format!("couldn't write {}", path.display()))
+}
+fn read_chunk_nbt(data: &[u8], sector: usize) -> anyhow::Result<Compound> {
+    let start = sector * SECTOR_SIZE;
+    let Some(length) = data.get(start..start + 4) else {
+        bail!("chunk is past the end of the file");
+    };
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    let Some(record) = data
+        .get(start + 4..start + 4 + length)
+        .filter(|r| !r.is_empty())
+    else {
+        bail!("chunk is cut off");
+    };
+    let mut raw = vec![];
+    match record[0] {
+        COMPRESSION_GZIP => {
+            GzDecoder::new(&record[1..]).read_to_end(&mut raw)?;
+        }
+        COMPRESSION_ZLIB => {
+            ZlibDecoder::new(&record[1..]).read_to_end(&mut raw)?;
+        }
+        COMPRESSION_NONE => raw.extend_from_slice(&record[1..]),
+        other if other & 0x80 != 0 => bail!("chunks stored in .mcc files aren't supported"),
+        other => bail!("unsupported compression {}", other),
+    }
+    Ok(nbt::read(&raw)?.1)
+}
+fn import_chunk(
+    root: &Compound,
+    registry: &BlockRegistry,
+    height: WorldHeight,
+    mapping: &BlockMapping,
+    unknown: &mut HashSet<String>,
+) -> anyhow::Result<Option<(ChunkPos, ChunkBlocks)>> {
+    let version = root.int("DataVersion")?;
+    if version < MIN_DATA_VERSION {
+        bail!(
+            "saved by data version {}, open and save the world in Minecraft 1.18 or later first",
+            version
+        );
+    }
+    let pos = ChunkPos::new(root.int("xPos")? as i32, root.int("zPos")? as i32);
+    let status = root.get("Status").and_then(Tag::as_str).unwrap_or_default();
+    if status.strip_prefix(NAMESPACE).unwrap_or(status) != FULL_STATUS {
+        log::debug!("skipping chunk {:?}, its status is {:?}", pos, status);
+        return Ok(None);
+    }
+    let mut blocks = ChunkBlocks::new(height);
+    for section in root.list("sections")? {
+        let section = section.as_compound().context("section isn't a compound")?;
+        let y = section.int("Y")? as i32 * SECTION_HEIGHT as i32;
+        let Some(states) = section.get("block_states").and_then(Tag::as_compound) else {
+            continue;
+        };
+        if !height.contains(y) {
+            continue;
+        }
+        let container = import_states(states, registry, mapping, unknown)
+            .with_context(|| format!("section at y {}", y))?;
+        *blocks.section_mut(height.section_index(y)) = Section::from_blocks(container).unwrap();
+    }
+    Ok(Some((pos, blocks)))
+}
+fn import_states(
+    states: &Compound,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+    unknown: &mut HashSet<String>,
+) -> anyhow::Result<PalettedContainer<Block>> {
+    let palette = states
+        .list("palette")?
+        .iter()
+        .map(|entry| mapping.import_entry(registry, entry, unknown))
+        .collect::<anyhow::Result<Vec<Block>>>()?;
+    let bits = vanilla_bits(palette.len());
+    let data = match states.get("data") {
+        Some(_) => states
+            .long_array("data")?
+            .iter()
+            .map(|l| *l as u64)
+            .collect(),
+        None => vec![],
+    };
+    //sections of a single block have no data, anything else we can read the way it's packed
+    let bits = if data.is_empty() { 0 } else { bits };
+    PalettedContainer::from_parts(SECTION_VOLUME, palette, bits, data)
+        .context("block states don't match their palette")
+}
+fn export_chunk(
+    pos: ChunkPos,
+    blocks: &ChunkBlocks,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> Compound {
+    let height = blocks.height();
+    let sections = blocks
+        .sections()
+        .iter()
+        .enumerate()
+        .map(|(index, section)| {
+            let y = height
+                .section_min_y(index)
+                .div_euclid(SECTION_HEIGHT as i32);
+            let mut compound = Compound::new();
+            compound.insert("Y", Tag::Byte(y as i8));
+            compound.insert(
+                "block_states",
+                Tag::Compound(export_states(section, registry, mapping)),
+            );
+            let mut biomes = Compound::new();
+            biomes.insert(
+                "palette",
+                Tag::List(vec![Tag::String("minecraft:plains".to_string())]),
+            );
+            compound.insert("biomes", Tag::Compound(biomes));
+            Tag::Compound(compound)
+        })
+        .collect();
+
+    let mut root = Compound::new();
+    root.insert("DataVersion", Tag::Int(DATA_VERSION));
+    root.insert("xPos", Tag::Int(pos.x));
+    root.insert("zPos", Tag::Int(pos.z));
+    root.insert(
+        "yPos",
+        Tag::Int(height.min_y.div_euclid(SECTION_HEIGHT as i32)),
+    );
+    root.insert("Status", Tag::String(FULL_STATUS.to_string()));
+    root.insert("LastUpdate", Tag::Long(0));
+    root.insert("InhabitedTime", Tag::Long(0));
+    //vanilla lights the chunk again when it loads
+    root.insert("isLightOn", Tag::Byte(0));
+    root.insert("sections", Tag::List(sections));
+    root.insert("block_entities", Tag::List(vec![]));
+    root
+}
+fn export_states(section: &Section, registry: &BlockRegistry, mapping: &BlockMapping) -> Compound {
+    //only the states actually used, our palette keeps ones that were overwritten
+    let container = section.blocks();
+    let mut palette: Vec<Block> = vec![];
+    let indices: Vec<u64> = (0..SECTION_VOLUME)
+        .map(|i| {
+            let block = container.get(i);
+            let index = match palette.iter().position(|b| *b == block) {
+                Some(index) => index,
+                None => {
+                    palette.push(block);
+                    palette.len() - 1
+                }
+            };
+            index as u64
+        })
+        .collect();
+
+    let mut states = Compound::new();
+    let palette_tags = palette
+        .iter()
+        .map(|block| mapping.export_entry(registry, *block))
+        .collect();
+    states.insert("palette", Tag::List(palette_tags));
+    if palette.len() > 1 {
+        let bits = vanilla_bits(palette.len());
+        let per_long = 64 / bits as usize;
+        let mut data = vec![0i64; SECTION_VOLUME.div_ceil(per_long)];
+        for (i, index) in indices.into_iter().enumerate() {
+            data[i / per_long] |= (index << ((i % per_long) as u32 * bits)) as i64;
+        }
+        states.insert("data", Tag::LongArray(data));
+    }
+    states
+}
+/// Bits per block vanilla packs a block state palette of this size with.
+fn vanilla_bits(palette_len: usize) -> u32 {
+    let bits = usize::BITS - palette_len.saturating_sub(1).leading_zeros();
+    bits.max(4)
+}
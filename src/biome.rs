@@ -0,0 +1,146 @@
+//! Biomes, picked for each column from its temperature and humidity.
+
+use anyhow::bail;
+use serde::Deserialize;
+
+/// Numeric id of a biome, as listed in `worldgen.ron`. Chunks store one per column.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct BiomeId(pub u8);
+
+/// One entry of the `biomes` list in `worldgen.ron`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeDef {
+    pub name: String,
+    pub id: u8,
+    /// Range of temperatures the biome covers, climate values going from -1 to 1.
+    pub temperature: (f64, f64),
+    pub humidity: (f64, f64),
+    /// Top block of every column.
+    pub surface_block: String,
+    /// What is under the surface block, `subsurface_depth` blocks deep.
+    pub subsurface_block: String,
+    pub subsurface_depth: u32,
+    /// Everything further down.
+    pub stone_block: String,
+    /// Moves the terrain up or down from the middle of the height curve.
+    #[serde(default)]
+    pub height_offset: f64,
+    /// Stretches hills and valleys away from the middle of the height curve, 0 being
+    /// flat.
+    #[serde(default = "default_one")]
+    pub height_scale: f64,
+    /// Multiplies how many caves there are, 0 for none.
+    #[serde(default = "default_one")]
+    pub cave_density: f64,
+    /// Multiplies how wide tunnels are.
+    #[serde(default = "default_one")]
+    pub cave_size: f64,
+    /// Names of features from `features.ron` and how many of each to try per chunk.
+    #[serde(default)]
+    pub features: Vec<(String, f64)>,
+}
+fn default_one() -> f64 {
+    1.0
+}
+impl BiomeDef {
+    /// How far the climate is from the biome's ranges, 0 inside them.
+    pub fn climate_distance(&self, temperature: f64, humidity: f64) -> f64 {
+        let outside = |(min, max): (f64, f64), value: f64| (min - value).max(value - max).max(0.0);
+        outside(self.temperature, temperature).hypot(outside(self.humidity, humidity))
+    }
+}
+/// The biomes of a world, in the order they are checked.
+#[derive(Clone, Debug)]
+pub struct Biomes {
+    defs: Vec<BiomeDef>,
+    /// Climate distance over which heights blend into the neighboring biome.
+    blend: f64,
+}
+impl Biomes {
+    pub fn new(defs: Vec<BiomeDef>, blend: f64) -> anyhow::Result<Self> {
+        if defs.is_empty() {
+            bail!("there has to be at least one biome");
+        }
+        for (i, def) in defs.iter().enumerate() {
+            if let Some(other) = defs[..i].iter().find(|other| other.id == def.id) {
+                bail!("{} and {} both use id {}", other.name, def.name, def.id);
+            }
+            if defs[..i].iter().any(|other| other.name == def.name) {
+                bail!("biome {} is listed twice", def.name);
+            }
+            for (name, (min, max)) in [("temperature", def.temperature), ("humidity", def.humidity)]
+            {
+                if !min.is_finite() || !max.is_finite() || min > max {
+                    bail!("{} of {} goes from {} down to {}", name, def.name, min, max);
+                }
+            }
+            if !def.height_offset.is_finite() || !def.height_scale.is_finite() {
+                bail!("height modifiers of {} have to be finite", def.name);
+            }
+            if !(0.0..=16.0).contains(&def.cave_density) || !(0.0..=4.0).contains(&def.cave_size) {
+                bail!(
+                    "cave_density of {} has to be from 0 to 16 and cave_size up to 4",
+                    def.name
+                );
+            }
+            if let Some((name, _)) = def
+                .features
+                .iter()
+                .find(|(_, per_chunk)| !(0.0..=64.0).contains(per_chunk))
+            {
+                bail!("{} of {} has to be from 0 to 64 per chunk", name, def.name);
+            }
+        }
+        if !(blend.is_finite() && blend > 0.0) {
+            bail!("biome_blend has to be above 0, not {}", blend);
+        }
+        Ok(Biomes { defs, blend })
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &BiomeDef> {
+        self.defs.iter()
+    }
+    pub fn get(&self, id: BiomeId) -> Option<&BiomeDef> {
+        self.defs.iter().find(|def| def.id == id.0)
+    }
+    pub fn by_name(&self, name: &str) -> Option<&BiomeDef> {
+        self.defs.iter().find(|def| def.name == name)
+    }
+    /// Index into `defs` of the first biome covering the climate, or of the closest
+    /// one if none does.
+    fn pick(&self, temperature: f64, humidity: f64) -> usize {
+        self.defs
+            .iter()
+            .position(|def| def.climate_distance(temperature, humidity) == 0.0)
+            .unwrap_or_else(|| {
+                let distance = |i: &usize| self.defs[*i].climate_distance(temperature, humidity);
+                (0..self.defs.len())
+                    .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+                    .unwrap_or(0)
+            })
+    }
+    /// The biome of a column with this climate.
+    pub fn biome_at(&self, temperature: f64, humidity: f64) -> &BiomeDef {
+        &self.defs[self.pick(temperature, humidity)]
+    }
+    /// `height_offset` and `height_scale` of the column, averaged with every biome
+    /// less than `blend` further away in climate than the closest one. As the climate
+    /// changes smoothly from column to column so do the heights, even where the
+    /// biome changes.
+    pub fn height_modifiers(&self, temperature: f64, humidity: f64) -> (f64, f64) {
+        let distances: Vec<f64> = self
+            .defs
+            .iter()
+            .map(|def| def.climate_distance(temperature, humidity))
+            .collect();
+        let closest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+        let (mut offset, mut scale, mut total) = (0.0, 0.0, 0.0);
+        for (def, distance) in self.defs.iter().zip(distances) {
+            let weight = (closest + self.blend - distance).max(0.0);
+            offset += def.height_offset * weight;
+            scale += def.height_scale * weight;
+            total += weight;
+        }
+        (offset / total, scale / total)
+    }
+}
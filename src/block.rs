@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+/// Numeric id of a registered block, as listed in `blocks.ron`. Id 0 is always air.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct BlockType(pub u16);
+impl BlockType {
+    pub const AIR: BlockType = BlockType(0);
+}
+/// A block type together with the values of its properties (a log's axis, a fluid's
+/// level), interned by the registry into a single id. This is what chunks store.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct Block(pub u16);
+impl Block {
+    pub const AIR: Block = Block(0);
+    pub fn is_air(&self) -> bool {
+        *self == Block::AIR
+    }
+}
+/// Atlas tile of each face of a block.
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub struct FaceTextures {
+    pub top: u32,
+    pub bottom: u32,
+    pub side: u32,
+    /// Side tile used instead of `side` when the same block sits on top, like grass
+    /// turning to dirt under more grass.
+    #[serde(default)]
+    pub covered_side: Option<u32>,
+}
+/// What a block is drawn as.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Shape {
+    /// A full cube, its faces hidden by opaque neighbors.
+    #[default]
+    Cube,
+    /// Two crossed planes with the side texture, like flowers. They are never
+    /// culled, so the texture's see-through pixels have to be fully transparent.
+    Cross,
+}
+/// Which pass a block's faces are drawn in, each with its own blending.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum RenderLayer {
+    /// Covering whatever is behind it completely.
+    #[default]
+    Opaque,
+    /// Pixels either fully covering or fully see-through, like leaves and flowers.
+    Cutout,
+    /// Blended with what is behind it, like water. Drawn last, back to front.
+    Translucent,
+}
+impl RenderLayer {
+    /// In the order they are drawn.
+    pub const ALL: [RenderLayer; 3] = [
+        RenderLayer::Opaque,
+        RenderLayer::Cutout,
+        RenderLayer::Translucent,
+    ];
+}
+/// A property a block's states can have and its allowed values, the first being
+/// the default.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PropertyDef {
+    pub name: String,
+    pub values: Vec<String>,
+}
+/// How states matching `when` (like `"axis=x"` or `"facing=north,half=top"`) are
+/// drawn: other textures and/or the cube turned in steps of 90 degrees, first
+/// around the x axis and then around the y axis.
+#[derive(Clone, Debug, Deserialize)]
+pub struct VariantDef {
+    pub when: String,
+    #[serde(default)]
+    pub textures: Option<FaceTextures>,
+    #[serde(default)]
+    pub x: u16,
+    #[serde(default)]
+    pub y: u16,
+}
+/// Properties of one block type, one entry of `blocks.ron`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockDef {
+    pub name: String,
+    pub id: u16,
+    #[serde(default)]
+    pub textures: FaceTextures,
+    #[serde(default)]
+    pub shape: Shape,
+    /// Whether the player collides with it and stands on it.
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Whether faces of neighboring blocks behind it have to be drawn.
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub layer: RenderLayer,
+    /// Whether generated features like trees can grow through it, replacing it.
+    #[serde(default)]
+    pub replaceable: bool,
+    /// Light level the block emits, 0 to 15.
+    #[serde(default)]
+    pub light: u8,
+    #[serde(default)]
+    pub properties: Vec<PropertyDef>,
+    /// Checked in order, the first one matching a state is used.
+    #[serde(default)]
+    pub variants: Vec<VariantDef>,
+}
+fn default_true() -> bool {
+    true
+}
+/// What the mesher needs to draw a block state, resolved from its variant.
+#[derive(Copy, Clone, Debug)]
+pub struct BlockModel {
+    pub textures: FaceTextures,
+    pub shape: Shape,
+    /// Quarter turns around the x axis, applied first.
+    pub rotate_x: u8,
+    /// Quarter turns around the y axis.
+    pub rotate_y: u8,
+}
+struct BlockState {
+    block_type: BlockType,
+    /// Index into each property's values, in the order the properties are declared.
+    values: Vec<u8>,
+    model: BlockModel,
+}
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockDef>>,
+    by_name: HashMap<String, BlockType>,
+    /// First state id of each block type, its states follow contiguously.
+    first_state: Vec<u16>,
+    states: Vec<BlockState>,
+}
+impl BlockRegistry {
+    /// Registry of the block list shipped with the game.
+    pub fn load_default() -> anyhow::Result<Self> {
+        Self::from_ron(include_str!("blocks.ron")).context("failed to load blocks.ron")
+    }
+    pub fn from_ron(source: &str) -> anyhow::Result<Self> {
+        let defs: Vec<BlockDef> = ron::from_str(source)?;
+        Self::from_defs(defs)
+    }
+    pub fn from_defs(defs: Vec<BlockDef>) -> anyhow::Result<Self> {
+        let mut blocks: Vec<Option<BlockDef>> = vec![];
+        let mut by_name = HashMap::new();
+        for def in defs {
+            let id = def.id as usize;
+            if blocks.len() <= id {
+                blocks.resize(id + 1, None);
+            }
+            if let Some(existing) = &blocks[id] {
+                bail!("{} and {} both use id {}", existing.name, def.name, id);
+            }
+            if by_name
+                .insert(def.name.clone(), BlockType(def.id))
+                .is_some()
+            {
+                bail!("block {} is registered twice", def.name);
+            }
+            blocks[id] = Some(def);
+        }
+        match blocks.first() {
+            Some(Some(def)) if def.name == "air" && def.properties.is_empty() => {}
+            _ => bail!("id 0 must be air, without properties"),
+        }
+
+        //intern every combination of property values, air first so it gets state 0
+        let mut first_state = vec![0; blocks.len()];
+        let mut states = vec![];
+        for def in blocks.iter().flatten() {
+            first_state[def.id as usize] = states.len() as u16;
+            for values in property_combinations(def)? {
+                let model = resolve_model(def, &values)?;
+                states.push(BlockState {
+                    block_type: BlockType(def.id),
+                    values,
+                    model,
+                });
+            }
+            if states.len() > u16::MAX as usize {
+                bail!(
+                    "too many block states, the registry is full at {}",
+                    def.name
+                );
+            }
+        }
+        Ok(BlockRegistry {
+            blocks,
+            by_name,
+            first_state,
+            states,
+        })
+    }
+    /// Properties of the block type, unknown ids are treated as air.
+    pub fn get(&self, block_type: BlockType) -> &BlockDef {
+        match self.blocks.get(block_type.0 as usize) {
+            Some(Some(def)) => def,
+            _ => self.blocks[0].as_ref().unwrap(),
+        }
+    }
+    /// Properties of the block's type.
+    pub fn def(&self, block: Block) -> &BlockDef {
+        self.get(self.block_type(block))
+    }
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.by_name.get(name).copied()
+    }
+    /// Like `by_name` but for blocks the game can't work without.
+    pub fn expect(&self, name: &str) -> BlockType {
+        self.by_name(name)
+            .unwrap_or_else(|| panic!("block {} is missing from the registry", name))
+    }
+    pub fn is_solid(&self, block: Block) -> bool {
+        self.def(block).solid
+    }
+    pub fn is_transparent(&self, block: Block) -> bool {
+        self.def(block).transparent
+    }
+    pub fn layer(&self, block: Block) -> RenderLayer {
+        self.def(block).layer
+    }
+    pub fn is_replaceable(&self, block: Block) -> bool {
+        self.def(block).replaceable
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDef> {
+        self.blocks.iter().flatten()
+    }
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+    /// The state with every property at its first value.
+    pub fn default_state(&self, block_type: BlockType) -> Block {
+        match self.blocks.get(block_type.0 as usize) {
+            Some(Some(_)) => Block(self.first_state[block_type.0 as usize]),
+            _ => Block::AIR,
+        }
+    }
+    /// Type of the block state, unknown states are treated as air.
+    pub fn block_type(&self, block: Block) -> BlockType {
+        self.states
+            .get(block.0 as usize)
+            .map_or(BlockType::AIR, |state| state.block_type)
+    }
+    pub fn model(&self, block: Block) -> &BlockModel {
+        let state = self.states.get(block.0 as usize).unwrap_or(&self.states[0]);
+        &state.model
+    }
+    /// Value of the named property, `None` if the block doesn't have it.
+    pub fn property(&self, block: Block, name: &str) -> Option<&str> {
+        let state = self.states.get(block.0 as usize)?;
+        let def = self.get(state.block_type);
+        let index = def.properties.iter().position(|p| p.name == name)?;
+        Some(&def.properties[index].values[state.values[index] as usize])
+    }
+    /// The same block with one property changed, `None` if the block doesn't have
+    /// the property or the value isn't allowed.
+    pub fn with_property(&self, block: Block, name: &str, value: &str) -> Option<Block> {
+        let state = self.states.get(block.0 as usize)?;
+        let def = self.get(state.block_type);
+        let index = def.properties.iter().position(|p| p.name == name)?;
+        let value = def.properties[index]
+            .values
+            .iter()
+            .position(|v| v == value)?;
+        let mut values = state.values.clone();
+        values[index] = value as u8;
+        Some(self.state_of(def, &values))
+    }
+    /// Name of the state the way `parse_state` reads it, like `oak_log[axis=x]`.
+    pub fn state_name(&self, block: Block) -> String {
+        let def = self.def(block);
+        let mut name = def.name.clone();
+        if !def.properties.is_empty() {
+            let values = &self.states[block.0 as usize].values;
+            let properties: Vec<String> = def
+                .properties
+                .iter()
+                .zip(values)
+                .map(|(p, v)| format!("{}={}", p.name, p.values[*v as usize]))
+                .collect();
+            name.push('[');
+            name.push_str(&properties.join(","));
+            name.push(']');
+        }
+        name
+    }
+    /// Reads `name` or `name[property=value,...]`, properties left out keep their
+    /// default. `None` for unknown blocks, properties or values.
+    pub fn parse_state(&self, state: &str) -> Option<Block> {
+        let (name, properties) = match state.split_once('[') {
+            Some((name, rest)) => (name, rest.strip_suffix(']')?),
+            None => (state, ""),
+        };
+        let mut block = self.default_state(self.by_name(name)?);
+        for property in properties.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = property.split_once('=')?;
+            block = self.with_property(block, key.trim(), value.trim())?;
+        }
+        Some(block)
+    }
+    /// The block turned clockwise seen from above by quarter turns, which swaps an
+    /// `axis` of x and z and turns a `facing` from north towards east.
+    pub fn rotated(&self, block: Block, quarter_turns: u8) -> Block {
+        const FACINGS: [&str; 4] = ["north", "east", "south", "west"];
+        let mut block = block;
+        if quarter_turns % 2 == 1 {
+            let swapped = match self.property(block, "axis") {
+                Some("x") => Some("z"),
+                Some("z") => Some("x"),
+                _ => None,
+            };
+            if let Some(axis) = swapped {
+                block = self.with_property(block, "axis", axis).unwrap_or(block);
+            }
+        }
+        let facing = self
+            .property(block, "facing")
+            .and_then(|facing| FACINGS.iter().position(|f| *f == facing));
+        if let Some(index) = facing {
+            let facing = FACINGS[(index + quarter_turns as usize) % 4];
+            block = self.with_property(block, "facing", facing).unwrap_or(block);
+        }
+        block
+    }
+    fn state_of(&self, def: &BlockDef, values: &[u8]) -> Block {
+        let mut offset = 0;
+        for (property, value) in def.properties.iter().zip(values) {
+            offset = offset * property.values.len() + *value as usize;
+        }
+        Block(self.first_state[def.id as usize] + offset as u16)
+    }
+}
+/// Every combination of the block's property values, the last property counting
+/// fastest so the offset of a state matches `BlockRegistry::state_of`.
+fn property_combinations(def: &BlockDef) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut combinations = vec![vec![]];
+    for property in &def.properties {
+        if property.values.is_empty() || property.values.len() > u8::MAX as usize {
+            bail!(
+                "{}: property {} needs 1 to 255 values",
+                def.name,
+                property.name
+            );
+        }
+        combinations = combinations
+            .into_iter()
+            .flat_map(|values: Vec<u8>| {
+                (0..property.values.len() as u8).map(move |v| {
+                    let mut values = values.clone();
+                    values.push(v);
+                    values
+                })
+            })
+            .collect();
+    }
+    Ok(combinations)
+}
+fn resolve_model(def: &BlockDef, values: &[u8]) -> anyhow::Result<BlockModel> {
+    let mut matched = None;
+    for variant in &def.variants {
+        if variant.x % 90 != 0 || variant.y % 90 != 0 {
+            bail!(
+                "{}: variant {} has to rotate in steps of 90",
+                def.name,
+                variant.when
+            );
+        }
+        //every variant is checked so mistakes in later ones are still reported
+        if variant_matches(def, variant, values)? && matched.is_none() {
+            matched = Some(variant);
+        }
+    }
+    Ok(match matched {
+        Some(variant) => BlockModel {
+            textures: variant.textures.unwrap_or(def.textures),
+            shape: def.shape,
+            rotate_x: (variant.x / 90 % 4) as u8,
+            rotate_y: (variant.y / 90 % 4) as u8,
+        },
+        None => BlockModel {
+            textures: def.textures,
+            shape: def.shape,
+            rotate_x: 0,
+            rotate_y: 0,
+        },
+    })
+}
+fn variant_matches(def: &BlockDef, variant: &VariantDef, values: &[u8]) -> anyhow::Result<bool> {
+    for condition in variant.when.split(',').filter(|c| !c.is_empty()) {
+        let Some((key, value)) = condition.split_once('=') else {
+            bail!(
+                "{}: variant condition {} isn't key=value",
+                def.name,
+                condition
+            );
+        };
+        let Some(index) = def.properties.iter().position(|p| p.name == key.trim()) else {
+            bail!("{}: variant uses unknown property {}", def.name, key);
+        };
+        let property = &def.properties[index];
+        if !property.values.iter().any(|v| v == value.trim()) {
+            bail!("{}: {} isn't a value of {}", def.name, value, property.name);
+        }
+        if property.values[values[index] as usize] != value.trim() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,305 @@
+//! Overhangs and caves, shaped in 3D on top of the height map terrain.
+//!
+//! Everything here is a function of the world position and seed alone, so chunks
+//! match up whichever order they are generated in:
+//!
+//! - overhang noise moves the surface up or down block by block, leaving ledges and
+//!   arches along it
+//! - caverns open wherever the cavern noise is above a threshold, under a cover of
+//!   rock so they don't riddle the surface
+//! - worms are tunnels starting at random spots of every chunk. A chunk replays the
+//!   worms of every chunk close enough to reach it and carves the parts inside it.
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{
+    block::Block,
+    chunk::{ChunkBlocks, WorldHeight, CHUNK_WIDTH},
+    coords::{ChunkPos, LocalBlockPos, CHUNK_SIZE},
+    worldgen::{ChunkRng, FractalNoise, NoiseSettings},
+};
+
+/// Distance between the points noise is sampled at across, see `NoiseGrid`.
+const GRID_WIDTH: usize = 4;
+/// Distance between them up, for caverns. Overhangs need the noise to change a lot
+/// within a few blocks up, so they sample it every other block.
+const CAVERN_GRID_HEIGHT: usize = 8;
+const OVERHANG_GRID_HEIGHT: usize = 2;
+const WORM_SALT: u64 = 5;
+
+/// The `caves` part of `worldgen.ron`. Biomes scale it with their `cave_density` and
+/// `cave_size`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaveSettings {
+    pub overhang: NoiseSettings,
+    /// How many blocks the overhang noise moves the surface up or down at most, 0 to
+    /// keep the plain height map.
+    pub overhang_depth: f64,
+    /// How many times faster the overhang noise changes up than across. Higher makes
+    /// more ledges and fewer steep slopes.
+    pub overhang_stretch: f64,
+    pub cavern: NoiseSettings,
+    /// Caverns open where the cavern noise is above this divided by the biome's
+    /// `cave_density`.
+    pub cavern_threshold: f64,
+    /// Blocks of rock kept between caverns and the surface.
+    pub cavern_cover: u32,
+    /// Average number of worms starting in a chunk, times the biome's `cave_density`.
+    pub worms_per_chunk: f64,
+    /// Shortest and longest worm, in blocks.
+    pub worm_length: (u32, u32),
+    /// Thinnest and widest worm, times the biome's `cave_size`.
+    pub worm_radius: (f64, f64),
+    /// Heights worms start at.
+    pub worm_y: (i32, i32),
+    /// Nothing at or below this is carved, so the world keeps a floor.
+    pub min_y: i32,
+}
+impl CaveSettings {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        self.overhang.validate("overhang")?;
+        self.cavern.validate("cavern")?;
+        if !(self.overhang_depth.is_finite() && self.overhang_depth >= 0.0) {
+            bail!("overhang_depth can't be {}", self.overhang_depth);
+        }
+        if !(self.overhang_stretch.is_finite() && self.overhang_stretch > 0.0) {
+            bail!("overhang_stretch has to be above 0");
+        }
+        if !self.cavern_threshold.is_finite() {
+            bail!("cavern_threshold has to be finite");
+        }
+        if !(0.0..=16.0).contains(&self.worms_per_chunk) {
+            bail!("worms_per_chunk has to be from 0 to 16");
+        }
+        let (min, max) = self.worm_length;
+        if min > max || max > 512 {
+            bail!("worm_length has to be at most 512 blocks, shortest first");
+        }
+        let (min, max) = self.worm_radius;
+        if !(min > 0.0 && min <= max && max <= 16.0) {
+            bail!("worm_radius has to be above 0 and at most 16, thinnest first");
+        }
+        if self.worm_y.0 > self.worm_y.1 {
+            bail!("worm_y has to be lowest first");
+        }
+        Ok(())
+    }
+}
+/// Noise of one chunk sampled every 4 blocks across and every few up, and
+/// interpolated in between. Much cheaper than sampling every block and, as the samples are at the
+/// same world positions for every chunk, seamless across chunk borders.
+pub(crate) struct NoiseGrid {
+    min_y: i32,
+    step: usize,
+    layers: usize,
+    values: Vec<f64>,
+}
+impl NoiseGrid {
+    const SIDE: usize = CHUNK_WIDTH / GRID_WIDTH + 1;
+
+    /// `step` is the distance between samples up, `stretch` how many times faster
+    /// the noise changes up than across.
+    pub(crate) fn new(
+        noise: &FractalNoise,
+        pos: ChunkPos,
+        height: WorldHeight,
+        step: usize,
+        stretch: f64,
+    ) -> Self {
+        let layers = (height.max_y() - height.min_y) as usize / step + 1;
+        let (origin_x, origin_z) = pos.origin();
+        let mut values = Vec::with_capacity(Self::SIDE * Self::SIDE * layers);
+        for layer in 0..layers {
+            let y = height.min_y + (layer * step) as i32;
+            for gz in 0..Self::SIDE {
+                for gx in 0..Self::SIDE {
+                    values.push(noise.get3(
+                        (origin_x + (gx * GRID_WIDTH) as i32) as f64,
+                        y as f64 * stretch,
+                        (origin_z + (gz * GRID_WIDTH) as i32) as f64,
+                    ));
+                }
+            }
+        }
+        NoiseGrid {
+            min_y: height.min_y,
+            step,
+            layers,
+            values,
+        }
+    }
+    pub(crate) fn get(&self, x: usize, y: i32, z: usize) -> f64 {
+        let y = (y - self.min_y) as usize;
+        let (gx, gy, gz) = (x / GRID_WIDTH, y / self.step, z / GRID_WIDTH);
+        let gy = gy.min(self.layers - 2);
+        let tx = (x % GRID_WIDTH) as f64 / GRID_WIDTH as f64;
+        let tz = (z % GRID_WIDTH) as f64 / GRID_WIDTH as f64;
+        let ty = (y - gy * self.step) as f64 / self.step as f64;
+        let at = |dx: usize, dy: usize, dz: usize| {
+            self.values[((gy + dy) * Self::SIDE + gz + dz) * Self::SIDE + gx + dx]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let layer = |dy| {
+            lerp(
+                lerp(at(0, dy, 0), at(1, dy, 0), tx),
+                lerp(at(0, dy, 1), at(1, dy, 1), tx),
+                tz,
+            )
+        };
+        lerp(layer(0), layer(1), ty)
+    }
+}
+/// One step of a worm: a ball of air.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WormPoint {
+    pub pos: [f64; 3],
+    pub radius: f64,
+}
+/// A tunnel, as the balls carved along it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Worm {
+    pub points: Vec<WormPoint>,
+}
+pub struct Caves {
+    seed: u64,
+    settings: CaveSettings,
+    overhang: FractalNoise,
+    cavern: FractalNoise,
+    /// How many chunks away a worm can carve from the chunk it starts in.
+    reach: i32,
+}
+impl Caves {
+    /// `max_size` is the largest `cave_size` of any biome.
+    pub fn new(seed: u64, settings: CaveSettings, max_size: f64) -> Self {
+        let reach = settings.worm_length.1 as f64 + settings.worm_radius.1 * max_size;
+        Caves {
+            seed,
+            overhang: FractalNoise::new(seed, 3, &settings.overhang),
+            cavern: FractalNoise::new(seed, 4, &settings.cavern),
+            reach: (reach / CHUNK_SIZE as f64).ceil() as i32,
+            settings,
+        }
+    }
+    pub fn settings(&self) -> &CaveSettings {
+        &self.settings
+    }
+    pub(crate) fn overhang_grid(&self, pos: ChunkPos, height: WorldHeight) -> NoiseGrid {
+        NoiseGrid::new(
+            &self.overhang,
+            pos,
+            height,
+            OVERHANG_GRID_HEIGHT,
+            self.settings.overhang_stretch,
+        )
+    }
+    pub(crate) fn cavern_grid(&self, pos: ChunkPos, height: WorldHeight) -> NoiseGrid {
+        NoiseGrid::new(&self.cavern, pos, height, CAVERN_GRID_HEIGHT, 1.0)
+    }
+    /// Chunks whose worms can carve into the chunk.
+    pub fn sources(&self, pos: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+        let reach = self.reach;
+        (-reach..=reach).flat_map(move |dz| (-reach..=reach).map(move |dx| pos.offset(dx, dz)))
+    }
+    /// Worms starting in the chunk, given the `cave_density` and `cave_size` of its
+    /// biome.
+    pub fn worms(&self, chunk: ChunkPos, density: f64, size: f64) -> Vec<Worm> {
+        let settings = &self.settings;
+        let mut rng = ChunkRng::new(self.seed, WORM_SALT, chunk);
+        let expected = settings.worms_per_chunk * density;
+        let mut count = expected.floor() as u32;
+        if rng.next_f64() < expected.fract() {
+            count += 1;
+        }
+        let (origin_x, origin_z) = chunk.origin();
+        (0..count)
+            .map(|_| {
+                let mut pos = [
+                    origin_x as f64 + rng.next_f64() * CHUNK_SIZE as f64,
+                    rng.range(settings.worm_y.0, settings.worm_y.1) as f64 + 0.5,
+                    origin_z as f64 + rng.next_f64() * CHUNK_SIZE as f64,
+                ];
+                let length =
+                    rng.range(settings.worm_length.0 as i32, settings.worm_length.1 as i32);
+                let (thinnest, widest) = settings.worm_radius;
+                let radius = (thinnest + (widest - thinnest) * rng.next_f64()) * size;
+                //only basic arithmetic and square roots, which give the same result on
+                //every platform, unlike sin and cos
+                let mut direction = normalized([rng.next_f64() - 0.5, 0.0, rng.next_f64() - 0.5]);
+                let mut turn = [0.0; 3];
+                let mut points = Vec::with_capacity(length as usize);
+                for step in 0..length {
+                    let t = step as f64 / length as f64;
+                    //widest in the middle, narrowing towards both ends
+                    points.push(WormPoint {
+                        pos,
+                        radius: radius * (0.5 + 2.0 * t * (1.0 - t)),
+                    });
+                    for axis in 0..3 {
+                        turn[axis] = turn[axis] * 0.8 + (rng.next_f64() - 0.5) * 0.12;
+                        direction[axis] += turn[axis];
+                    }
+                    //tunnels mostly run level
+                    direction[1] *= 0.85;
+                    direction = normalized(direction);
+                    for axis in 0..3 {
+                        pos[axis] += direction[axis];
+                    }
+                }
+                Worm { points }
+            })
+            .collect()
+    }
+    /// Turns the blocks of the chunk inside the worm into air, except for `water` so
+    /// seas don't drain into tunnels.
+    pub(crate) fn carve_worm(
+        &self,
+        worm: &Worm,
+        pos: ChunkPos,
+        blocks: &mut ChunkBlocks,
+        water: Block,
+    ) {
+        let (origin_x, origin_z) = pos.origin();
+        let height = blocks.height();
+        let min_y = (self.settings.min_y + 1).max(height.min_y);
+        let max_y = height.max_y() - 1;
+        for point in &worm.points {
+            let [x, y, z] = point.pos;
+            let r = point.radius;
+            let from = |v: f64, min: i32| ((v - r).floor() as i32).max(min);
+            let to = |v: f64, max: i32| ((v + r).floor() as i32).min(max);
+            let (x0, x1) = (from(x, origin_x), to(x, origin_x + CHUNK_SIZE - 1));
+            let (z0, z1) = (from(z, origin_z), to(z, origin_z + CHUNK_SIZE - 1));
+            let (y0, y1) = (from(y, min_y), to(y, max_y));
+            for bz in z0..=z1 {
+                for bx in x0..=x1 {
+                    for by in y0..=y1 {
+                        let d = [
+                            bx as f64 + 0.5 - x,
+                            by as f64 + 0.5 - y,
+                            bz as f64 + 0.5 - z,
+                        ];
+                        if d[0] * d[0] + d[1] * d[1] + d[2] * d[2] < r * r {
+                            let local = LocalBlockPos::new(
+                                (bx - origin_x) as usize,
+                                by,
+                                (bz - origin_z) as usize,
+                            );
+                            if blocks.get(local) != water {
+                                blocks.set(local, Block::AIR);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+fn normalized(v: [f64; 3]) -> [f64; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length < 1e-9 {
+        return [1.0, 0.0, 0.0];
+    }
+    v.map(|c| c / length)
+}
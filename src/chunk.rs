@@ -0,0 +1,328 @@
+use crate::{biome::BiomeId, block::Block, coords::LocalBlockPos, engine::Mesh};
+
+pub const CHUNK_WIDTH: usize = 16;
+pub const SECTION_HEIGHT: usize = 16;
+pub const SECTION_VOLUME: usize = CHUNK_WIDTH * CHUNK_WIDTH * SECTION_HEIGHT;
+
+/// Vertical extent of the world, made of whole sections.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WorldHeight {
+    pub min_y: i32,
+    pub sections: usize,
+}
+impl WorldHeight {
+    /// World from `min_y` up to but not including `max_y`, both multiples of 16.
+    pub fn new(min_y: i32, max_y: i32) -> Self {
+        assert!(
+            min_y < max_y
+                && min_y.rem_euclid(SECTION_HEIGHT as i32) == 0
+                && max_y.rem_euclid(SECTION_HEIGHT as i32) == 0,
+            "world height {}..{} has to be whole sections",
+            min_y,
+            max_y
+        );
+        WorldHeight {
+            min_y,
+            sections: (max_y - min_y) as usize / SECTION_HEIGHT,
+        }
+    }
+    pub fn max_y(&self) -> i32 {
+        self.min_y + (self.sections * SECTION_HEIGHT) as i32
+    }
+    pub fn contains(&self, y: i32) -> bool {
+        (self.min_y..self.max_y()).contains(&y)
+    }
+    pub fn section_index(&self, y: i32) -> usize {
+        (y - self.min_y) as usize / SECTION_HEIGHT
+    }
+    /// World y of the section's lowest layer.
+    pub fn section_min_y(&self, index: usize) -> i32 {
+        self.min_y + (index * SECTION_HEIGHT) as i32
+    }
+}
+impl Default for WorldHeight {
+    fn default() -> Self {
+        WorldHeight::new(-64, 320)
+    }
+}
+pub struct Chunk {
+    pub blocks: ChunkBlocks,
+    /// One per section, `None` for sections with nothing to draw.
+    pub meshes: Vec<Option<Mesh>>,
+    /// Whether blocks changed since the chunk was loaded or last saved.
+    pub modified: bool,
+}
+impl Chunk {
+    /// Chunk that isn't meshed yet.
+    pub fn new(blocks: ChunkBlocks) -> Self {
+        let sections = blocks.height().sections;
+        Chunk {
+            blocks,
+            meshes: (0..sections).map(|_| None).collect(),
+            modified: false,
+        }
+    }
+}
+/// A column of sections covering the whole world height.
+#[derive(Clone, Debug)]
+pub struct ChunkBlocks {
+    height: WorldHeight,
+    sections: Vec<Section>,
+    /// Biome of each column, indexed by `z * 16 + x`.
+    biomes: Vec<BiomeId>,
+}
+impl ChunkBlocks {
+    pub fn new(height: WorldHeight) -> Self {
+        ChunkBlocks {
+            height,
+            sections: vec![Section::new(); height.sections],
+            biomes: vec![BiomeId::default(); CHUNK_WIDTH * CHUNK_WIDTH],
+        }
+    }
+    pub fn height(&self) -> WorldHeight {
+        self.height
+    }
+    /// Block at the position, air above and below the world.
+    pub fn get(&self, pos: LocalBlockPos) -> Block {
+        if !self.height.contains(pos.y) {
+            return Block::AIR;
+        }
+        let section = &self.sections[self.height.section_index(pos.y)];
+        let y = (pos.y - self.height.min_y) as usize % SECTION_HEIGHT;
+        section.get(pos.x as usize, y, pos.z as usize)
+    }
+    /// Sets the block and returns the one that was there before.
+    pub fn set(&mut self, pos: LocalBlockPos, block: Block) -> Block {
+        assert!(
+            self.height.contains(pos.y),
+            "y {} is outside the world",
+            pos.y
+        );
+        let index = self.height.section_index(pos.y);
+        let y = (pos.y - self.height.min_y) as usize % SECTION_HEIGHT;
+        self.sections[index].set(pos.x as usize, y, pos.z as usize, block)
+    }
+    pub fn fill(&mut self, block: Block) {
+        for section in &mut self.sections {
+            section.fill(block);
+        }
+    }
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+    pub fn section(&self, index: usize) -> &Section {
+        &self.sections[index]
+    }
+    pub fn section_mut(&mut self, index: usize) -> &mut Section {
+        &mut self.sections[index]
+    }
+    pub fn biome(&self, x: usize, z: usize) -> BiomeId {
+        self.biomes[z * CHUNK_WIDTH + x]
+    }
+    pub fn set_biome(&mut self, x: usize, z: usize, biome: BiomeId) {
+        self.biomes[z * CHUNK_WIDTH + x] = biome;
+    }
+}
+/// 16x16x16 blocks in a flat palette-encoded array, indexed by local `x`, `y`, `z`
+/// with `x` varying fastest.
+#[derive(Clone, Debug)]
+pub struct Section {
+    data: PalettedContainer<Block>,
+    non_air: u16,
+}
+impl Section {
+    pub fn new() -> Self {
+        Section {
+            data: PalettedContainer::new(SECTION_VOLUME, Block::AIR),
+            non_air: 0,
+        }
+    }
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        debug_assert!(x < CHUNK_WIDTH && y < SECTION_HEIGHT && z < CHUNK_WIDTH);
+        (y * CHUNK_WIDTH + z) * CHUNK_WIDTH + x
+    }
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.data.get(Self::index(x, y, z))
+    }
+    /// Sets the block and returns the one that was there before.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) -> Block {
+        let old = self.data.set(Self::index(x, y, z), block);
+        match (old.is_air(), block.is_air()) {
+            (true, false) => self.non_air += 1,
+            (false, true) => self.non_air -= 1,
+            _ => {}
+        }
+        old
+    }
+    pub fn fill(&mut self, block: Block) {
+        self.data.fill(block);
+        self.non_air = if block.is_air() {
+            0
+        } else {
+            SECTION_VOLUME as u16
+        };
+    }
+    /// Whether every block is air.
+    pub fn is_empty(&self) -> bool {
+        self.non_air == 0
+    }
+    /// The block filling the whole section, if it is a single one.
+    pub fn uniform(&self) -> Option<Block> {
+        self.data.uniform()
+    }
+    pub fn blocks(&self) -> &PalettedContainer<Block> {
+        &self.data
+    }
+    pub fn from_blocks(data: PalettedContainer<Block>) -> Option<Self> {
+        if data.len != SECTION_VOLUME {
+            return None;
+        }
+        let non_air = (0..SECTION_VOLUME)
+            .filter(|i| !data.get(*i).is_air())
+            .count();
+        Some(Section {
+            data,
+            non_air: non_air as u16,
+        })
+    }
+}
+impl Default for Section {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// Fixed length array of values stored as indices into a palette of the distinct
+/// values, packed into as few bits as the palette needs. An array holding a single
+/// value (all air, solid stone) takes no space beyond the palette.
+#[derive(Clone, Debug)]
+pub struct PalettedContainer<T> {
+    palette: Vec<T>,
+    bits: u32,
+    data: Vec<u64>,
+    len: usize,
+}
+impl<T: Copy + PartialEq> PalettedContainer<T> {
+    pub fn new(len: usize, value: T) -> Self {
+        PalettedContainer {
+            palette: vec![value],
+            bits: 0,
+            data: vec![],
+            len,
+        }
+    }
+    /// Container from its packed form, `None` if the data doesn't fit `len` entries
+    /// of `bits` each or refers past the end of the palette.
+    pub fn from_parts(len: usize, palette: Vec<T>, bits: u32, data: Vec<u64>) -> Option<Self> {
+        if palette.is_empty() || bits > 32 || palette.len() > 1 << bits {
+            return None;
+        }
+        let expected = 64u32
+            .checked_div(bits)
+            .map_or(0, |per_long| len.div_ceil(per_long as usize));
+        if data.len() != expected {
+            return None;
+        }
+        let container = PalettedContainer {
+            palette,
+            bits,
+            data,
+            len,
+        };
+        if (0..len).any(|i| container.palette_index(i) >= container.palette.len()) {
+            return None;
+        }
+        Some(container)
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn palette(&self) -> &[T] {
+        &self.palette
+    }
+    pub fn bits_per_entry(&self) -> u32 {
+        self.bits
+    }
+    /// Palette indices packed `64 / bits_per_entry` to a long, lowest bits first.
+    pub fn packed(&self) -> &[u64] {
+        &self.data
+    }
+    pub fn get(&self, index: usize) -> T {
+        self.palette[self.palette_index(index)]
+    }
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        assert!(
+            index < self.len,
+            "index {} out of range {}",
+            index,
+            self.len
+        );
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(i) => i,
+            None => {
+                self.palette.push(value);
+                let needed = bits_for(self.palette.len());
+                if needed > self.bits {
+                    self.repack(needed);
+                }
+                self.palette.len() - 1
+            }
+        };
+        let old = self.get(index);
+        self.write(index, palette_index as u64);
+        old
+    }
+    pub fn fill(&mut self, value: T) {
+        self.palette.clear();
+        self.palette.push(value);
+        self.bits = 0;
+        self.data.clear();
+    }
+    /// The value of every entry if there is only one, without scanning the data
+    /// once the palette has grown.
+    pub fn uniform(&self) -> Option<T> {
+        if self.bits == 0 {
+            Some(self.palette[0])
+        } else {
+            None
+        }
+    }
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_long = (64 / self.bits) as usize;
+        let shift = (index % per_long) as u32 * self.bits;
+        ((self.data[index / per_long] >> shift) & mask(self.bits)) as usize
+    }
+    fn write(&mut self, index: usize, palette_index: u64) {
+        if self.bits == 0 {
+            return;
+        }
+        let per_long = (64 / self.bits) as usize;
+        let shift = (index % per_long) as u32 * self.bits;
+        let long = &mut self.data[index / per_long];
+        *long = (*long & !(mask(self.bits) << shift)) | (palette_index << shift);
+    }
+    fn repack(&mut self, bits: u32) {
+        let old: Vec<usize> = (0..self.len).map(|i| self.palette_index(i)).collect();
+        let per_long = (64 / bits) as usize;
+        self.bits = bits;
+        self.data = vec![0; self.len.div_ceil(per_long)];
+        for (i, palette_index) in old.into_iter().enumerate() {
+            self.write(i, palette_index as u64);
+        }
+    }
+}
+fn bits_for(palette_len: usize) -> u32 {
+    if palette_len <= 1 {
+        0
+    } else {
+        usize::BITS - (palette_len - 1).leading_zeros()
+    }
+}
+fn mask(bits: u32) -> u64 {
+    (1u64 << bits) - 1
+}
@@ -0,0 +1,137 @@
+use std::ops::{Add, AddAssign};
+
+use cgmath::{Point3, Vector3};
+
+use crate::chunk::CHUNK_WIDTH;
+
+pub const CHUNK_SIZE: i32 = CHUNK_WIDTH as i32;
+
+/// Position of a block in the world.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        BlockPos { x, y, z }
+    }
+    pub fn offset(&self, dx: i32, dy: i32, dz: i32) -> Self {
+        BlockPos {
+            x: self.x + dx,
+            y: self.y + dy,
+            z: self.z + dz,
+        }
+    }
+    /// Chunk the block is in, rounding towards negative infinity so block -1 is in
+    /// chunk -1 rather than chunk 0.
+    pub fn chunk(&self) -> ChunkPos {
+        ChunkPos {
+            x: self.x.div_euclid(CHUNK_SIZE),
+            z: self.z.div_euclid(CHUNK_SIZE),
+        }
+    }
+    /// Position inside the block's chunk.
+    pub fn local(&self) -> LocalBlockPos {
+        LocalBlockPos {
+            x: self.x.rem_euclid(CHUNK_SIZE) as u8,
+            y: self.y,
+            z: self.z.rem_euclid(CHUNK_SIZE) as u8,
+        }
+    }
+}
+/// Position of a chunk in chunk units, so `ChunkPos { x: 1, z: -1 }` covers
+/// world blocks x in 16..32 and z in -16..0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        ChunkPos { x, z }
+    }
+    pub fn offset(&self, dx: i32, dz: i32) -> Self {
+        ChunkPos {
+            x: self.x + dx,
+            z: self.z + dz,
+        }
+    }
+    /// World block coordinates of the chunk's (0, 0) column.
+    pub fn origin(&self) -> (i32, i32) {
+        (self.x * CHUNK_SIZE, self.z * CHUNK_SIZE)
+    }
+    /// World position of a block inside this chunk.
+    pub fn block(&self, local: LocalBlockPos) -> BlockPos {
+        let (x, z) = self.origin();
+        BlockPos {
+            x: x + local.x as i32,
+            y: local.y,
+            z: z + local.z as i32,
+        }
+    }
+}
+/// Position of a block inside its chunk. Chunks span the whole world height, so
+/// `y` is the world y while `x` and `z` are in 0..16.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LocalBlockPos {
+    pub x: u8,
+    pub y: i32,
+    pub z: u8,
+}
+impl LocalBlockPos {
+    pub fn new(x: usize, y: i32, z: usize) -> Self {
+        debug_assert!(x < CHUNK_WIDTH && z < CHUNK_WIDTH);
+        LocalBlockPos {
+            x: x as u8,
+            y,
+            z: z as u8,
+        }
+    }
+}
+/// Free position of an entity or the camera, in blocks. Block `(x, y, z)` covers
+/// `x..x + 1` and so on.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EntityPos {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+impl EntityPos {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        EntityPos { x, y, z }
+    }
+    /// Block the position is inside of.
+    pub fn block(&self) -> BlockPos {
+        BlockPos {
+            x: self.x.floor() as i32,
+            y: self.y.floor() as i32,
+            z: self.z.floor() as i32,
+        }
+    }
+    pub fn chunk(&self) -> ChunkPos {
+        self.block().chunk()
+    }
+}
+impl Add<Vector3<f32>> for EntityPos {
+    type Output = EntityPos;
+    fn add(self, v: Vector3<f32>) -> EntityPos {
+        EntityPos::new(self.x + v.x, self.y + v.y, self.z + v.z)
+    }
+}
+impl AddAssign<Vector3<f32>> for EntityPos {
+    fn add_assign(&mut self, v: Vector3<f32>) {
+        *self = *self + v;
+    }
+}
+impl From<EntityPos> for Point3<f32> {
+    fn from(pos: EntityPos) -> Self {
+        Point3::new(pos.x, pos.y, pos.z)
+    }
+}
+impl From<Point3<f32>> for EntityPos {
+    fn from(point: Point3<f32>) -> Self {
+        EntityPos::new(point.x, point.y, point.z)
+    }
+}
@@ -0,0 +1,404 @@
+//! Trees, boulders and flowers placed on generated terrain, by the features in
+//! `features.ron` and the biome lists of them in `worldgen.ron`.
+//!
+//! A feature stands on the ground of the chunk it starts in, found in that chunk's
+//! generated blocks, but its blocks can reach into the chunks around it. Those are
+//! handed to `World::place_features`, which keeps the ones for chunks that don't
+//! exist yet until they do.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    biome::{BiomeId, Biomes},
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, CHUNK_WIDTH},
+    coords::{BlockPos, ChunkPos, LocalBlockPos},
+    worldgen::ChunkRng,
+};
+
+/// Salt of the first feature's `ChunkRng`, each feature after it adds one.
+const FEATURE_SALT: u64 = 64;
+
+/// One entry of `features.ron`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureDef {
+    /// What biomes call it.
+    pub name: String,
+    /// Blocks it can stand on.
+    pub on: Vec<String>,
+    pub shape: ShapeDef,
+}
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum ShapeDef {
+    /// A trunk `trunk` blocks high with a round crown of leaves at the top.
+    Tree {
+        log: String,
+        leaves: String,
+        trunk: (u32, u32),
+        radius: u32,
+    },
+    /// A trunk with rings of leaves narrowing towards the top, like a spruce.
+    Cone {
+        log: String,
+        leaves: String,
+        trunk: (u32, u32),
+        radius: u32,
+    },
+    /// A stack of one block, like a cactus.
+    Column { block: String, height: (u32, u32) },
+    /// A ball half sunk into the ground.
+    Boulder { block: String, radius: (f64, f64) },
+    /// `count` of the blocks scattered on the ground up to `spread` blocks away, but
+    /// only inside the chunk.
+    Patch {
+        blocks: Vec<String>,
+        count: u32,
+        spread: u32,
+    },
+}
+/// A shape with its blocks looked up.
+enum Shape {
+    Tree {
+        log: Block,
+        leaves: Block,
+        trunk: (u32, u32),
+        radius: i32,
+    },
+    Cone {
+        log: Block,
+        leaves: Block,
+        trunk: (u32, u32),
+        radius: i32,
+    },
+    Column {
+        block: Block,
+        height: (u32, u32),
+    },
+    Boulder {
+        block: Block,
+        radius: (f64, f64),
+    },
+    Patch {
+        blocks: Vec<Block>,
+        count: u32,
+        spread: i32,
+    },
+}
+struct Feature {
+    on: Vec<Block>,
+    shape: Shape,
+}
+pub struct Decorator {
+    seed: u64,
+    defs: Vec<FeatureDef>,
+    features: Vec<Feature>,
+    /// Index into `features` and tries per chunk, for every biome.
+    biomes: HashMap<BiomeId, Vec<(usize, f64)>>,
+}
+impl Decorator {
+    /// Features the game ships with.
+    pub fn default_features() -> anyhow::Result<Vec<FeatureDef>> {
+        Self::features_from_ron(include_str!("features.ron")).context("failed to load features.ron")
+    }
+    pub fn load_features(path: &Path) -> anyhow::Result<Vec<FeatureDef>> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::features_from_ron(&source)
+            .with_context(|| format!("failed to load {}", path.display()))
+    }
+    pub fn features_from_ron(source: &str) -> anyhow::Result<Vec<FeatureDef>> {
+        Ok(ron::from_str(source)?)
+    }
+    /// Checks `defs` and the feature lists of `biomes` against each other and the
+    /// registry.
+    pub fn new(
+        seed: u64,
+        defs: Vec<FeatureDef>,
+        biomes: &Biomes,
+        registry: &BlockRegistry,
+    ) -> anyhow::Result<Self> {
+        let block = |name: &str| -> anyhow::Result<Block> {
+            let block_type = registry
+                .by_name(name)
+                .with_context(|| format!("unknown block {}", name))?;
+            Ok(registry.default_state(block_type))
+        };
+        let range = |name: &str, (min, max): (u32, u32)| -> anyhow::Result<(u32, u32)> {
+            if !(1..=32).contains(&min) || min > max || max > 32 {
+                bail!("{} has to be from 1 to 32, smallest first", name);
+            }
+            Ok((min, max))
+        };
+        let radius = |radius: u32| -> anyhow::Result<i32> {
+            if radius > 8 {
+                bail!("radius can't be above 8");
+            }
+            Ok(radius as i32)
+        };
+        let mut features = vec![];
+        for (i, def) in defs.iter().enumerate() {
+            let resolve = || -> anyhow::Result<Feature> {
+                if defs[..i].iter().any(|other| other.name == def.name) {
+                    bail!("listed twice");
+                }
+                if def.on.is_empty() {
+                    bail!("needs at least one block to stand on");
+                }
+                let shape = match &def.shape {
+                    ShapeDef::Tree {
+                        log,
+                        leaves,
+                        trunk,
+                        radius: r,
+                    } => Shape::Tree {
+                        log: block(log)?,
+                        leaves: block(leaves)?,
+                        trunk: range("trunk", *trunk)?,
+                        radius: radius(*r)?,
+                    },
+                    ShapeDef::Cone {
+                        log,
+                        leaves,
+                        trunk,
+                        radius: r,
+                    } => Shape::Cone {
+                        log: block(log)?,
+                        leaves: block(leaves)?,
+                        trunk: range("trunk", *trunk)?,
+                        radius: radius(*r)?,
+                    },
+                    ShapeDef::Column {
+                        block: name,
+                        height,
+                    } => Shape::Column {
+                        block: block(name)?,
+                        height: range("height", *height)?,
+                    },
+                    ShapeDef::Boulder {
+                        block: name,
+                        radius: (min, max),
+                    } => {
+                        if !(*min > 0.0 && min <= max && *max <= 8.0) {
+                            bail!("boulder radius has to be above 0 and at most 8, smallest first");
+                        }
+                        Shape::Boulder {
+                            block: block(name)?,
+                            radius: (*min, *max),
+                        }
+                    }
+                    ShapeDef::Patch {
+                        blocks,
+                        count,
+                        spread,
+                    } => {
+                        if blocks.is_empty() {
+                            bail!("a patch needs at least one block");
+                        }
+                        if *count > 64 || *spread > 8 {
+                            bail!("a patch can't have more than 64 blocks or spread above 8");
+                        }
+                        Shape::Patch {
+                            blocks: blocks
+                                .iter()
+                                .map(|name| block(name))
+                                .collect::<anyhow::Result<_>>()?,
+                            count: *count,
+                            spread: *spread as i32,
+                        }
+                    }
+                };
+                Ok(Feature {
+                    on: def
+                        .on
+                        .iter()
+                        .map(|name| block(name))
+                        .collect::<anyhow::Result<_>>()?,
+                    shape,
+                })
+            };
+            features.push(resolve().with_context(|| format!("in feature {}", def.name))?);
+        }
+        let mut lists = HashMap::new();
+        for biome in biomes.iter() {
+            let mut list = vec![];
+            for (name, per_chunk) in &biome.features {
+                let index = defs
+                    .iter()
+                    .position(|def| def.name == *name)
+                    .with_context(|| {
+                        format!("biome {} has unknown feature {}", biome.name, name)
+                    })?;
+                list.push((index, *per_chunk));
+            }
+            lists.insert(BiomeId(biome.id), list);
+        }
+        Ok(Decorator {
+            seed,
+            defs,
+            features,
+            biomes: lists,
+        })
+    }
+    pub fn features(&self) -> &[FeatureDef] {
+        &self.defs
+    }
+    /// Blocks of the features starting in the chunk, picked by the biome at its
+    /// center. `blocks` is the chunk as generated, the blocks returned can be in
+    /// the chunks around it too.
+    pub fn decorate(&self, pos: ChunkPos, blocks: &ChunkBlocks) -> Vec<(BlockPos, Block)> {
+        let mut placed = vec![];
+        let Some(list) = self.biomes.get(&blocks.biome(8, 8)) else {
+            return placed;
+        };
+        for (index, per_chunk) in list {
+            let feature = &self.features[*index];
+            let mut rng = ChunkRng::new(self.seed, FEATURE_SALT + *index as u64, pos);
+            let mut count = per_chunk.floor() as u32;
+            if rng.next_f64() < per_chunk.fract() {
+                count += 1;
+            }
+            for _ in 0..count {
+                let (x, z) = (
+                    rng.range(0, CHUNK_WIDTH as i32 - 1),
+                    rng.range(0, CHUNK_WIDTH as i32 - 1),
+                );
+                //drawn before the ground is checked so one failed try doesn't shift the
+                //ones after it
+                let mut feature_rng = ChunkRng::new(rng.next_u64(), 0, pos);
+                let Some(ground) = ground(blocks, x, z, &feature.on) else {
+                    continue;
+                };
+                let root = pos.block(LocalBlockPos::new(x as usize, ground + 1, z as usize));
+                feature.grow(root, pos, blocks, &mut feature_rng, &mut placed);
+            }
+        }
+        placed
+    }
+}
+impl Feature {
+    /// Adds the blocks of the feature standing at `root`, the first block above the
+    /// ground.
+    fn grow(
+        &self,
+        root: BlockPos,
+        pos: ChunkPos,
+        blocks: &ChunkBlocks,
+        rng: &mut ChunkRng,
+        placed: &mut Vec<(BlockPos, Block)>,
+    ) {
+        match &self.shape {
+            Shape::Tree {
+                log,
+                leaves,
+                trunk,
+                radius,
+            } => {
+                let height = rng.range(trunk.0 as i32, trunk.1 as i32);
+                for y in 0..height {
+                    placed.push((root.offset(0, y, 0), *log));
+                }
+                //two wide layers around the top of the trunk and a narrower one over it
+                for dy in -2..=1 {
+                    let r = if dy > 0 { *radius - 1 } else { *radius };
+                    for dz in -r..=r {
+                        for dx in -r..=r {
+                            let corner = dx.abs() == r && dz.abs() == r;
+                            if corner && (dy > 0 || rng.next_u64() & 1 == 0) {
+                                continue;
+                            }
+                            placed.push((root.offset(dx, height - 1 + dy, dz), *leaves));
+                        }
+                    }
+                }
+            }
+            Shape::Cone {
+                log,
+                leaves,
+                trunk,
+                radius,
+            } => {
+                let height = rng.range(trunk.0 as i32, trunk.1 as i32);
+                for y in 0..height {
+                    placed.push((root.offset(0, y, 0), *log));
+                }
+                //rings from a few blocks up the trunk to one block over it, every other
+                //ring pulled in
+                let bottom = (height / 3).max(1);
+                for y in bottom..=height {
+                    let from_top = (height - y) as f64 / (height - bottom).max(1) as f64;
+                    let mut r = (from_top * *radius as f64).round() as i32;
+                    if (height - y) % 2 == 1 {
+                        r = (r - 1).max(1);
+                    }
+                    if y == height {
+                        r = 0;
+                    }
+                    for dz in -r..=r {
+                        for dx in -r..=r {
+                            if dx * dx + dz * dz <= r * r + 1 {
+                                placed.push((root.offset(dx, y, dz), *leaves));
+                            }
+                        }
+                    }
+                }
+            }
+            Shape::Column { block, height } => {
+                for y in 0..rng.range(height.0 as i32, height.1 as i32) {
+                    placed.push((root.offset(0, y, 0), *block));
+                }
+            }
+            Shape::Boulder { block, radius } => {
+                let r = radius.0 + (radius.1 - radius.0) * rng.next_f64();
+                let reach = r.ceil() as i32;
+                for dy in -reach..=reach {
+                    for dz in -reach..=reach {
+                        for dx in -reach..=reach {
+                            //centered on the top of the ground block
+                            let (x, y, z) = (dx as f64, dy as f64 + 0.5, dz as f64);
+                            if x * x + y * y + z * z <= r * r {
+                                placed.push((root.offset(dx, dy, dz), *block));
+                            }
+                        }
+                    }
+                }
+            }
+            Shape::Patch {
+                blocks: flowers,
+                count,
+                spread,
+            } => {
+                let (origin_x, origin_z) = pos.origin();
+                for _ in 0..*count {
+                    let x = root.x - origin_x + rng.range(-*spread, *spread);
+                    let z = root.z - origin_z + rng.range(-*spread, *spread);
+                    let flower = flowers[rng.next_u64() as usize % flowers.len()];
+                    let inside = (0..CHUNK_WIDTH as i32).contains(&x)
+                        && (0..CHUNK_WIDTH as i32).contains(&z);
+                    if !inside {
+                        continue;
+                    }
+                    if let Some(ground) = ground(blocks, x, z, &self.on) {
+                        let local = LocalBlockPos::new(x as usize, ground + 1, z as usize);
+                        placed.push((pos.block(local), flower));
+                    }
+                }
+            }
+        }
+    }
+}
+/// Y of the highest block of the column if it is one of `on` and there is room
+/// above it.
+fn ground(blocks: &ChunkBlocks, x: i32, z: i32, on: &[Block]) -> Option<i32> {
+    let height = blocks.height();
+    let (x, z) = (x as usize, z as usize);
+    let y = (height.min_y..height.max_y() - 1)
+        .rev()
+        .find(|y| !blocks.get(LocalBlockPos::new(x, *y, z)).is_air())?;
+    on.contains(&blocks.get(LocalBlockPos::new(x, y, z)))
+        .then_some(y)
+}
@@ -12,7 +12,16 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-use crate::{camera, player::Player, texture, Block, BlockType, Chunk};
+use crate::{
+    block::RenderLayer,
+    camera,
+    lighting::{Lighting, FACES},
+    loader::ChunkLoader,
+    mesh::{ChunkMeshData, TranslucentQuads, Vertex},
+    player::Player,
+    texture,
+    world::World,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -34,55 +43,149 @@ impl CameraUniform {
         self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).into();
     }
 }
+/// Brightness of every face, as `Lighting::shades`, four to a `vec4` since arrays
+/// in uniforms are laid out 16 bytes apart.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    shades: [[f32; 4]; FACES / 4],
+}
+impl LightingUniform {
+    fn new(lighting: &Lighting) -> Self {
+        let shades = lighting.shades();
+        Self {
+            shades: [0, 4].map(|start| [0, 1, 2, 3].map(|i| shades[start + i])),
+        }
+    }
+}
 pub struct Mesh {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
-    num_elements: u32,
-}
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 3],
-    pub tex_coords: [f32; 2],
+    /// The section's origin, an instance attribute drawn once.
+    origin_buffer: Buffer,
+    origin: [i32; 3],
+    /// Where each `RenderLayer`'s indices are in the index buffer.
+    layers: [std::ops::Range<u32>; 3],
+    /// Sorted into the translucent layer's indices as the camera moves.
+    translucent: TranslucentQuads,
 }
-
 impl Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Uint32,
+            }],
         }
     }
 }
+/// Layout of a mesh's origin, the same for all its vertices.
+fn origin_desc() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x3,
+        }],
+    }
+}
+/// Pipeline drawing the faces of one layer: translucent ones blended over what is
+/// behind them, without hiding what is drawn after them.
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    layer: RenderLayer,
+) -> wgpu::RenderPipeline {
+    let (label, entry_point, blend) = match layer {
+        RenderLayer::Opaque => ("Opaque Pipeline", "fs_opaque", wgpu::BlendState::REPLACE),
+        RenderLayer::Cutout => ("Cutout Pipeline", "fs_cutout", wgpu::BlendState::REPLACE),
+        RenderLayer::Translucent => (
+            "Translucent Pipeline",
+            "fs_translucent",
+            wgpu::BlendState::ALPHA_BLENDING,
+        ),
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), origin_desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+            // or Features::POLYGON_MODE_POINT
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: layer != RenderLayer::Translucent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        // If the pipeline will be used with a multiview render pass, this
+        // indicates how many array layers the attachments will have.
+        multiview: None,
+    })
+}
 pub struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
+    /// One for each `RenderLayer`.
+    render_pipelines: [wgpu::RenderPipeline; 3],
     camera: camera::Camera,
     projection: camera::Projection,
     pub player: Player,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    lighting_buffer: wgpu::Buffer,
+    lighting_bind_group: wgpu::BindGroup,
     depth_texture: texture::Texture,
     window: Window,
     texture_bind_group: wgpu::BindGroup,
+    /// Most dirty sections sent to be remeshed per frame, the rest wait for the next
+    /// frames.
+    pub remesh_budget: usize,
+    /// Most finished meshes uploaded per frame.
+    pub upload_budget: usize,
+    /// Block the camera was in when the translucent quads were last sorted.
+    sorted_from: Option<[i32; 3]>,
 }
 
 impl State {
@@ -104,7 +207,7 @@ impl State {
             .map_or(PhysicalSize::new(800, 600), |vm| vm.size());
         let window = WindowBuilder::new()
             .with_title("WGPUCraft")
-            .with_fullscreen(video_mode.map(|vm| Fullscreen::Exclusive(vm)))
+            .with_fullscreen(video_mode.map(Fullscreen::Exclusive))
             .build(&event_loop)
             .unwrap();
 
@@ -316,6 +419,36 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
+        let lighting_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting Buffer"),
+            contents: bytemuck::cast_slice(&[LightingUniform::new(&Lighting::default())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lighting_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("lighting_bind_group_layout"),
+            });
+
+        let lighting_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &lighting_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: lighting_buffer.as_entire_binding(),
+            }],
+            label: Some("lighting_bind_group"),
+        });
+
         log::warn!("Load model");
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader.wgsl"),
@@ -328,58 +461,22 @@ impl State {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &lighting_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
+        let render_pipelines = RenderLayer::ALL.map(|layer| {
+            create_render_pipeline(
+                &device,
+                &render_pipeline_layout,
+                &shader,
+                config.format,
+                layer,
+            )
         });
 
         (
@@ -389,20 +486,33 @@ impl State {
                 queue,
                 config,
                 size,
-                render_pipeline,
+                render_pipelines,
                 camera,
                 projection,
                 player,
                 camera_buffer,
                 camera_bind_group,
                 camera_uniform,
+                lighting_buffer,
+                lighting_bind_group,
                 depth_texture,
                 window,
                 texture_bind_group: diffuse_bind_group,
+                remesh_budget: 64,
+                upload_budget: 16,
+                sorted_from: None,
             },
             event_loop,
         )
     }
+    /// Lights the terrain with `lighting` from the next frame on.
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.queue.write_buffer(
+            &self.lighting_buffer,
+            0,
+            bytemuck::cast_slice(&[LightingUniform::new(&lighting)]),
+        );
+    }
     pub fn window(&self) -> &Window {
         &self.window
     }
@@ -432,8 +542,11 @@ impl State {
             _ => false,
         }
     }
-    pub fn update(&mut self, dt: std::time::Duration, chunks: &mut [Chunk; 256]) {
-        self.player.update_player(&mut self.camera, dt, chunks);
+    pub fn update(&mut self, dt: std::time::Duration, world: &mut World, loader: &mut ChunkLoader) {
+        self.player.update_player(&mut self.camera, dt, world);
+        loader.update(world, self.player.position().chunk(), self.remesh_budget);
+        self.upload_meshes(world, loader);
+        self.sort_translucent(world);
         self.camera_uniform
             .update_view_proj(&self.camera, &self.projection);
         self.queue.write_buffer(
@@ -442,7 +555,48 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
     }
-    pub fn render(&mut self, chunks: &[Chunk; 256]) -> Result<(), wgpu::SurfaceError> {
+    /// Uploads up to `upload_budget` of the meshes the loader's workers built.
+    fn upload_meshes(&mut self, world: &mut World, loader: &mut ChunkLoader) {
+        for (pos, index, data) in loader.take_meshes(self.upload_budget) {
+            let mesh = data.map(|data| self.build_mesh(&data));
+            if let Some(chunk) = world.chunk_mut(pos) {
+                chunk.meshes[index] = mesh;
+            }
+        }
+    }
+    /// Sorts the translucent quads of every mesh back to front again once the camera
+    /// moved to another block.
+    fn sort_translucent(&mut self, world: &World) {
+        let eye = self.camera.position;
+        let block = [eye.x, eye.y, eye.z].map(|axis| axis.floor() as i32);
+        if self.sorted_from == Some(block) {
+            return;
+        }
+        self.sorted_from = Some(block);
+        for mesh in world
+            .chunks()
+            .flat_map(|(_, chunk)| chunk.meshes.iter().flatten())
+        {
+            self.write_translucent(mesh);
+        }
+    }
+    /// Writes the mesh's translucent indices, the quads furthest from the camera
+    /// first.
+    fn write_translucent(&self, mesh: &Mesh) {
+        if mesh.translucent.is_empty() {
+            return;
+        }
+        let eye = self.camera.position;
+        let origin = mesh.origin.map(|axis| axis as f32);
+        let eye = [eye.x - origin[0], eye.y - origin[1], eye.z - origin[2]];
+        let start = mesh.layers[RenderLayer::Translucent as usize].start;
+        self.queue.write_buffer(
+            &mesh.index_buffer,
+            start as wgpu::BufferAddress * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            bytemuck::cast_slice(&mesh.translucent.sorted(eye)),
+        );
+    }
+    pub fn render(&mut self, world: &World) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
@@ -479,14 +633,33 @@ impl State {
                     stencil_ops: None,
                 }),
             });
-            render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            for chunk in chunks {
-                render_pass.set_vertex_buffer(0, chunk.mesh.vertex_buffer.slice(..));
-                render_pass
-                    .set_index_buffer(chunk.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                render_pass.draw_indexed(0..chunk.mesh.num_elements, 0, 0..1);
+            render_pass.set_bind_group(2, &self.lighting_bind_group, &[]);
+            let mut meshes: Vec<&Mesh> = world
+                .chunks()
+                .flat_map(|(_, chunk)| chunk.meshes.iter().flatten())
+                .collect();
+            //translucent sections blend over the ones behind them, so those go first
+            let eye = self.camera.position;
+            let distance = |mesh: &Mesh| {
+                let [x, y, z] = mesh.origin.map(|axis| axis as f32 + 8.0);
+                (x - eye.x).powi(2) + (y - eye.y).powi(2) + (z - eye.z).powi(2)
+            };
+            meshes.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+            for layer in RenderLayer::ALL {
+                render_pass.set_pipeline(&self.render_pipelines[layer as usize]);
+                for mesh in &meshes {
+                    let indices = mesh.layers[layer as usize].clone();
+                    if indices.is_empty() {
+                        continue;
+                    }
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_vertex_buffer(1, mesh.origin_buffer.slice(..));
+                    render_pass
+                        .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(indices, 0, 0..1);
+                }
             }
         }
 
@@ -495,331 +668,41 @@ impl State {
 
         Ok(())
     }
-    pub fn build_mesh(&self, vertices: Vec<Vertex>, indices: Vec<u32>) -> Mesh {
+    /// Uploads the mesh data to the GPU.
+    pub fn build_mesh(&self, data: &ChunkMeshData) -> Mesh {
         let vertex_buffer = self
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
+                contents: bytemuck::cast_slice(&data.vertices),
                 usage: wgpu::BufferUsages::VERTEX,
             });
         let index_buffer = self
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(&indices),
-                usage: wgpu::BufferUsages::INDEX,
+                contents: bytemuck::cast_slice(&data.indices),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
             });
-        Mesh {
+        let origin_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Origin Buffer"),
+                contents: bytemuck::cast_slice(&data.origin.map(|axis| axis as f32)),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let mesh = Mesh {
             vertex_buffer,
             index_buffer,
-            num_elements: indices.len() as u32,
-        }
-    }
-    pub fn build_chunk(
-        &self,
-        blocks: &Vec<Vec<Vec<Block>>>,
-        x_offset: f32,
-        z_offset: f32,
-        left_chunk: Option<&Vec<Vec<Vec<Block>>>>,
-        right_chunk: Option<&Vec<Vec<Vec<Block>>>>,
-        front_chunk: Option<&Vec<Vec<Vec<Block>>>>,
-        back_chunk: Option<&Vec<Vec<Vec<Block>>>>,
-    ) -> Mesh {
-        let mut vertices: Vec<Vertex> = vec![];
-        let mut indices: Vec<u32> = vec![];
-
-        //vars in for loop code, preinitialized
-        let mut grass_above;
-        let mut neighbor_chunk_block_option;
-        let mut base_index;
-        let mut face;
-        let mut neighbor;
-        for (x, column) in blocks.iter().enumerate() {
-            for (y, row) in column.iter().enumerate() {
-                for (z, block) in row.iter().enumerate() {
-                    //init code
-                    if let BlockType::Air = block.block_type {
-                        continue;
-                    }
-                    let pos = [x as f32 + x_offset, y as f32, z as f32 + z_offset];
-                    grass_above = y + 1 < column.len()
-                        && matches!(blocks[x][y + 1][z].block_type, BlockType::Grass);
-
-                    //block rendering
-                    base_index = vertices.len() as u32;
-                    face = Face::Top;
-                    neighbor = if y + 1 < column.len() {
-                        Some(&blocks[x][y + 1][z])
-                    } else {
-                        None
-                    };
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        false,
-                        None,
-                    );
-
-                    base_index = vertices.len() as u32;
-                    face = Face::Bottom;
-                    neighbor = if y > 0 {
-                        Some(&blocks[x][y - 1][z])
-                    } else {
-                        None
-                    };
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        false,
-                        None,
-                    );
-
-                    base_index = vertices.len() as u32;
-                    face = Face::Left; //this is actually front i think
-                    neighbor = if x > 0 {
-                        Some(&blocks[x - 1][y][z])
-                    } else {
-                        None
-                    };
-                    neighbor_chunk_block_option =
-                        left_chunk.map_or(None, |chunk| Some(&chunk[15][y][z]));
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        grass_above,
-                        neighbor_chunk_block_option,
-                    );
-
-                    base_index = vertices.len() as u32;
-                    face = Face::Right;
-                    neighbor = if x + 1 < blocks.len() {
-                        Some(&blocks[x + 1][y][z])
-                    } else {
-                        None
-                    };
-                    neighbor_chunk_block_option =
-                        right_chunk.map_or(None, |chunk| Some(&chunk[0][y][z]));
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        grass_above,
-                        neighbor_chunk_block_option,
-                    );
-
-                    base_index = vertices.len() as u32;
-                    face = Face::Front;
-                    neighbor = if z + 1 < row.len() {
-                        Some(&blocks[x][y][z + 1])
-                    } else {
-                        None
-                    };
-                    neighbor_chunk_block_option =
-                        front_chunk.map_or(None, |chunk| Some(&chunk[x][y][0]));
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        grass_above,
-                        neighbor_chunk_block_option,
-                    );
-
-                    base_index = vertices.len() as u32;
-                    face = Face::Back;
-                    neighbor = if z > 0 {
-                        Some(&blocks[x][y][z - 1])
-                    } else {
-                        None
-                    };
-                    neighbor_chunk_block_option =
-                        back_chunk.map_or(None, |chunk| Some(&chunk[x][y][15]));
-                    get_block_face(
-                        base_index,
-                        face,
-                        neighbor,
-                        block,
-                        pos,
-                        &mut vertices,
-                        &mut indices,
-                        grass_above,
-                        neighbor_chunk_block_option,
-                    );
-                }
-            }
-        }
-        self.build_mesh(vertices, indices)
-        //better technique, start in the middle and work your way out?
-    }
-}
-fn get_block_face(
-    base_index: u32,
-    face: Face,
-    neighbor_block_option: Option<&Block>,
-    block: &Block,
-    pos: [f32; 3],
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u32>,
-    grass_above: bool,
-    neighbor_chunk_block_option: Option<&Block>,
-) {
-    let mut render = false;
-    match neighbor_block_option {
-        Some(neighbor_block) => {
-            if let BlockType::Air = neighbor_block.block_type {
-                vertices.extend_from_slice(&get_mesh_texture_and_pos(
-                    face,
-                    &block.block_type,
-                    pos,
-                    grass_above,
-                ));
-                render = true;
-            }
-            //otherwise the neighboring block is a solid block so you don't need to render
-        }
-        None => {
-            match neighbor_chunk_block_option {
-                Some(neighbor_chunk_block) => {
-                    if let BlockType::Air = neighbor_chunk_block.block_type {
-                        vertices.extend_from_slice(&get_mesh_texture_and_pos(
-                            face,
-                            &block.block_type,
-                            pos,
-                            grass_above,
-                        ));
-                        render = true;
-                    }
-                    //otherwise the neighboring chunk's block is a solid block so you don't need to render
-                }
-                None => {}
-            }
-        }
-    }
-    if render {
-        indices.push(base_index + 3);
-        indices.push(base_index + 2);
-        indices.push(base_index);
-        indices.push(base_index + 1);
-        indices.push(base_index + 2);
-        indices.push(base_index + 3);
-    }
-}
-fn get_mesh_texture_and_pos(
-    face: Face,
-    block_type: &BlockType,
-    pos: [f32; 3],
-    grass_above: bool,
-) -> Vec<Vertex> {
-    let vertices = match face {
-        Face::Top => [
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] - 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] + 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] - 0.5],
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] + 0.5],
-        ],
-        Face::Bottom => [
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] + 0.5],
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] + 0.5],
-        ],
-        Face::Left => [
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] + 0.5],
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] - 0.5],
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] + 0.5],
-        ],
-        Face::Right => [
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] + 0.5],
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] + 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] - 0.5],
-        ],
-        Face::Front => [
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] + 0.5],
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] + 0.5],
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] + 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] + 0.5],
-        ],
-        Face::Back => [
-            [pos[0] - 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] + 0.5, pos[1] + 0.5, pos[2] - 0.5],
-            [pos[0] + 0.5, pos[1] - 0.5, pos[2] - 0.5],
-            [pos[0] - 0.5, pos[1] + 0.5, pos[2] - 0.5],
-        ],
-    };
-    let index = match block_type {
-        BlockType::Grass => match face {
-            Face::Left | Face::Right | Face::Back | Face::Front => {
-                if grass_above {
-                    1
-                } else {
-                    2
-                }
-            }
-            Face::Top => 3,
-            Face::Bottom => 1,
-        },
-        _ => todo!(),
-    };
-
-    let texture_coords = get_texture_coords(index);
-    let mut vertices_array = vec![];
-    for i in 0..4 {
-        vertices_array.push(Vertex {
-            position: vertices[i],
-            tex_coords: texture_coords[i],
-        })
+            origin_buffer,
+            origin: data.origin,
+            layers: RenderLayer::ALL.map(|layer| {
+                let range = data.layer_range(layer);
+                range.start as u32..range.end as u32
+            }),
+            translucent: TranslucentQuads::new(data),
+        };
+        self.write_translucent(&mesh);
+        mesh
     }
-
-    vertices_array
-}
-fn get_texture_coords(index: usize) -> [[f32; 2]; 4] {
-    const NUM_SPRITES_IN_TEXTURE: usize = 16; //must be perfect square
-    const SPRITE_SIZE: f32 = 1.0 / (NUM_SPRITES_IN_TEXTURE as f32);
-
-    let row = index / NUM_SPRITES_IN_TEXTURE;
-    let col = index % NUM_SPRITES_IN_TEXTURE;
-
-    let min_x = col as f32 * SPRITE_SIZE;
-    let max_x = min_x + SPRITE_SIZE;
-    let min_y = row as f32 * SPRITE_SIZE;
-    let max_y = min_y + SPRITE_SIZE;
-    [
-        [min_x, min_y],
-        [max_x, max_y],
-        [min_x, max_y],
-        [max_x, min_y],
-    ]
-}
-enum Face {
-    Top,
-    Bottom,
-    Left,
-    Right,
-    Back,
-    Front,
 }
@@ -0,0 +1,192 @@
+//! A pool of worker threads for chunk work like generating and meshing, taking the
+//! jobs nearest to a focus chunk, usually the player's, first.
+//!
+//! On the web there are no threads, so a pool without workers runs its jobs on the
+//! calling thread instead, a few each time it is polled.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::coords::ChunkPos;
+
+/// Jobs a pool without workers runs per `poll`.
+const INLINE_JOBS: usize = 4;
+
+/// A job that panicked instead of returning a result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Panicked {
+    /// The chunk the job was about.
+    pub pos: ChunkPos,
+    pub message: String,
+}
+struct Queue<J> {
+    jobs: Vec<(ChunkPos, J)>,
+    focus: ChunkPos,
+    closed: bool,
+}
+impl<J> Queue<J> {
+    /// Removes the job nearest to the focus.
+    fn pop(&mut self) -> Option<(ChunkPos, J)> {
+        let focus = self.focus;
+        let distance = |pos: &ChunkPos| (pos.x - focus.x).pow(2) + (pos.z - focus.z).pow(2);
+        let (nearest, _) = self
+            .jobs
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (pos, _))| distance(pos))?;
+        Some(self.jobs.swap_remove(nearest))
+    }
+}
+struct Shared<J> {
+    queue: Mutex<Queue<J>>,
+    /// Signaled when a job is pushed or the pool closes.
+    changed: Condvar,
+}
+pub struct JobPool<J, R> {
+    shared: Arc<Shared<J>>,
+    work: Arc<dyn Fn(ChunkPos, J) -> R + Send + Sync>,
+    sender: Sender<Result<R, Panicked>>,
+    results: Receiver<Result<R, Panicked>>,
+    workers: Vec<JoinHandle<()>>,
+}
+impl<J: Send + 'static, R: Send + 'static> JobPool<J, R> {
+    /// Pool of `threads` workers running `work` on every job and the chunk it is
+    /// about, 0 running them in `poll` instead.
+    pub fn new(threads: usize, work: impl Fn(ChunkPos, J) -> R + Send + Sync + 'static) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: vec![],
+                focus: ChunkPos::default(),
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let work: Arc<dyn Fn(ChunkPos, J) -> R + Send + Sync> = Arc::new(work);
+        let (sender, results) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|i| {
+                let (shared, work, sender) = (shared.clone(), work.clone(), sender.clone());
+                thread::Builder::new()
+                    .name(format!("chunk worker {}", i))
+                    .spawn(move || run_worker(&shared, &*work, &sender))
+                    .expect("failed to start a chunk worker")
+            })
+            .collect();
+        JobPool {
+            shared,
+            work,
+            sender,
+            results,
+            workers,
+        }
+    }
+    /// Pool with a worker for every core but the one the game runs on, or none on
+    /// the web.
+    pub fn with_available_threads(work: impl Fn(ChunkPos, J) -> R + Send + Sync + 'static) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        let threads = 0;
+        #[cfg(not(target_arch = "wasm32"))]
+        let threads = thread::available_parallelism().map_or(1, |n| n.get().max(2) - 1);
+        Self::new(threads, work)
+    }
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+    /// Queues a job about the chunk at `pos`.
+    pub fn push(&self, pos: ChunkPos, job: J) {
+        self.lock().jobs.push((pos, job));
+        self.shared.changed.notify_one();
+    }
+    /// Makes jobs nearest to `focus` run first, from the next one taken on.
+    pub fn set_focus(&self, focus: ChunkPos) {
+        self.lock().focus = focus;
+    }
+    /// Drops queued jobs `keep` turns down, jobs already running still finish.
+    pub fn retain(&self, mut keep: impl FnMut(ChunkPos, &J) -> bool) {
+        self.lock().jobs.retain(|(pos, job)| keep(*pos, job));
+    }
+    /// Jobs not taken by a worker yet.
+    pub fn queued(&self) -> usize {
+        self.lock().jobs.len()
+    }
+    /// Results of the jobs finished since the last call, in the order they finished,
+    /// the ones that panicked as errors.
+    pub fn poll(&self) -> Vec<Result<R, Panicked>> {
+        if self.workers.is_empty() {
+            for _ in 0..INLINE_JOBS {
+                let Some((pos, job)) = self.lock().pop() else {
+                    break;
+                };
+                let _ = self.sender.send(run(&*self.work, pos, job));
+            }
+        }
+        self.results.try_iter().collect()
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue<J>> {
+        //jobs run without the lock, so a panicking one can't poison it
+        self.shared.queue.lock().unwrap()
+    }
+}
+impl<J, R> Drop for JobPool<J, R> {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.shared.queue.lock() {
+            queue.closed = true;
+        }
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+/// Runs one job, catching a panic so the worker keeps going and whoever waits for
+/// the job hears about it.
+fn run<J, R>(
+    work: &(dyn Fn(ChunkPos, J) -> R + Send + Sync),
+    pos: ChunkPos,
+    job: J,
+) -> Result<R, Panicked> {
+    panic::catch_unwind(AssertUnwindSafe(|| work(pos, job))).map_err(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        Panicked { pos, message }
+    })
+}
+fn run_worker<J, R>(
+    shared: &Shared<J>,
+    work: &(dyn Fn(ChunkPos, J) -> R + Send + Sync),
+    sender: &Sender<Result<R, Panicked>>,
+) {
+    loop {
+        let (pos, job) = {
+            let Ok(mut queue) = shared.queue.lock() else {
+                return;
+            };
+            loop {
+                if queue.closed {
+                    return;
+                }
+                if let Some(job) = queue.pop() {
+                    break job;
+                }
+                queue = match shared.changed.wait(queue) {
+                    Ok(queue) => queue,
+                    Err(_) => return,
+                };
+            }
+        };
+        if sender.send(run(work, pos, job)).is_err() {
+            return;
+        }
+    }
+}
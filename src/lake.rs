@@ -0,0 +1,159 @@
+//! Lakes in hollows of the terrain above sea level.
+//!
+//! Like worms, lakes start at random spots of every chunk and a chunk replays the
+//! lakes of the chunks around it, filling the parts inside it. Whether a lake is kept
+//! and how high its water is only depends on the height map, so every chunk a lake
+//! covers agrees on it.
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{
+    block::Block,
+    chunk::{ChunkBlocks, CHUNK_WIDTH},
+    coords::{ChunkPos, LocalBlockPos, CHUNK_SIZE},
+    worldgen::ChunkRng,
+};
+
+const LAKE_SALT: u64 = 6;
+
+/// The `lakes` part of `worldgen.ron`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LakeSettings {
+    /// Average number of lakes tried in a chunk, only the ones in a hollow are kept.
+    pub per_chunk: f64,
+    /// Smallest and largest radius, in blocks.
+    pub radius: (f64, f64),
+    /// How many blocks the middle of a lake goes below its water's edge.
+    pub depth: u32,
+}
+impl LakeSettings {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=16.0).contains(&self.per_chunk) {
+            bail!("lakes per_chunk has to be from 0 to 16");
+        }
+        let (min, max) = self.radius;
+        if !(min > 0.0 && min <= max && max <= 32.0) {
+            bail!("lake radius has to be above 0 and at most 32, smallest first");
+        }
+        if self.depth > 16 {
+            bail!("lake depth can't be above 16");
+        }
+        Ok(())
+    }
+}
+/// A round pool of water, deepest in the middle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lake {
+    /// X and z of the middle.
+    pub center: [f64; 2],
+    pub radius: f64,
+    /// Y of the first block above the water.
+    pub level: i32,
+    pub depth: u32,
+}
+impl Lake {
+    /// Lowest y of water in the column, `None` outside the lake.
+    pub fn bottom(&self, x: i32, z: i32) -> Option<i32> {
+        let dx = x as f64 + 0.5 - self.center[0];
+        let dz = z as f64 + 0.5 - self.center[1];
+        let falloff = 1.0 - (dx * dx + dz * dz) / (self.radius * self.radius);
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(self.level - 1 - (self.depth as f64 * falloff).floor() as i32)
+    }
+}
+pub struct Lakes {
+    seed: u64,
+    settings: LakeSettings,
+    /// How many chunks away a lake can fill from the chunk it starts in.
+    reach: i32,
+}
+impl Lakes {
+    pub fn new(seed: u64, settings: LakeSettings) -> Self {
+        Lakes {
+            seed,
+            reach: ((settings.radius.1 + 1.0) / CHUNK_SIZE as f64).ceil() as i32,
+            settings,
+        }
+    }
+    pub fn settings(&self) -> &LakeSettings {
+        &self.settings
+    }
+    /// Chunks whose lakes can reach into the chunk.
+    pub fn sources(&self, pos: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+        let reach = self.reach;
+        (-reach..=reach).flat_map(move |dz| (-reach..=reach).map(move |dx| pos.offset(dx, dz)))
+    }
+    /// Lakes starting in the chunk. `surface_height` is the height map, lakes are
+    /// kept where it is above `sea_level` and every column around the edge is at
+    /// least as high as the middle, so the banks hold the water.
+    pub fn lakes(
+        &self,
+        chunk: ChunkPos,
+        surface_height: impl Fn(i32, i32) -> i32,
+        sea_level: i32,
+    ) -> Vec<Lake> {
+        let settings = &self.settings;
+        let mut rng = ChunkRng::new(self.seed, LAKE_SALT, chunk);
+        let mut count = settings.per_chunk.floor() as u32;
+        if rng.next_f64() < settings.per_chunk.fract() {
+            count += 1;
+        }
+        let (origin_x, origin_z) = chunk.origin();
+        let mut lakes = vec![];
+        for _ in 0..count {
+            let center = [
+                origin_x as f64 + rng.next_f64() * CHUNK_SIZE as f64,
+                origin_z as f64 + rng.next_f64() * CHUNK_SIZE as f64,
+            ];
+            let (smallest, largest) = settings.radius;
+            let radius = smallest + (largest - smallest) * rng.next_f64();
+            let level = surface_height(center[0].floor() as i32, center[1].floor() as i32);
+            if level <= sea_level {
+                continue;
+            }
+            let lake = Lake {
+                center,
+                radius,
+                level,
+                depth: settings.depth,
+            };
+            //the columns just outside the lake
+            let bank = Lake {
+                radius: radius + 1.5,
+                ..lake
+            };
+            let reach = bank.radius.ceil() as i32;
+            let (x0, z0) = (center[0].floor() as i32, center[1].floor() as i32);
+            let holds = (z0 - reach..=z0 + reach).all(|z| {
+                (x0 - reach..=x0 + reach).all(|x| {
+                    lake.bottom(x, z).is_some()
+                        || bank.bottom(x, z).is_none()
+                        || surface_height(x, z) >= level
+                })
+            });
+            if holds {
+                lakes.push(lake);
+            }
+        }
+        lakes
+    }
+    /// Fills the part of the lake inside the chunk with `water`, whatever was there.
+    pub(crate) fn fill(&self, lake: &Lake, pos: ChunkPos, blocks: &mut ChunkBlocks, water: Block) {
+        let (origin_x, origin_z) = pos.origin();
+        let height = blocks.height();
+        for z in 0..CHUNK_WIDTH {
+            for x in 0..CHUNK_WIDTH {
+                let Some(bottom) = lake.bottom(origin_x + x as i32, origin_z + z as i32) else {
+                    continue;
+                };
+                for y in bottom.max(height.min_y)..lake.level.min(height.max_y()) {
+                    blocks.set(LocalBlockPos::new(x, y, z), water);
+                }
+            }
+        }
+    }
+}
@@ -1,49 +1,77 @@
 use crate::engine::State;
-use engine::Mesh;
-use noise::{NoiseFn, Perlin};
-use std::convert::TryInto;
+use block::BlockRegistry;
+use chunk::WorldHeight;
+use std::{path::PathBuf, sync::Arc};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use winit::{
     event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
 };
+pub mod anvil;
+pub mod biome;
+pub mod block;
 mod camera;
+pub mod cave;
+pub mod chunk;
+pub mod coords;
+pub mod decoration;
 mod engine;
+pub mod jobs;
+pub mod lighting;
+pub mod loader;
+pub mod mesh;
 mod texture;
 mod player;
-#[derive(Copy, Clone, Default,Debug)]
-pub struct Block {
-    block_type: BlockType,
-    is_solid: bool
-}
-impl Block{
-    pub fn new(block_type: BlockType) -> Self {
-        let mut is_solid;
-        match block_type {
-            BlockType::Grass => is_solid = true,
-            _ => is_solid = false
-        }
-        Block { block_type, is_solid }
-    }
-}
-#[derive(Copy, Clone, Default,Debug)]
-pub enum BlockType {
-    #[default]
-    Air,
-    Water,
-    Grass,
-    Stone,
-}
-pub struct Chunk {
-    blocks: Vec<Vec<Vec<Block>>>,
-    mesh: Mesh,
-}
+pub mod lake;
+pub mod nbt;
+pub mod ore;
+pub mod region;
+pub mod schematic;
+pub mod vox;
+pub mod world;
+pub mod worldgen;
+use coords::{ChunkPos, EntityPos};
+use lighting::Lighting;
+use loader::{ChunkLoader, RenderDistance};
+use world::World;
+use worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator};
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let options = Options::from_args();
+    if let Some(radius) = options.ore_report {
+        //a debug command, printing the report instead of starting the game
+        match create_generator(&registry, &options) {
+            Ok(generator) => {
+                let chunks = (-radius..=radius)
+                    .flat_map(|z| (-radius..=radius).map(move |x| ChunkPos::new(x, z)));
+                print!("{}", generator.ores().report(&generator, chunks));
+            }
+            Err(error) => log::error!("couldn't create the generator: {:?}", error),
+        }
+        return;
+    }
+    if let Some(radius) = options.mesh_bench {
+        match create_generator(&registry, &options) {
+            Ok(generator) => print!("{}", mesh_bench(registry, &generator, radius)),
+            Err(error) => log::error!("couldn't create the generator: {:?}", error),
+        }
+        return;
+    }
     // State::new uses async code, so we're going to wait for it to finish
     let (mut state, event_loop) = State::new().await;
-    let mut chunks = create_terrain(&state);
+    state.set_lighting(options.lighting);
+    let (mut world, mut loader) = create_terrain(registry, &options).unwrap();
+    //stand on the ground, wherever the terrain ended up
+    let spawn = state.player.position();
+    loader.wait_for(&mut world, spawn.chunk());
+    let column = spawn.block();
+    if let Some(top) = world.highest_block(column.x, column.z) {
+        state
+            .player
+            .set_position(EntityPos::new(spawn.x, top as f32 + 2.5, spawn.z));
+    }
 
     let mut last_render_time = instant::Instant::now();
     event_loop.run(move |event, _, control_flow| {
@@ -82,12 +110,24 @@ pub async fn run() {
                     _ => {}
                 }
             }
+            Event::LoopDestroyed => {
+                match world.save_modified() {
+                    Ok(saved) => log::info!("saved {} chunks", saved),
+                    Err(error) => log::error!("couldn't save the world: {:?}", error),
+                }
+                if let Some(dir) = &options.export_anvil {
+                    match world.export_anvil(dir, &anvil::BlockMapping::vanilla()) {
+                        Ok(exported) => log::info!("exported {} chunks to {}", exported, dir.display()),
+                        Err(error) => log::error!("couldn't export the world: {:?}", error),
+                    }
+                }
+            }
             Event::RedrawRequested(window_id) if window_id == state.window().id() => {
                 let now = instant::Instant::now();
                 let dt = now - last_render_time;
                 last_render_time = now;
-                state.update(dt, &mut chunks);
-                match state.render(&chunks) {
+                state.update(dt, &mut world, &mut loader);
+                match state.render(&world) {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => state.resize(state.size),
@@ -101,116 +141,156 @@ pub async fn run() {
         }
     });
 }
-fn create_terrain(state: &State) -> [Chunk; 256] {
-    let mut chunks = vec![];
-    let mut chunk_blocks_vec = vec![];
-    let mut chunk_mesh_vec = vec![];
-    //gen chunks
-    for i in 0..256 {
-        let row = (i / 16) * 16;
-        let col = (i % 16) * 16;
-        chunk_blocks_vec.push(chunk_gen(1, row, col));
-    }
-    //gen meshes
-    for i in 0..256 {
-        let row = (i / 16) * 16;
-        let col = (i % 16) * 16;
-        let blocks = &chunk_blocks_vec[i];
-        let mesh = state.build_chunk(
-            blocks,
-            row as f32,
-            col as f32,
-            match i.checked_sub(16) {
-                //actually front
-                Some(j) => chunk_blocks_vec.get(j),
-                None => None,
-            },
-            match i.checked_add(16) {
-                //actually back
-                Some(j) => chunk_blocks_vec.get(j),
-                None => None,
-            },
-            if (i + 1) % 16 == 0 {
-                //actually left
-                None
-            } else {
-                chunk_blocks_vec.get(i + 1)
-            },
-            match i.checked_sub(1) {
-                Some(j) => {
-                    if j % 16 == 15 {
-                        //actually right
-                        None
-                    } else {
-                        chunk_blocks_vec.get(j)
+/// Command line options, none of which exist on the web.
+#[derive(Default)]
+struct Options {
+    /// `--import-anvil <dir>`: region directory of a Minecraft world to load on top
+    /// of the generated terrain.
+    import_anvil: Option<PathBuf>,
+    /// `--export-anvil <dir>`: region directory to write the world to on exit.
+    export_anvil: Option<PathBuf>,
+    /// `--seed <number>`: seed of the terrain, 1 if not given.
+    seed: Option<u64>,
+    /// `--worldgen <file>`: generator settings to use instead of the built in ones.
+    worldgen: Option<PathBuf>,
+    /// `--ores <file>`: ore rules to use instead of the built in ones.
+    ores: Option<PathBuf>,
+    /// `--ore-report <radius>`: print the ores of the chunks up to `radius` chunks
+    /// from the origin and quit.
+    ore_report: Option<i32>,
+    /// `--render-distance <chunks>`: how far around the player chunks are loaded, 8
+    /// if not given.
+    render_distance: Option<i32>,
+    /// `--mesher <simple|greedy>`: how chunk meshes are built, greedy if not given.
+    mesher: Option<mesh::Mesher>,
+    /// `--mesh-bench <radius>`: mesh the chunks up to `radius` chunks from the
+    /// origin with every mesher, print how they compare and quit.
+    mesh_bench: Option<i32>,
+    /// `--lighting <sun|classic>`, `--sun <x,y,z>` and `--ambient <amount>`: how the
+    /// terrain's faces are lit, the sun from `Lighting::default` if not given.
+    lighting: Lighting,
+}
+impl Options {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--import-anvil" => options.import_anvil = args.next().map(PathBuf::from),
+                "--export-anvil" => options.export_anvil = args.next().map(PathBuf::from),
+                "--seed" => match args.next().map(|seed| seed.parse()) {
+                    Some(Ok(seed)) => options.seed = Some(seed),
+                    _ => log::warn!("--seed needs a number"),
+                },
+                "--worldgen" => options.worldgen = args.next().map(PathBuf::from),
+                "--ores" => options.ores = args.next().map(PathBuf::from),
+                "--ore-report" => match args.next().map(|radius| radius.parse()) {
+                    Some(Ok(radius)) if radius >= 0 => options.ore_report = Some(radius),
+                    _ => log::warn!("--ore-report needs a radius in chunks"),
+                },
+                "--render-distance" => match args.next().map(|distance| distance.parse()) {
+                    Some(Ok(distance)) if distance >= 1 => options.render_distance = Some(distance),
+                    _ => log::warn!("--render-distance needs a number of chunks"),
+                },
+                "--mesher" => match args.next().and_then(|name| mesh::Mesher::from_name(&name)) {
+                    Some(mesher) => options.mesher = Some(mesher),
+                    None => log::warn!("--mesher needs simple or greedy"),
+                },
+                "--mesh-bench" => match args.next().map(|radius| radius.parse()) {
+                    Some(Ok(radius)) if radius >= 0 => options.mesh_bench = Some(radius),
+                    _ => log::warn!("--mesh-bench needs a radius in chunks"),
+                },
+                "--lighting" => match args.next().as_deref() {
+                    Some("sun") => options.lighting = Lighting::default(),
+                    Some("classic") => options.lighting = Lighting::Classic,
+                    _ => log::warn!("--lighting needs sun or classic"),
+                },
+                "--sun" => match args.next().as_deref().and_then(parse_direction) {
+                    Some(sun) => options.lighting = options.lighting.with_sun(sun),
+                    None => log::warn!("--sun needs a direction like 1,2,0"),
+                },
+                "--ambient" => match args.next().map(|ambient| ambient.parse()) {
+                    Some(Ok(ambient)) if (0.0..=1.0).contains(&ambient) => {
+                        options.lighting = options.lighting.with_ambient(ambient)
                     }
-                }
-                None => None,
-            },
-        );
-        chunk_mesh_vec.push(mesh);
+                    _ => log::warn!("--ambient needs an amount from 0 to 1"),
+                },
+                other => log::warn!("unknown argument {}", other),
+            }
+        }
+        options
     }
-    for _ in 0..256 {
-        chunks.push(Chunk {
-            blocks: chunk_blocks_vec.remove(0),
-            mesh: chunk_mesh_vec.remove(0),
-        }) //always takes out the first element
+    #[cfg(target_arch = "wasm32")]
+    fn from_args() -> Self {
+        Options::default()
     }
-    chunks.try_into().unwrap_or_else(|v: Vec<Chunk>| {
-        panic!("Expected a Vec of length 256 but it was {}", v.len())
-    })
 }
-fn chunk_gen(seed: u32, row: i32, col: i32) -> Vec<Vec<Vec<Block>>> {
-    let mut test_blocks = vec![];
-    let perlin = Perlin::new(seed);
-    let x_scale = 0.03;
-    let z_scale = 0.03;
-    for x in 0..16 {
-        //front back
-        let mut vec1 = vec![];
-        for z in 0..16 {
-            //left right
-            let mut vec2 = vec![];
-            let noise_value =
-                (perlin.get([(x + row) as f64 * x_scale, (z + col) as f64 * z_scale]) + 2.0) * 10.0;
-            for y in 0..30 {
-                //up down
-                let block_type = if y < (noise_value) as usize {
-                    BlockType::Grass
-                } else {
-                    BlockType::Air
-                };
-
-                vec2.push(Block::new(block_type));
-            }
-            vec1.push(vec2);
-        }
-
-        test_blocks.push(flip_2d_vector(vec1));
+/// A direction written as `x,y,z`, not all 0.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_direction(text: &str) -> Option<[f32; 3]> {
+    let axes = text
+        .split(',')
+        .map(|axis| axis.trim().parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    match axes[..] {
+        [x, y, z] if [x, y, z] != [0.0; 3] => Some([x, y, z]),
+        _ => None,
     }
-    test_blocks
 }
-fn flip_2d_vector(input: Vec<Vec<Block>>) -> Vec<Vec<Block>> {
-    if input.is_empty() {
-        return Vec::new();
+fn create_generator(
+    registry: &BlockRegistry,
+    options: &Options,
+) -> anyhow::Result<NoiseGenerator> {
+    let settings = match &options.worldgen {
+        Some(path) => GeneratorSettings::load(path)?,
+        None => GeneratorSettings::load_default()?,
+    };
+    let height = WorldHeight::default();
+    let generator = NoiseGenerator::new(options.seed.unwrap_or(1), settings, registry, height)?;
+    match &options.ores {
+        Some(path) => generator.with_ores(ore::Ores::load_rules(path)?, registry),
+        None => Ok(generator),
     }
-
-    let num_rows = input.len();
-    let num_cols = input[0].len();
-
-    let mut flipped = vec![
-        vec![
-            Block::default();
-            num_rows
-        ];
-        num_cols
-    ];
-
-    for i in 0..num_rows {
-        for j in 0..num_cols {
-            flipped[j][i] = input[i][j];
+}
+/// The world, empty until the loader loads the chunks around the player.
+fn create_terrain(
+    registry: Arc<BlockRegistry>,
+    options: &Options,
+) -> anyhow::Result<(World, ChunkLoader)> {
+    let generator = create_generator(&registry, options)?;
+    let mut world = World::new(registry.clone(), generator.height());
+    #[cfg(not(target_arch = "wasm32"))]
+    match region::RegionStorage::open("saves/world") {
+        Ok(storage) => world.set_storage(storage),
+        Err(error) => log::error!("playing without saving: {:?}", error),
+    }
+    let distance = RenderDistance::new(options.render_distance.unwrap_or(8));
+    let mut loader = ChunkLoader::new(Arc::new(generator), registry, distance);
+    loader.set_mesher(options.mesher.unwrap_or_default());
+    if let Some(dir) = &options.import_anvil {
+        match world.import_anvil(dir, &anvil::BlockMapping::vanilla()) {
+            Ok(imported) => log::info!("imported {} chunks from {}", imported, dir.display()),
+            Err(error) => log::error!("couldn't import {}: {:?}", dir.display(), error),
         }
     }
-
-    flipped
+    Ok((world, loader))
+}
+/// Generates the chunks up to `radius` chunks from the origin, with their neighbors
+/// so every face is there, and compares how the meshers do on them.
+fn mesh_bench(registry: Arc<BlockRegistry>, generator: &NoiseGenerator, radius: i32) -> String {
+    let mut world = World::new(registry.clone(), generator.height());
+    let around = radius + 1;
+    for z in -around..=around {
+        for x in -around..=around {
+            let pos = ChunkPos::new(x, z);
+            let blocks = generator.generate(pos);
+            let features = generator.decorate(pos, &blocks);
+            world.insert_chunk(pos, chunk::Chunk::new(blocks));
+            world.place_features(features);
+        }
+    }
+    let chunks =
+        (-radius..=radius).flat_map(|z| (-radius..=radius).map(move |x| ChunkPos::new(x, z)));
+    mesh::mesher_report(&world, &registry, chunks)
 }
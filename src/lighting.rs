@@ -0,0 +1,84 @@
+//! How bright the terrain's faces are by the direction they point, worked out once
+//! per face here and handed to `shader.wgsl` as a uniform.
+
+use crate::mesh;
+
+/// Faces a `Vertex` can point in, the six of a cube and the two diagonal planes of
+/// crosses.
+pub const FACES: usize = 8;
+/// The default sun's direction, high up a bit to the south east.
+const SUN: [f32; 3] = [0.3, 1.0, 0.5];
+/// The default sun lighting's ambient light.
+const AMBIENT: f32 = 0.4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Lighting {
+    /// Light from the sun, lighting faces more the closer they point at it, on top
+    /// of `ambient` light reaching every face.
+    Sun {
+        /// Direction towards the sun, any length but 0.
+        direction: [f32; 3],
+        /// Brightness of faces the sun doesn't reach, from 0 to 1.
+        ambient: f32,
+    },
+    /// Fixed brightness per face, as Minecraft classic shades them: tops full,
+    /// bottoms half, sides facing north and south at 80% and east and west at 60%.
+    Classic,
+}
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting::Sun {
+            direction: SUN,
+            ambient: AMBIENT,
+        }
+    }
+}
+impl Lighting {
+    /// Sun lighting with the sun in `direction`, keeping the ambient light of this
+    /// lighting if it is sun lighting too.
+    pub fn with_sun(self, direction: [f32; 3]) -> Lighting {
+        let (_, ambient) = self.sun();
+        Lighting::Sun { direction, ambient }
+    }
+    /// Sun lighting with `ambient` light, keeping the sun of this lighting if it is
+    /// sun lighting too.
+    pub fn with_ambient(self, ambient: f32) -> Lighting {
+        let (direction, _) = self.sun();
+        Lighting::Sun { direction, ambient }
+    }
+    /// The sun's direction and the ambient light, the default ones if this isn't
+    /// sun lighting.
+    fn sun(&self) -> ([f32; 3], f32) {
+        match *self {
+            Lighting::Sun { direction, ambient } => (direction, ambient),
+            Lighting::Classic => (SUN, AMBIENT),
+        }
+    }
+    /// Brightness of every face, indexed like `Vertex::face`.
+    pub fn shades(&self) -> [f32; FACES] {
+        match *self {
+            //crosses between the two kinds of sides
+            Lighting::Classic => [1.0, 0.5, 0.6, 0.6, 0.8, 0.8, 0.7, 0.7],
+            Lighting::Sun { direction, ambient } => {
+                let ambient = ambient.clamp(0.0, 1.0);
+                let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+                let mut shades = [ambient; FACES];
+                if length == 0.0 {
+                    return shades;
+                }
+                for (face, shade) in shades.iter_mut().enumerate() {
+                    let normal = mesh::face_normal(face as u32);
+                    let facing: f32 = (0..3).map(|i| normal[i] * direction[i] / length).sum();
+                    //both sides of a cross are seen, and lit, through the same face
+                    let facing = if face >= 6 {
+                        facing.abs()
+                    } else {
+                        facing.max(0.0)
+                    };
+                    *shade = ambient + (1.0 - ambient) * facing;
+                }
+                shades
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Chunks loaded around the player as they move and unloaded once they are far
+//! away. Generating and meshing them happens on a `JobPool`, the results handed back
+//! to the main thread to be put into the world and uploaded a few per frame.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use crate::{
+    block::{Block, BlockRegistry},
+    chunk::{Chunk, ChunkBlocks},
+    coords::{BlockPos, ChunkPos},
+    jobs::JobPool,
+    mesh::{self, ChunkMeshData, Mesher},
+    world::{ChunkSnapshot, World},
+    worldgen::WorldGenerator,
+};
+
+/// How far from the player chunks are loaded, in chunks. Chunks load once they
+/// are within `load` and unload once they are further than `unload`, so ones at the
+/// border don't load and unload over and over as the player walks along it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderDistance {
+    pub load: i32,
+    pub unload: i32,
+}
+impl RenderDistance {
+    /// Unloading two chunks further out than loading.
+    pub fn new(load: i32) -> Self {
+        RenderDistance {
+            load,
+            unload: load + 2,
+        }
+    }
+    /// Whether the chunk at `pos` loads with the player in chunk `center`.
+    pub fn loads(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        distance_squared(center, pos) <= self.load * self.load
+    }
+    /// Whether the chunk at `pos` stays loaded with the player in chunk `center`.
+    pub fn keeps(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        distance_squared(center, pos) <= self.unload * self.unload
+    }
+    /// Every chunk that loads with the player in chunk `center`, nearest first.
+    pub fn chunks(&self, center: ChunkPos) -> Vec<ChunkPos> {
+        let load = self.load;
+        let mut chunks: Vec<ChunkPos> = (-load..=load)
+            .flat_map(|dz| (-load..=load).map(move |dx| center.offset(dx, dz)))
+            .filter(|pos| self.loads(center, *pos))
+            .collect();
+        chunks.sort_by_key(|pos| distance_squared(center, *pos));
+        chunks
+    }
+}
+fn distance_squared(a: ChunkPos, b: ChunkPos) -> i32 {
+    (a.x - b.x).pow(2) + (a.z - b.z).pow(2)
+}
+enum Job {
+    Generate,
+    /// Sections to mesh, with the version each is meshed as.
+    Mesh(Box<ChunkSnapshot>, Vec<(usize, u64)>, Mesher),
+}
+enum Done {
+    Generated(ChunkPos, ChunkBlocks, Vec<(BlockPos, Block)>),
+    Meshed(ChunkPos, Vec<(usize, u64, Option<ChunkMeshData>)>),
+}
+pub struct ChunkLoader {
+    jobs: JobPool<Job, Done>,
+    distance: RenderDistance,
+    mesher: Mesher,
+    /// Chunk the player was in at the last update.
+    center: Option<ChunkPos>,
+    /// Chunks queued or being generated.
+    generating: HashSet<ChunkPos>,
+    /// Version of the latest mesh job of every section waiting for one, meshes of
+    /// older jobs are thrown away since the blocks changed after them.
+    versions: HashMap<(ChunkPos, usize), u64>,
+    next_version: u64,
+    /// Meshes ready to upload, oldest first.
+    meshed: VecDeque<(ChunkPos, usize, u64, Option<ChunkMeshData>)>,
+}
+impl ChunkLoader {
+    pub fn new(
+        generator: Arc<dyn WorldGenerator>,
+        registry: Arc<BlockRegistry>,
+        distance: RenderDistance,
+    ) -> Self {
+        let jobs = JobPool::with_available_threads(move |pos, job| match job {
+            Job::Generate => {
+                let blocks = generator.generate(pos);
+                let features = generator.decorate(pos, &blocks);
+                Done::Generated(pos, blocks, features)
+            }
+            Job::Mesh(snapshot, sections, mesher) => {
+                let meshes = sections
+                    .into_iter()
+                    .map(|(index, version)| {
+                        let mesh = mesh::mesh_section(&snapshot, &registry, index, mesher);
+                        (index, version, mesh)
+                    })
+                    .collect();
+                Done::Meshed(pos, meshes)
+            }
+        });
+        ChunkLoader {
+            jobs,
+            distance,
+            mesher: Mesher::default(),
+            center: None,
+            generating: HashSet::new(),
+            versions: HashMap::new(),
+            next_version: 0,
+            meshed: VecDeque::new(),
+        }
+    }
+    pub fn distance(&self) -> RenderDistance {
+        self.distance
+    }
+    /// Makes sections meshed from now on use `mesher`.
+    pub fn set_mesher(&mut self, mesher: Mesher) {
+        self.mesher = mesher;
+    }
+    /// Chunks queued or being generated.
+    pub fn generating(&self) -> usize {
+        self.generating.len()
+    }
+    /// Puts the chunk into the world as it was saved, or has it generated in the
+    /// background if it never was.
+    fn load(&mut self, world: &mut World, pos: ChunkPos) {
+        if world.chunk(pos).is_some() || self.generating.contains(&pos) {
+            return;
+        }
+        match world.load_saved(pos) {
+            Some(blocks) => insert(world, pos, blocks),
+            None => {
+                self.generating.insert(pos);
+                self.jobs.push(pos, Job::Generate);
+            }
+        }
+    }
+    /// Loads the chunks around `center` and waits until the one in the middle is in
+    /// the world, for the player to start in, or generating it failed.
+    pub fn wait_for(&mut self, world: &mut World, center: ChunkPos) {
+        self.update(world, center, 0);
+        while world.chunk(center).is_none() && self.generating.contains(&center) {
+            std::thread::yield_now();
+            self.update(world, center, 0);
+        }
+    }
+    /// Loads and unloads chunks if the player moved to another chunk `near`, puts
+    /// the chunks generated since the last update into the world, and sends up to
+    /// `budget` dirty sections to be meshed, the ones nearest to `near` first.
+    pub fn update(&mut self, world: &mut World, near: ChunkPos, budget: usize) {
+        if self.center != Some(near) {
+            self.center = Some(near);
+            self.jobs.set_focus(near);
+            self.stream(world, near);
+        }
+        for done in self.jobs.poll() {
+            let done = match done {
+                Ok(done) => done,
+                Err(panicked) => {
+                    log::error!(
+                        "job for chunk {:?} panicked: {}",
+                        panicked.pos,
+                        panicked.message
+                    );
+                    //the chunk stays missing until it loads again, instead of holding
+                    //back the meshing of its neighbors
+                    self.generating.remove(&panicked.pos);
+                    continue;
+                }
+            };
+            match done {
+                Done::Generated(pos, blocks, features) => {
+                    self.generating.remove(&pos);
+                    //chunks loaded some other way in the meantime, like imported ones,
+                    //win over the generated ones
+                    if world.chunk(pos).is_none() && self.distance.keeps(near, pos) {
+                        insert(world, pos, blocks);
+                        world.place_features(features);
+                    }
+                }
+                Done::Meshed(pos, meshes) => self.meshed.extend(
+                    meshes
+                        .into_iter()
+                        .map(|(index, version, mesh)| (pos, index, version, mesh)),
+                ),
+            }
+        }
+        //a chunk meshed before the chunks around it are generated is meshed again
+        //once they are, so wait for them
+        let generating = &self.generating;
+        let ready = |pos: ChunkPos| {
+            (-1..=1).all(|dz| (-1..=1).all(|dx| !generating.contains(&pos.offset(dx, dz))))
+        };
+        let mut sections: HashMap<ChunkPos, Vec<(usize, u64)>> = HashMap::new();
+        for (pos, index) in world.take_dirty(near, budget, ready) {
+            self.next_version += 1;
+            self.versions.insert((pos, index), self.next_version);
+            sections
+                .entry(pos)
+                .or_default()
+                .push((index, self.next_version));
+        }
+        for (pos, sections) in sections {
+            if let Some(snapshot) = world.snapshot(pos) {
+                self.jobs
+                    .push(pos, Job::Mesh(Box::new(snapshot), sections, self.mesher));
+            }
+        }
+    }
+    /// Unloads the chunks too far from `center`, dropping their queued jobs, and
+    /// loads the ones close enough.
+    fn stream(&mut self, world: &mut World, center: ChunkPos) {
+        let distance = self.distance;
+        for pos in world.chunk_positions() {
+            if distance.keeps(center, pos) {
+                continue;
+            }
+            if let Err(error) = world.unload_chunk(pos) {
+                log::error!("couldn't save chunk {:?}, keeping it: {:?}", pos, error);
+            }
+        }
+        let generating = &mut self.generating;
+        self.jobs.retain(|pos, job| {
+            let keep = !matches!(job, Job::Generate) || distance.keeps(center, pos);
+            if !keep {
+                generating.remove(&pos);
+            }
+            keep
+        });
+        self.versions
+            .retain(|(pos, _), _| world.chunk(*pos).is_some());
+        for pos in distance.chunks(center) {
+            self.load(world, pos);
+        }
+    }
+    /// Meshes built since they were last taken, as `(chunk, section, mesh)`, leaving
+    /// out the ones whose blocks changed since. Stops after `budget` of them that
+    /// aren't `None`, keeping the rest for later.
+    pub fn take_meshes(&mut self, budget: usize) -> Vec<(ChunkPos, usize, Option<ChunkMeshData>)> {
+        let mut meshes = vec![];
+        let mut uploads = 0;
+        while uploads < budget {
+            let Some((pos, index, version, mesh)) = self.meshed.pop_front() else {
+                break;
+            };
+            if self.versions.get(&(pos, index)) != Some(&version) {
+                continue;
+            }
+            self.versions.remove(&(pos, index));
+            uploads += mesh.is_some() as usize;
+            meshes.push((pos, index, mesh));
+        }
+        meshes
+    }
+}
+/// Adds the chunk to the world, to be meshed along with its neighbors.
+fn insert(world: &mut World, pos: ChunkPos, blocks: ChunkBlocks) {
+    world.insert_chunk(pos, Chunk::new(blocks));
+    world.mark_chunk_dirty(pos);
+}
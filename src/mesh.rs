@@ -0,0 +1,735 @@
+//! Meshes of chunk sections, built from the blocks of a chunk and its neighbors
+//! without touching the GPU, so workers can build them and tests can check them.
+//! `engine::State` uploads them.
+
+use std::ops::Range;
+
+use crate::{
+    block::{Block, BlockModel, BlockRegistry, RenderLayer, Shape},
+    chunk::{CHUNK_WIDTH, SECTION_HEIGHT, SECTION_VOLUME},
+    coords::{ChunkPos, LocalBlockPos},
+    world::{ChunkSnapshot, World},
+};
+
+/// Vertices and indices of a section's mesh, before they are uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMeshData {
+    /// World position of the section's lowest corner, the vertices' positions are
+    /// relative to it.
+    pub origin: [i32; 3],
+    pub vertices: Vec<Vertex>,
+    /// Indices of the triangles of every layer, one layer after another in the
+    /// order of `RenderLayer::ALL`.
+    pub indices: Vec<u32>,
+    /// Where the indices of each layer end.
+    pub layer_ends: [usize; 3],
+}
+impl ChunkMeshData {
+    /// Where the layer's indices are in `indices`.
+    pub fn layer_range(&self, layer: RenderLayer) -> Range<usize> {
+        let index = layer as usize;
+        let start = if index == 0 {
+            0
+        } else {
+            self.layer_ends[index - 1]
+        };
+        start..self.layer_ends[index]
+    }
+    /// Indices of the layer's triangles.
+    pub fn layer(&self, layer: RenderLayer) -> &[u32] {
+        &self.indices[self.layer_range(layer)]
+    }
+}
+/// The quads of a mesh's translucent layer, kept to sort them back to front as the
+/// camera moves: blending draws them over each other in the order of their indices.
+#[derive(Clone, Debug, Default)]
+pub struct TranslucentQuads {
+    /// Middle of each quad, relative to the section's origin.
+    centers: Vec<[f32; 3]>,
+    /// The six indices of each quad.
+    indices: Vec<u32>,
+}
+impl TranslucentQuads {
+    pub fn new(data: &ChunkMeshData) -> Self {
+        let indices = data.layer(RenderLayer::Translucent).to_vec();
+        //the middle of the quad's two triangles, the corners on the diagonal they
+        //share counting twice like the others
+        let centers = indices
+            .chunks(6)
+            .map(|quad| {
+                let mut center = [0.0; 3];
+                for index in quad {
+                    let position = data.vertices[*index as usize].position();
+                    for axis in 0..3 {
+                        center[axis] += position[axis] as f32 / 6.0;
+                    }
+                }
+                center
+            })
+            .collect();
+        TranslucentQuads { centers, indices }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    /// The indices with the quads furthest from `eye` first, `eye` being relative to
+    /// the section's origin.
+    pub fn sorted(&self, eye: [f32; 3]) -> Vec<u32> {
+        let distance = |center: &[f32; 3]| -> f32 {
+            (0..3).map(|axis| (center[axis] - eye[axis]).powi(2)).sum()
+        };
+        let mut order: Vec<usize> = (0..self.centers.len()).collect();
+        order.sort_by(|a, b| distance(&self.centers[*b]).total_cmp(&distance(&self.centers[*a])));
+        order
+            .into_iter()
+            .flat_map(|quad| self.indices[quad * 6..quad * 6 + 6].iter().copied())
+            .collect()
+    }
+}
+/// A vertex of the terrain packed into a `u32`, which `shader.wgsl` unpacks again.
+/// From the lowest bit up:
+///
+/// - 15 bits of position in the section, 5 for each axis, from 0 to 16
+/// - 3 bits of the direction the face points, a `Face` or 6 and 7 for the two
+///   diagonal planes of crosses
+/// - 3 bits of how the texture lies on the face, see `tex_coords`
+/// - 8 bits of atlas tile
+/// - 2 bits of ambient occlusion, how dark the corner is from 0 to 3
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex(pub u32);
+impl Vertex {
+    /// Swaps the two axes of the face's plane to get the texture coordinates.
+    pub const SWAP_UV: u32 = 1;
+    /// Counts the texture's u coordinate the other way.
+    pub const FLIP_U: u32 = 2;
+    /// Counts the texture's v coordinate the other way.
+    pub const FLIP_V: u32 = 4;
+    pub fn new(position: [u32; 3], face: u32, uv: u32, tile: u32, occlusion: u32) -> Self {
+        debug_assert!(position.iter().all(|axis| *axis <= 16) && face < 8 && uv < 8);
+        debug_assert!(tile < 256 && occlusion < 4);
+        Vertex(
+            position[0]
+                | position[1] << 5
+                | position[2] << 10
+                | face << 15
+                | uv << 18
+                | tile << 21
+                | occlusion << 29,
+        )
+    }
+    pub fn position(&self) -> [u32; 3] {
+        [self.0 & 31, self.0 >> 5 & 31, self.0 >> 10 & 31]
+    }
+    pub fn face(&self) -> u32 {
+        self.0 >> 15 & 7
+    }
+    pub fn uv(&self) -> u32 {
+        self.0 >> 18 & 7
+    }
+    pub fn tile(&self) -> u32 {
+        self.0 >> 21 & 255
+    }
+    pub fn occlusion(&self) -> u32 {
+        self.0 >> 29 & 3
+    }
+    /// Unit normal of the face, one of the two for the diagonal planes of crosses.
+    pub fn normal(&self) -> [f32; 3] {
+        face_normal(self.face())
+    }
+    /// Texture coordinates in blocks, the position along the two axes of the face's
+    /// plane turned as `uv` says. The shader repeats the tile once per block, so
+    /// merged quads need nothing else.
+    pub fn tex_coords(&self) -> [i32; 2] {
+        let [x, y, z] = self.position().map(|axis| axis as i32);
+        let plane = match self.face() {
+            0 | 1 => [x, z],
+            2 | 3 => [z, y],
+            _ => [x, y],
+        };
+        let uv = self.uv();
+        let [u, v] = if uv & Self::SWAP_UV != 0 {
+            [plane[1], plane[0]]
+        } else {
+            plane
+        };
+        [
+            if uv & Self::FLIP_U != 0 { -u } else { u },
+            if uv & Self::FLIP_V != 0 { -v } else { v },
+        ]
+    }
+}
+/// Unit normal of the face `face`, as in `Vertex`.
+pub fn face_normal(face: u32) -> [f32; 3] {
+    let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+    match face {
+        0..=5 => {
+            let face = [
+                Face::Top,
+                Face::Bottom,
+                Face::Left,
+                Face::Right,
+                Face::Back,
+                Face::Front,
+            ][face as usize];
+            face.normal().map(|n| n as f32)
+        }
+        6 => [diagonal, 0.0, -diagonal],
+        _ => [diagonal, 0.0, diagonal],
+    }
+}
+/// How sections are turned into meshes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mesher {
+    /// A quad for every face that can be seen.
+    Simple,
+    /// Faces side by side with the same texture merged into bigger quads.
+    #[default]
+    Greedy,
+}
+impl Mesher {
+    pub const ALL: [Mesher; 2] = [Mesher::Simple, Mesher::Greedy];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mesher::Simple => "simple",
+            Mesher::Greedy => "greedy",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Mesher> {
+        Mesher::ALL
+            .iter()
+            .copied()
+            .find(|mesher| mesher.name() == name)
+    }
+}
+/// A corner of a quad before it is packed, in blocks from the section's lowest
+/// corner.
+#[derive(Copy, Clone)]
+struct Corner {
+    position: [f32; 3],
+    /// Position in the atlas tile, counting on past 1 across merged quads where the
+    /// tile repeats.
+    tex_coords: [f32; 2],
+}
+struct Quad {
+    corners: [Corner; 4],
+    /// Direction it faces, as in `Vertex`.
+    face: u32,
+    tile: u32,
+    /// Ambient occlusion of each corner, see `corner_occlusion`.
+    occlusion: [u32; 4],
+}
+impl Quad {
+    /// The corners as vertices, working out how the texture lies from their
+    /// texture coordinates.
+    fn pack(&self) -> [Vertex; 4] {
+        let [first, second] = match self.face {
+            0..=5 => {
+                let [_, first, second] = Face::ALL[self.face as usize].plane();
+                [first, second]
+            }
+            _ => [0, 1],
+        };
+        //how much a texture coordinate grows along one axis, between corners across
+        //the quad from each other only along that axis
+        let slope = |axis: usize, other: usize, uv: usize| {
+            let corners = &self.corners;
+            (0..4)
+                .flat_map(|i| (0..4).map(move |j| (&corners[i], &corners[j])))
+                .find(|(a, b)| {
+                    a.position[other] == b.position[other] && a.position[axis] > b.position[axis]
+                })
+                .map_or(0.0, |(a, b)| {
+                    (a.tex_coords[uv] - b.tex_coords[uv]) / (a.position[axis] - b.position[axis])
+                })
+        };
+        let mut uv = 0;
+        let (u, v) = if slope(first, second, 0) != 0.0 {
+            (slope(first, second, 0), slope(second, first, 1))
+        } else {
+            uv |= Vertex::SWAP_UV;
+            (slope(second, first, 0), slope(first, second, 1))
+        };
+        if u < 0.0 {
+            uv |= Vertex::FLIP_U;
+        }
+        if v < 0.0 {
+            uv |= Vertex::FLIP_V;
+        }
+        [0, 1, 2, 3].map(|i| {
+            let position = self.corners[i].position.map(|axis| axis as u32);
+            Vertex::new(position, self.face, uv, self.tile, self.occlusion[i])
+        })
+    }
+}
+/// A face the greedy mesher can merge with the same one of the blocks next to it.
+#[derive(Copy, Clone, PartialEq)]
+struct FaceKey {
+    block: Block,
+    /// Face of the block's model, before it is turned.
+    face: Face,
+    covered: bool,
+    occlusion: [u32; 4],
+}
+/// Builds the mesh of one section of the snapshot's chunk. It doesn't touch the GPU,
+/// so any thread can do it. `None` where there is nothing to draw.
+pub fn mesh_section(
+    snapshot: &ChunkSnapshot,
+    registry: &BlockRegistry,
+    index: usize,
+    mesher: Mesher,
+) -> Option<ChunkMeshData> {
+    let mut vertices: Vec<Vertex> = vec![];
+    //indices of each layer's triangles, put one after another at the end
+    let mut layers: [Vec<u32>; 3] = Default::default();
+    let (pos, blocks) = (snapshot.pos, &snapshot.blocks);
+    if blocks.section(index).is_empty() || section_is_hidden(snapshot, registry, index) {
+        return None;
+    }
+    let (x_offset, z_offset) = pos.origin();
+    let min_y = blocks.height().section_min_y(index);
+    //blocks past the chunk border come from the neighboring chunks of the snapshot
+    let neighbor_at = |local: LocalBlockPos, [dx, dy, dz]: [i32; 3]| {
+        let neighbor = pos.block(local).offset(dx, dy, dz);
+        if !blocks.height().contains(neighbor.y) {
+            return None;
+        }
+        if neighbor.chunk() == pos {
+            Some(blocks.get(neighbor.local()))
+        } else {
+            snapshot.get_block(neighbor)
+        }
+    };
+    //faces the greedy mesher merges once it has all of them, see `merge_faces`
+    let mut faces = vec![None; Face::ALL.len() * SECTION_VOLUME];
+
+    for x in 0..CHUNK_WIDTH {
+        for y in min_y..min_y + SECTION_HEIGHT as i32 {
+            for z in 0..CHUNK_WIDTH {
+                let local = LocalBlockPos::new(x, y, z);
+                let block = blocks.get(local);
+                if block.is_air() {
+                    continue;
+                }
+                //center of the block in the section, it covers x..x + 1 and so on
+                let center = [x as f32 + 0.5, (y - min_y) as f32 + 0.5, z as f32 + 0.5];
+                let covered = registry.block_type(blocks.get(LocalBlockPos::new(x, y + 1, z)))
+                    == registry.block_type(block);
+                let model = registry.model(block);
+                let indices = &mut layers[registry.layer(block) as usize];
+                if model.shape == Shape::Cross {
+                    get_cross_faces(model, center, &mut vertices, indices);
+                    continue;
+                }
+
+                //block rendering, each face of the model is culled against the
+                //neighbor on the side the block state turns it to
+                for face in Face::ALL {
+                    let direction = face.rotated(model);
+                    //no neighbor means the edge of the loaded world, which is never
+                    //looked at from outside
+                    let neighbor = neighbor_at(local, direction.normal());
+                    if !neighbor.is_some_and(|neighbor| face_is_seen(registry, block, neighbor)) {
+                        continue;
+                    }
+                    let mut quad = get_mesh_texture_and_pos(face, model, center, covered);
+                    quad.occlusion = corner_occlusion(&quad, center, direction, |offset| {
+                        neighbor_at(local, offset)
+                            .is_some_and(|neighbor| !registry.is_transparent(neighbor))
+                    });
+                    match mesher {
+                        Mesher::Simple => push_quad(&quad, &mut vertices, indices),
+                        //faces only merge with ones shaded the same
+                        Mesher::Greedy => {
+                            let cell = [x, (y - min_y) as usize, z];
+                            faces[face_index(direction, cell)] = Some(FaceKey {
+                                block,
+                                face,
+                                covered,
+                                occlusion: quad.occlusion,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if mesher == Mesher::Greedy {
+        merge_faces(&mut faces, registry, &mut vertices, &mut layers);
+    }
+    let mut indices = vec![];
+    let mut layer_ends = [0; 3];
+    for (layer, end) in layers.iter().zip(&mut layer_ends) {
+        indices.extend_from_slice(layer);
+        *end = indices.len();
+    }
+    if indices.is_empty() {
+        return None;
+    }
+    Some(ChunkMeshData {
+        origin: [x_offset, min_y, z_offset],
+        vertices,
+        indices,
+        layer_ends,
+    })
+}
+/// Whether the face of `block` towards `neighbor` can be seen: not behind an opaque
+/// block, and not between two of the same see-through block, like inside water.
+/// Faces of opaque blocks show through water and other transparent blocks.
+pub fn face_is_seen(registry: &BlockRegistry, block: Block, neighbor: Block) -> bool {
+    if !registry.is_transparent(neighbor) {
+        return false;
+    }
+    !registry.is_transparent(block) || registry.block_type(neighbor) != registry.block_type(block)
+}
+/// A table of the vertices and indices every mesher makes for all sections of
+/// `chunks`, and how long it took them. The chunks' neighbors should be loaded too,
+/// or the faces towards them are left out.
+pub fn mesher_report(
+    world: &World,
+    registry: &BlockRegistry,
+    chunks: impl IntoIterator<Item = ChunkPos>,
+) -> String {
+    let snapshots: Vec<ChunkSnapshot> = chunks
+        .into_iter()
+        .filter_map(|pos| world.snapshot(pos))
+        .collect();
+    let mut report = format!(
+        "{:<10}{:>12}{:>12}{:>12}{:>14}{:>12}\n",
+        "mesher", "sections", "vertices", "indices", "vertex bytes", "ms"
+    );
+    for mesher in Mesher::ALL {
+        let start = instant::Instant::now();
+        let (mut sections, mut vertices, mut indices) = (0, 0, 0);
+        for snapshot in &snapshots {
+            for index in 0..snapshot.blocks.height().sections {
+                let Some(mesh) = mesh_section(snapshot, registry, index, mesher) else {
+                    continue;
+                };
+                sections += 1;
+                vertices += mesh.vertices.len();
+                indices += mesh.indices.len();
+            }
+        }
+        let millis = start.elapsed().as_secs_f64() * 1000.0;
+        report += &format!(
+            "{:<10}{:>12}{:>12}{:>12}{:>14}{:>12.1}\n",
+            mesher.name(),
+            sections,
+            vertices,
+            indices,
+            vertices * std::mem::size_of::<Vertex>(),
+            millis
+        );
+    }
+    report
+}
+/// Index into the greedy mesher's faces of the face pointing in `direction` of the
+/// block at `cell` of the section. The faces of one direction are laid out by the
+/// axes of `Face::plane`, so rows along the first axis of the plane are next to each
+/// other.
+fn face_index(direction: Face, cell: [usize; 3]) -> usize {
+    let [normal, first, second] = direction.plane();
+    let width = CHUNK_WIDTH;
+    ((direction as usize * width + cell[normal]) * width + cell[second]) * width + cell[first]
+}
+/// Merges the faces into as few quads as it can: each face not merged yet grows
+/// along the first axis of its plane as far as the same face goes, then along the
+/// second as long as whole rows of that width match.
+fn merge_faces(
+    faces: &mut [Option<FaceKey>],
+    registry: &BlockRegistry,
+    vertices: &mut Vec<Vertex>,
+    layers: &mut [Vec<u32>; 3],
+) {
+    let width = CHUNK_WIDTH;
+    for direction in Face::ALL {
+        let [normal, first, second] = direction.plane();
+        let index = |n: usize, a: usize, b: usize| {
+            let mut cell = [0; 3];
+            cell[normal] = n;
+            cell[first] = a;
+            cell[second] = b;
+            face_index(direction, cell)
+        };
+        for n in 0..width {
+            for b in 0..width {
+                for a in 0..width {
+                    let Some(key) = faces[index(n, a, b)] else {
+                        continue;
+                    };
+                    let mut size_a = 1;
+                    while a + size_a < width && faces[index(n, a + size_a, b)] == Some(key) {
+                        size_a += 1;
+                    }
+                    let mut size_b = 1;
+                    while b + size_b < width
+                        && (a..a + size_a).all(|a| faces[index(n, a, b + size_b)] == Some(key))
+                    {
+                        size_b += 1;
+                    }
+                    for b in b..b + size_b {
+                        for a in a..a + size_a {
+                            faces[index(n, a, b)] = None;
+                        }
+                    }
+                    //the quad starts as the face of the block in its low corner
+                    let mut center = [0.5; 3];
+                    center[normal] += n as f32;
+                    center[first] += a as f32;
+                    center[second] += b as f32;
+                    let model = registry.model(key.block);
+                    let mut quad = get_mesh_texture_and_pos(key.face, model, center, key.covered);
+                    quad.occlusion = key.occlusion;
+                    stretch(&mut quad.corners, center, [first, second], [size_a, size_b]);
+                    let layer = registry.layer(key.block) as usize;
+                    push_quad(&quad, vertices, &mut layers[layer]);
+                }
+            }
+        }
+    }
+}
+/// Stretches the quad of a block's face over `size` blocks along each of the `axes`
+/// of its plane. The texture coordinates go on counting, so the tile repeats once
+/// per block.
+fn stretch(quad: &mut [Corner; 4], center: [f32; 3], axes: [usize; 2], size: [usize; 2]) {
+    let original = *quad;
+    for (i, (axis, other)) in [(axes[0], axes[1]), (axes[1], axes[0])].iter().enumerate() {
+        let extra = (size[i] - 1) as f32;
+        for (vertex, corner) in quad.iter_mut().zip(&original) {
+            if corner.position[*axis] < center[*axis] {
+                continue;
+            }
+            //the corner across the block from it along the axis
+            let Some(across) = original.iter().find(|across| {
+                across.position[*axis] < center[*axis]
+                    && across.position[*other] == corner.position[*other]
+            }) else {
+                continue;
+            };
+            vertex.position[*axis] += extra;
+            for uv in 0..2 {
+                vertex.tex_coords[uv] += (corner.tex_coords[uv] - across.tex_coords[uv]) * extra;
+            }
+        }
+    }
+}
+/// Whether the section is one opaque block throughout and so are the sections
+/// around it, in which case none of its faces can be seen. Saves walking through
+/// the solid underground sections block by block.
+fn section_is_hidden(snapshot: &ChunkSnapshot, registry: &BlockRegistry, index: usize) -> bool {
+    let pos = snapshot.pos;
+    let is_opaque = |pos: ChunkPos, index: usize| match snapshot.chunk(pos) {
+        //past the edges of the world nothing gets drawn anyway
+        None => true,
+        Some(blocks) => blocks
+            .section(index)
+            .uniform()
+            .is_some_and(|block| !registry.is_transparent(block)),
+    };
+    let sections = snapshot.blocks.height().sections;
+    is_opaque(pos, index)
+        && (index + 1 == sections || is_opaque(pos, index + 1))
+        && (index == 0 || is_opaque(pos, index - 1))
+        && is_opaque(pos.offset(1, 0), index)
+        && is_opaque(pos.offset(-1, 0), index)
+        && is_opaque(pos.offset(0, 1), index)
+        && is_opaque(pos.offset(0, -1), index)
+}
+/// How dark each corner of the quad of a block's face is from the blocks in front
+/// of the face around it: 3 when the two blocks along the corner's edges are both
+/// opaque, since the one diagonally across can't be seen past them anyway,
+/// otherwise one for each of the three that is.
+fn corner_occlusion(
+    quad: &Quad,
+    center: [f32; 3],
+    direction: Face,
+    opaque: impl Fn([i32; 3]) -> bool,
+) -> [u32; 4] {
+    let [_, first, second] = direction.plane();
+    quad.corners.map(|corner| {
+        //the block in front of the face, moved towards the corner along the axes
+        let mut along = [direction.normal(); 2];
+        for (offset, axis) in along.iter_mut().zip([first, second].iter()) {
+            offset[*axis] = if corner.position[*axis] > center[*axis] {
+                1
+            } else {
+                -1
+            };
+        }
+        let mut diagonal = along[0];
+        diagonal[second] = along[1][second];
+        let (side, other_side) = (opaque(along[0]), opaque(along[1]));
+        if side && other_side {
+            3
+        } else {
+            side as u32 + other_side as u32 + opaque(diagonal) as u32
+        }
+    })
+}
+fn push_quad(quad: &Quad, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let base_index = vertices.len() as u32;
+    vertices.extend_from_slice(&quad.pack());
+    //split along the lighter diagonal, otherwise the shading of quads with one dark
+    //corner depends on which way they are turned
+    let occlusion = quad.occlusion;
+    if occlusion[2] + occlusion[3] > occlusion[0] + occlusion[1] {
+        indices.extend([0, 1, 2, 0, 3, 1].map(|i| base_index + i));
+    } else {
+        indices.extend([3, 2, 0, 1, 2, 3].map(|i| base_index + i));
+    }
+}
+/// Two planes from corner to corner of the block, each seen from both sides.
+fn get_cross_faces(
+    model: &BlockModel,
+    pos: [f32; 3],
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+) {
+    //the corners of the front face, bent across the diagonals
+    let corners = [[0.5, -0.5], [-0.5, 0.5], [-0.5, -0.5], [0.5, 0.5]];
+    for (face, diagonal) in [(6, 1.0), (7, -1.0)] {
+        let base_index = vertices.len() as u32;
+        let quad = Quad {
+            corners: [0, 1, 2, 3].map(|i| {
+                let [x, y] = corners[i];
+                Corner {
+                    position: [pos[0] + x, pos[1] + y, pos[2] + x * diagonal],
+                    tex_coords: TILE_CORNERS[i],
+                }
+            }),
+            face,
+            tile: model.textures.side,
+            occlusion: [0; 4],
+        };
+        vertices.extend_from_slice(&quad.pack());
+        indices.extend([3, 2, 0, 1, 2, 3].map(|i| base_index + i));
+        indices.extend([0, 2, 3, 3, 2, 1].map(|i| base_index + i));
+    }
+}
+fn get_mesh_texture_and_pos(face: Face, model: &BlockModel, pos: [f32; 3], covered: bool) -> Quad {
+    let textures = &model.textures;
+    //corners around the block's center, turned with the model before being placed
+    let corners = match face {
+        Face::Top => [
+            [-0.5, 0.5, -0.5],
+            [0.5, 0.5, 0.5],
+            [0.5, 0.5, -0.5],
+            [-0.5, 0.5, 0.5],
+        ],
+        Face::Bottom => [
+            [0.5, -0.5, -0.5],
+            [-0.5, -0.5, 0.5],
+            [-0.5, -0.5, -0.5],
+            [0.5, -0.5, 0.5],
+        ],
+        Face::Left => [
+            [-0.5, -0.5, 0.5],
+            [-0.5, 0.5, -0.5],
+            [-0.5, -0.5, -0.5],
+            [-0.5, 0.5, 0.5],
+        ],
+        Face::Right => [
+            [0.5, -0.5, -0.5],
+            [0.5, 0.5, 0.5],
+            [0.5, -0.5, 0.5],
+            [0.5, 0.5, -0.5],
+        ],
+        Face::Front => [
+            [0.5, -0.5, 0.5],
+            [-0.5, 0.5, 0.5],
+            [-0.5, -0.5, 0.5],
+            [0.5, 0.5, 0.5],
+        ],
+        Face::Back => [
+            [-0.5, -0.5, -0.5],
+            [0.5, 0.5, -0.5],
+            [0.5, -0.5, -0.5],
+            [-0.5, 0.5, -0.5],
+        ],
+    };
+    let index = match face {
+        Face::Left | Face::Right | Face::Back | Face::Front => match textures.covered_side {
+            Some(covered_side) if covered => covered_side,
+            _ => textures.side,
+        },
+        Face::Top => textures.top,
+        Face::Bottom => textures.bottom,
+    };
+
+    Quad {
+        corners: [0, 1, 2, 3].map(|i| {
+            let [x, y, z] = rotate(corners[i], model);
+            Corner {
+                position: [pos[0] + x, pos[1] + y, pos[2] + z],
+                tex_coords: TILE_CORNERS[i],
+            }
+        }),
+        face: face.rotated(model) as u32,
+        tile: index,
+        occlusion: [0; 4],
+    }
+}
+/// Corners of a block's quad in its atlas tile.
+const TILE_CORNERS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 1.0], [0.0, 1.0], [1.0, 0.0]];
+/// Turns a point around the block's center by the model's quarter turns.
+fn rotate(mut point: [f32; 3], model: &BlockModel) -> [f32; 3] {
+    for _ in 0..model.rotate_x {
+        point = [point[0], -point[2], point[1]];
+    }
+    for _ in 0..model.rotate_y {
+        point = [point[2], point[1], -point[0]];
+    }
+    point
+}
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Face {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    Back,
+    Front,
+}
+impl Face {
+    /// In the order of their discriminants, the `Vertex::face` they are stored as.
+    const ALL: [Face; 6] = [
+        Face::Top,
+        Face::Bottom,
+        Face::Left,
+        Face::Right,
+        Face::Back,
+        Face::Front,
+    ];
+    fn normal(&self) -> [i32; 3] {
+        match self {
+            Face::Top => [0, 1, 0],
+            Face::Bottom => [0, -1, 0],
+            Face::Left => [-1, 0, 0],
+            Face::Right => [1, 0, 0],
+            Face::Front => [0, 0, 1],
+            Face::Back => [0, 0, -1],
+        }
+    }
+    /// Axes of the blocks' coordinates: the one along the face's normal, then the
+    /// two of the plane it lies in.
+    fn plane(&self) -> [usize; 3] {
+        match self {
+            Face::Top | Face::Bottom => [1, 0, 2],
+            Face::Left | Face::Right => [0, 2, 1],
+            Face::Front | Face::Back => [2, 0, 1],
+        }
+    }
+    /// The direction this face of the model points once the model is turned.
+    fn rotated(self, model: &BlockModel) -> Face {
+        let normal = rotate(self.normal().map(|n| n as f32), model);
+        match normal.map(|n| n.round() as i32) {
+            [0, 1, 0] => Face::Top,
+            [0, -1, 0] => Face::Bottom,
+            [-1, 0, 0] => Face::Left,
+            [1, 0, 0] => Face::Right,
+            [0, 0, 1] => Face::Front,
+            _ => Face::Back,
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Minecraft's Named Binary Tag format, the big endian Java edition flavor used by
+//! region files and structure files. Strings are read as UTF-8, which covers block
+//! names and everything else we look at.
+
+use std::{
+    convert::TryInto,
+    io::{Read, Write},
+};
+
+use anyhow::{bail, Context};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+/// Files nest a few levels deep, anything past this is corrupt or hostile.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+    /// Value of any integer tag, files aren't always consistent about the width.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(v) => Some(*v as i64),
+            Tag::Short(v) => Some(*v as i64),
+            Tag::Int(v) => Some(*v as i64),
+            Tag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(c) => Some(c),
+            _ => None,
+        }
+    }
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+/// Named tags in the order they were read or inserted, so files are written back
+/// the way they came.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    entries: Vec<(String, Tag)>,
+}
+impl Compound {
+    pub fn new() -> Self {
+        Compound::default()
+    }
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.entries.iter().find(|(n, _)| n == name).map(|(_, t)| t)
+    }
+    /// Adds the tag, replacing one with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, tag: Tag) {
+        let name = name.into();
+        match self.entries.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = tag,
+            None => self.entries.push((name, tag)),
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.entries.iter().map(|(n, t)| (n.as_str(), t))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    fn expect(&self, name: &str) -> anyhow::Result<&Tag> {
+        self.get(name)
+            .with_context(|| format!("missing tag {}", name))
+    }
+    pub fn int(&self, name: &str) -> anyhow::Result<i64> {
+        self.expect(name)?
+            .as_i64()
+            .with_context(|| format!("tag {} isn't a number", name))
+    }
+    pub fn string(&self, name: &str) -> anyhow::Result<&str> {
+        self.expect(name)?
+            .as_str()
+            .with_context(|| format!("tag {} isn't a string", name))
+    }
+    pub fn compound(&self, name: &str) -> anyhow::Result<&Compound> {
+        self.expect(name)?
+            .as_compound()
+            .with_context(|| format!("tag {} isn't a compound", name))
+    }
+    pub fn list(&self, name: &str) -> anyhow::Result<&[Tag]> {
+        match self.expect(name)? {
+            Tag::List(list) => Ok(list),
+            _ => bail!("tag {} isn't a list", name),
+        }
+    }
+    pub fn byte_array(&self, name: &str) -> anyhow::Result<&[i8]> {
+        match self.expect(name)? {
+            Tag::ByteArray(bytes) => Ok(bytes),
+            _ => bail!("tag {} isn't a byte array", name),
+        }
+    }
+    pub fn int_array(&self, name: &str) -> anyhow::Result<&[i32]> {
+        match self.expect(name)? {
+            Tag::IntArray(ints) => Ok(ints),
+            _ => bail!("tag {} isn't an int array", name),
+        }
+    }
+    pub fn long_array(&self, name: &str) -> anyhow::Result<&[i64]> {
+        match self.expect(name)? {
+            Tag::LongArray(longs) => Ok(longs),
+            _ => bail!("tag {} isn't a long array", name),
+        }
+    }
+}
+/// Reads an uncompressed file, returning the root compound and its name.
+pub fn read(data: &[u8]) -> anyhow::Result<(String, Compound)> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.u8()? != COMPOUND {
+        bail!("root tag isn't a compound");
+    }
+    let name = reader.string()?;
+    let root = reader.compound(0)?;
+    Ok((name, root))
+}
+pub fn write(name: &str, root: &Compound) -> Vec<u8> {
+    let mut out = vec![COMPOUND];
+    write_string(&mut out, name);
+    write_compound(&mut out, root);
+    out
+}
+/// Reads a file the way structure files are usually stored, gzipped, also taking
+/// uncompressed ones.
+pub fn read_gzip(data: &[u8]) -> anyhow::Result<(String, Compound)> {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return read(data);
+    }
+    let mut raw = vec![];
+    GzDecoder::new(data)
+        .read_to_end(&mut raw)
+        .context("bad gzip data")?;
+    read(&raw)
+}
+pub fn write_gzip(name: &str, root: &Compound) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    //writing into a Vec can't fail
+    encoder.write_all(&write(name, root)).unwrap();
+    encoder.finish().unwrap()
+}
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + len) else {
+            bail!("nbt data ends early");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn i16(&mut self) -> anyhow::Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+    fn i64(&mut self) -> anyhow::Result<i64> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into()?))
+    }
+    fn length(&mut self) -> anyhow::Result<usize> {
+        let len = self.i32()?;
+        if len < 0 {
+            bail!("negative length {}", len);
+        }
+        Ok(len as usize)
+    }
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.i16()? as u16 as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+    fn compound(&mut self, depth: usize) -> anyhow::Result<Compound> {
+        let mut compound = Compound::new();
+        loop {
+            let id = self.u8()?;
+            if id == END {
+                return Ok(compound);
+            }
+            let name = self.string()?;
+            let tag = self
+                .payload(id, depth + 1)
+                .with_context(|| format!("in tag {}", name))?;
+            compound.entries.push((name, tag));
+        }
+    }
+    fn payload(&mut self, id: u8, depth: usize) -> anyhow::Result<Tag> {
+        if depth > MAX_DEPTH {
+            bail!("tags nested too deep");
+        }
+        Ok(match id {
+            BYTE => Tag::Byte(self.u8()? as i8),
+            SHORT => Tag::Short(self.i16()?),
+            INT => Tag::Int(self.i32()?),
+            LONG => Tag::Long(self.i64()?),
+            FLOAT => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            DOUBLE => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            BYTE_ARRAY => {
+                let len = self.length()?;
+                Tag::ByteArray(self.bytes(len)?.iter().map(|b| *b as i8).collect())
+            }
+            STRING => Tag::String(self.string()?),
+            LIST => {
+                let element = self.u8()?;
+                let len = self.length()?;
+                if element == END && len > 0 {
+                    bail!("list of end tags");
+                }
+                //every element takes at least a byte, which bounds bogus lengths
+                let mut list = Vec::with_capacity(len.min(self.data.len() - self.pos));
+                for _ in 0..len {
+                    list.push(self.payload(element, depth + 1)?);
+                }
+                Tag::List(list)
+            }
+            COMPOUND => Tag::Compound(self.compound(depth)?),
+            INT_ARRAY => {
+                let len = self.length()?;
+                let bytes = self.bytes(len.checked_mul(4).context("array too long")?)?;
+                Tag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| i32::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            LONG_ARRAY => {
+                let len = self.length()?;
+                let bytes = self.bytes(len.checked_mul(8).context("array too long")?)?;
+                Tag::LongArray(
+                    bytes
+                        .chunks_exact(8)
+                        .map(|b| i64::from_be_bytes(b.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            other => bail!("unknown tag type {}", other),
+        })
+    }
+}
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+fn write_compound(out: &mut Vec<u8>, compound: &Compound) {
+    for (name, tag) in &compound.entries {
+        out.push(tag.id());
+        write_string(out, name);
+        write_payload(out, tag);
+    }
+    out.push(END);
+}
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(v) => out.push(*v as u8),
+        Tag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::ByteArray(bytes) => {
+            out.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            out.extend(bytes.iter().map(|b| *b as u8));
+        }
+        Tag::String(s) => write_string(out, s),
+        Tag::List(list) => {
+            //empty lists are written as lists of end tags, like vanilla does
+            out.push(list.first().map_or(END, Tag::id));
+            out.extend_from_slice(&(list.len() as i32).to_be_bytes());
+            for element in list {
+                write_payload(out, element);
+            }
+        }
+        Tag::Compound(compound) => write_compound(out, compound),
+        Tag::IntArray(ints) => {
+            out.extend_from_slice(&(ints.len() as i32).to_be_bytes());
+            for v in ints {
+                out.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        Tag::LongArray(longs) => {
+            out.extend_from_slice(&(longs.len() as i32).to_be_bytes());
+            for v in longs {
+                out.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Ore veins, placed into generated chunks by the rules in `ores.ron`.
+//!
+//! Veins stay inside the chunk they start in, so every chunk gets its ores from the
+//! seed and its position alone, whichever order chunks are generated in.
+
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, CHUNK_WIDTH},
+    coords::{ChunkPos, LocalBlockPos},
+    worldgen::{ChunkRng, WorldGenerator},
+};
+
+/// Salt of the first rule's `ChunkRng`, each rule after it adds one.
+const ORE_SALT: u64 = 16;
+
+/// One entry of `ores.ron`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OreRule {
+    /// Block veins are made of.
+    pub block: String,
+    /// Most blocks in one vein.
+    pub vein_size: u32,
+    /// Veins tried per chunk.
+    pub count: u32,
+    /// Lowest and highest y veins start at.
+    pub height: (i32, i32),
+    /// The only block veins replace.
+    pub host: String,
+}
+/// A rule with its blocks looked up.
+struct Ore {
+    block: Block,
+    host: Block,
+    vein_size: u32,
+    count: u32,
+    height: (i32, i32),
+}
+pub struct Ores {
+    seed: u64,
+    rules: Vec<OreRule>,
+    ores: Vec<Ore>,
+}
+impl Ores {
+    /// Rules the game ships with.
+    pub fn default_rules() -> anyhow::Result<Vec<OreRule>> {
+        Self::rules_from_ron(include_str!("ores.ron")).context("failed to load ores.ron")
+    }
+    pub fn load_rules(path: &Path) -> anyhow::Result<Vec<OreRule>> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::rules_from_ron(&source).with_context(|| format!("failed to load {}", path.display()))
+    }
+    pub fn rules_from_ron(source: &str) -> anyhow::Result<Vec<OreRule>> {
+        Ok(ron::from_str(source)?)
+    }
+    pub fn new(seed: u64, rules: Vec<OreRule>, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let block = |name: &str| -> anyhow::Result<Block> {
+            let block_type = registry
+                .by_name(name)
+                .with_context(|| format!("unknown block {}", name))?;
+            Ok(registry.default_state(block_type))
+        };
+        let mut ores = vec![];
+        for rule in &rules {
+            let resolve = || -> anyhow::Result<Ore> {
+                if !(1..=64).contains(&rule.vein_size) {
+                    bail!("vein_size has to be from 1 to 64");
+                }
+                if rule.count > 256 {
+                    bail!("count can't be above 256");
+                }
+                if rule.height.0 > rule.height.1 {
+                    bail!("height has to be lowest first");
+                }
+                if rule.block == rule.host {
+                    bail!("host can't be the ore itself");
+                }
+                Ok(Ore {
+                    block: block(&rule.block)?,
+                    host: block(&rule.host)?,
+                    vein_size: rule.vein_size,
+                    count: rule.count,
+                    height: rule.height,
+                })
+            };
+            ores.push(resolve().with_context(|| format!("in ore {}", rule.block))?);
+        }
+        Ok(Ores { seed, rules, ores })
+    }
+    pub fn rules(&self) -> &[OreRule] {
+        &self.rules
+    }
+    /// Replaces host blocks of the chunk with veins of every rule, in order.
+    pub fn place(&self, pos: ChunkPos, blocks: &mut ChunkBlocks) {
+        let height = blocks.height();
+        for (index, ore) in self.ores.iter().enumerate() {
+            let mut rng = ChunkRng::new(self.seed, ORE_SALT + index as u64, pos);
+            for _ in 0..ore.count {
+                let mut at = [
+                    rng.range(0, CHUNK_WIDTH as i32 - 1),
+                    rng.range(ore.height.0, ore.height.1),
+                    rng.range(0, CHUNK_WIDTH as i32 - 1),
+                ];
+                //a random walk, which may cross itself, so veins vary in size
+                for _ in 0..ore.vein_size {
+                    let inside = (0..CHUNK_WIDTH as i32).contains(&at[0])
+                        && (0..CHUNK_WIDTH as i32).contains(&at[2])
+                        && (height.min_y..height.max_y()).contains(&at[1]);
+                    if inside {
+                        let local = LocalBlockPos::new(at[0] as usize, at[1], at[2] as usize);
+                        if blocks.get(local) == ore.host {
+                            blocks.set(local, ore.block);
+                        }
+                    }
+                    let step = rng.next_u64();
+                    at[(step % 3) as usize] += if step & 4 == 0 { 1 } else { -1 };
+                }
+            }
+        }
+    }
+    /// How many blocks of each ore the chunk has, in the order of the rules, every
+    /// block once.
+    pub fn count(&self, blocks: &ChunkBlocks) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(Block, &str, usize)> = vec![];
+        for (ore, rule) in self.ores.iter().zip(&self.rules) {
+            if counts.iter().all(|(block, ..)| *block != ore.block) {
+                counts.push((ore.block, &rule.block, 0));
+            }
+        }
+        let height = blocks.height();
+        for y in height.min_y..height.max_y() {
+            for z in 0..CHUNK_WIDTH {
+                for x in 0..CHUNK_WIDTH {
+                    let block = blocks.get(LocalBlockPos::new(x, y, z));
+                    if let Some(count) = counts.iter_mut().find(|(ore, ..)| *ore == block) {
+                        count.2 += 1;
+                    }
+                }
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(_, name, count)| (name, count))
+            .collect()
+    }
+    /// A table of `count` for every chunk, as `generator` makes them, and the
+    /// average per chunk.
+    pub fn report(
+        &self,
+        generator: &dyn WorldGenerator,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+    ) -> String {
+        let mut names = vec![];
+        let mut rows = vec![];
+        for pos in chunks {
+            let counts = self.count(&generator.generate(pos));
+            names = counts.iter().map(|(name, _)| *name).collect();
+            let label = format!("{} {}", pos.x, pos.z);
+            rows.push((
+                label,
+                counts.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+            ));
+        }
+        let mut report = format!("{:<12}", "chunk");
+        for name in &names {
+            report += &format!("{:>14}", name);
+        }
+        report += "\n";
+        let mut totals = vec![0; names.len()];
+        for (label, counts) in &rows {
+            report += &format!("{:<12}", label);
+            for (total, count) in totals.iter_mut().zip(counts) {
+                report += &format!("{:>14}", count);
+                *total += count;
+            }
+            report += "\n";
+        }
+        report += &format!("{:<12}", "average");
+        for total in totals {
+            report += &format!("{:>14.1}", total as f64 / rows.len().max(1) as f64);
+        }
+        report + "\n"
+    }
+}
@@ -0,0 +1,355 @@
+//! Saved worlds, stored as region files of 32x32 chunks each.
+//!
+//! A region file starts with a header: the magic `WCRG`, the format version as a
+//! little endian `u16`, two reserved bytes, then an offset table of 1024 entries,
+//! one per chunk in x + z * 32 order. Each entry is the chunk's first 4 KiB sector
+//! and its length in bytes as little endian `u32`s, both 0 for chunks that were
+//! never saved. A chunk record is a compression byte (0 none, 1 zlib) followed by
+//! the chunk data:
+//!
+//! - `u32` data version, see [`DATA_VERSION`]
+//! - `i32` y of the lowest section, `u16` number of sections
+//! - for each section: `u16` palette length, each palette entry as a `u16` length
+//!   and the block state name (`oak_log[axis=x]`), `u8` bits per block, `u32` long
+//!   count and the packed palette indices as `u64`s
+//! - the biome id of each column as a `u8`, in x + z * 16 order (since version 2)
+//!
+//! Blocks are saved by name so the ids in `blocks.ron` can change between versions.
+
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    biome::BiomeId,
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, PalettedContainer, Section, WorldHeight, CHUNK_WIDTH, SECTION_VOLUME},
+    coords::ChunkPos,
+};
+
+pub const REGION_SIZE: i32 = 32;
+const MAGIC: &[u8; 4] = b"WCRG";
+/// Version of the region file layout, older ones are still read.
+pub const FORMAT_VERSION: u16 = 1;
+/// Version of the chunk data layout and block state names. Bump it when either
+/// changes and convert older chunks in `read_chunk`.
+pub const DATA_VERSION: u32 = 2;
+const SECTOR_SIZE: u64 = 4096;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const HEADER_SIZE: u64 = 8 + CHUNKS_PER_REGION as u64 * 8;
+const HEADER_SECTORS: u32 = HEADER_SIZE.div_ceil(SECTOR_SIZE) as u32;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZLIB: u8 = 1;
+
+/// Region files of one world in a directory, opened as chunks are loaded and saved.
+pub struct RegionStorage {
+    dir: PathBuf,
+    regions: HashMap<(i32, i32), RegionFile>,
+}
+impl RegionStorage {
+    pub fn open(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("couldn't create save directory {}", dir.display()))?;
+        Ok(RegionStorage {
+            dir,
+            regions: HashMap::new(),
+        })
+    }
+    /// The saved chunk, or `None` if it was never saved.
+    pub fn load_chunk(
+        &mut self,
+        pos: ChunkPos,
+        registry: &BlockRegistry,
+        height: WorldHeight,
+    ) -> anyhow::Result<Option<ChunkBlocks>> {
+        let Some(region) = self.region(pos, false)? else {
+            return Ok(None);
+        };
+        match region.read(chunk_index(pos))? {
+            Some(data) => read_chunk(&data, registry, height)
+                .with_context(|| format!("chunk {:?} is corrupt", pos))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+    pub fn save_chunk(
+        &mut self,
+        pos: ChunkPos,
+        blocks: &ChunkBlocks,
+        registry: &BlockRegistry,
+    ) -> anyhow::Result<()> {
+        let data = write_chunk(blocks, registry);
+        let region = self.region(pos, true)?.unwrap();
+        region.write(chunk_index(pos), &data)
+    }
+    /// Every chunk saved in the directory, in no particular order.
+    pub fn saved_chunks(&mut self) -> anyhow::Result<Vec<ChunkPos>> {
+        let mut chunks = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let Some((x, z)) = name.to_str().and_then(region_key) else {
+                continue;
+            };
+            let origin = ChunkPos::new(x * REGION_SIZE, z * REGION_SIZE);
+            let region = self.region(origin, false)?.unwrap();
+            for (index, (_, length)) in region.offsets.iter().enumerate() {
+                if *length != 0 {
+                    let index = index as i32;
+                    chunks.push(origin.offset(index % REGION_SIZE, index / REGION_SIZE));
+                }
+            }
+        }
+        Ok(chunks)
+    }
+    fn region(&mut self, pos: ChunkPos, create: bool) -> anyhow::Result<Option<&mut RegionFile>> {
+        let key = (pos.x.div_euclid(REGION_SIZE), pos.z.div_euclid(REGION_SIZE));
+        if !self.regions.contains_key(&key) {
+            let path = self.dir.join(format!("r.{}.{}.wcr", key.0, key.1));
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            let region = RegionFile::open(&path)
+                .with_context(|| format!("couldn't open region file {}", path.display()))?;
+            self.regions.insert(key, region);
+        }
+        Ok(self.regions.get_mut(&key))
+    }
+}
+/// Region coordinates of a region file name like `r.-1.0.wcr`.
+fn region_key(file_name: &str) -> Option<(i32, i32)> {
+    let (x, z) = file_name
+        .strip_prefix("r.")?
+        .strip_suffix(".wcr")?
+        .split_once('.')?;
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+fn chunk_index(pos: ChunkPos) -> usize {
+    (pos.x.rem_euclid(REGION_SIZE) + pos.z.rem_euclid(REGION_SIZE) * REGION_SIZE) as usize
+}
+struct RegionFile {
+    file: File,
+    /// First sector and length in bytes of each chunk's record.
+    offsets: Vec<(u32, u32)>,
+}
+impl RegionFile {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut offsets = vec![(0, 0); CHUNKS_PER_REGION];
+        if file.metadata()?.len() == 0 {
+            let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+            header.extend_from_slice(MAGIC);
+            header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+            header.extend_from_slice(&[0, 0]);
+            header.resize(HEADER_SIZE as usize, 0);
+            file.write_all(&header)?;
+        } else {
+            let mut header = vec![0; HEADER_SIZE as usize];
+            file.read_exact(&mut header)?;
+            if &header[0..4] != MAGIC {
+                bail!("not a region file");
+            }
+            let version = u16::from_le_bytes([header[4], header[5]]);
+            if version > FORMAT_VERSION {
+                bail!("region format {} is newer than this game", version);
+            }
+            for (i, entry) in header[8..].chunks_exact(8).enumerate() {
+                offsets[i] = (
+                    u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                    u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                );
+            }
+        }
+        Ok(RegionFile { file, offsets })
+    }
+    /// Decompressed data of the chunk's record.
+    fn read(&mut self, index: usize) -> anyhow::Result<Option<Vec<u8>>> {
+        let (sector, length) = self.offsets[index];
+        if length == 0 {
+            return Ok(None);
+        }
+        //a corrupt header shouldn't make us allocate or read past the file
+        let end = sector as u64 * SECTOR_SIZE + length as u64;
+        if sector < HEADER_SECTORS || end > self.file.metadata()?.len() {
+            bail!(
+                "chunk record of {} bytes at sector {} doesn't fit in the file",
+                length,
+                sector
+            );
+        }
+        let mut record = vec![0; length as usize];
+        self.file
+            .seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        self.file.read_exact(&mut record)?;
+        let data = match record[0] {
+            COMPRESSION_NONE => record[1..].to_vec(),
+            COMPRESSION_ZLIB => {
+                let mut data = vec![];
+                ZlibDecoder::new(&record[1..]).read_to_end(&mut data)?;
+                data
+            }
+            other => bail!("unknown compression {}", other),
+        };
+        Ok(Some(data))
+    }
+    /// Compresses the data and writes it into the first free run of sectors big
+    /// enough for it, which may be where the chunk was saved before.
+    fn write(&mut self, index: usize, data: &[u8]) -> anyhow::Result<()> {
+        let mut encoder = ZlibEncoder::new(vec![COMPRESSION_ZLIB], Compression::default());
+        encoder.write_all(data)?;
+        let record = encoder.finish()?;
+        let needed = (record.len() as u64).div_ceil(SECTOR_SIZE) as u32;
+
+        let mut used: Vec<(u32, u32)> = self
+            .offsets
+            .iter()
+            .enumerate()
+            .filter(|(i, (_, length))| *i != index && *length > 0)
+            .map(|(_, (sector, length))| {
+                (
+                    *sector,
+                    *sector + (*length as u64).div_ceil(SECTOR_SIZE) as u32,
+                )
+            })
+            .collect();
+        used.sort_unstable();
+        let mut sector = HEADER_SECTORS;
+        for (start, end) in used {
+            if start >= sector + needed {
+                break;
+            }
+            sector = sector.max(end);
+        }
+
+        self.file
+            .seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        self.file.write_all(&record)?;
+        //pad to whole sectors so the next chunk appended starts on a boundary
+        let padding = (needed as u64 * SECTOR_SIZE) as usize - record.len();
+        self.file.write_all(&vec![0; padding])?;
+
+        self.offsets[index] = (sector, record.len() as u32);
+        let mut entry = sector.to_le_bytes().to_vec();
+        entry.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        self.file.seek(SeekFrom::Start(8 + index as u64 * 8))?;
+        self.file.write_all(&entry)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+fn write_chunk(blocks: &ChunkBlocks, registry: &BlockRegistry) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&DATA_VERSION.to_le_bytes());
+    out.extend_from_slice(&blocks.height().min_y.to_le_bytes());
+    out.extend_from_slice(&(blocks.sections().len() as u16).to_le_bytes());
+    for section in blocks.sections() {
+        let container = section.blocks();
+        out.extend_from_slice(&(container.palette().len() as u16).to_le_bytes());
+        for block in container.palette() {
+            let name = registry.state_name(*block);
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+        }
+        out.push(container.bits_per_entry() as u8);
+        out.extend_from_slice(&(container.packed().len() as u32).to_le_bytes());
+        for long in container.packed() {
+            out.extend_from_slice(&long.to_le_bytes());
+        }
+    }
+    for z in 0..CHUNK_WIDTH {
+        for x in 0..CHUNK_WIDTH {
+            out.push(blocks.biome(x, z).0);
+        }
+    }
+    out
+}
+/// Reads a chunk saved with `write_chunk`. Sections are placed by their y so worlds
+/// saved with another height keep what still fits, and blocks that are no longer
+/// registered turn into air.
+fn read_chunk(
+    data: &[u8],
+    registry: &BlockRegistry,
+    height: WorldHeight,
+) -> anyhow::Result<ChunkBlocks> {
+    let mut reader = ByteReader { data, pos: 0 };
+    let version = reader.u32()?;
+    if version > DATA_VERSION {
+        bail!("chunk data version {} is newer than this game", version);
+    }
+    let min_y = reader.u32()? as i32;
+    let sections = reader.u16()? as usize;
+    let saved_height = WorldHeight { min_y, sections };
+
+    let mut blocks = ChunkBlocks::new(height);
+    for index in 0..sections {
+        let palette_len = reader.u16()? as usize;
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            let len = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.bytes(len)?)?;
+            palette.push(registry.parse_state(name).unwrap_or_else(|| {
+                log::warn!("unknown block {} in saved chunk, replaced with air", name);
+                Block::AIR
+            }));
+        }
+        let bits = reader.u8()? as u32;
+        let longs = reader.u32()? as usize;
+        let mut packed = Vec::with_capacity(longs);
+        for _ in 0..longs {
+            packed.push(reader.u64()?);
+        }
+        let container = PalettedContainer::from_parts(SECTION_VOLUME, palette, bits, packed)
+            .context("bad section block data")?;
+        let y = saved_height.section_min_y(index);
+        if height.contains(y) {
+            *blocks.section_mut(height.section_index(y)) =
+                Section::from_blocks(container).context("bad section size")?;
+        }
+    }
+    //older chunks are left with the default biome
+    if version >= 2 {
+        for z in 0..CHUNK_WIDTH {
+            for x in 0..CHUNK_WIDTH {
+                blocks.set_biome(x, z, BiomeId(reader.u8()?));
+            }
+        }
+    }
+    Ok(blocks)
+}
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> ByteReader<'a> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + len) else {
+            bail!("chunk data ends early");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into()?))
+    }
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+}
@@ -0,0 +1,624 @@
+//! Builds shared as files, read into a `BlockVolume` that can be pasted into the
+//! world: Sponge schematics (`.schem`, versions 1 to 3), Litematica files
+//! (`.litematic`) and vanilla structure files (`.nbt`). All of them are gzipped NBT
+//! naming blocks the vanilla way, mapped onto the registry with a `BlockMapping`.
+
+use std::{collections::HashSet, convert::TryFrom, fs, path::Path};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    anvil::{self, BlockMapping},
+    block::{Block, BlockRegistry},
+    nbt::{self, Compound, Tag},
+};
+
+/// Volumes bigger than this are refused instead of allocated, 256 blocks cubed.
+const MAX_VOLUME: usize = 1 << 24;
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+
+/// A box of blocks, `None` where pasting leaves the world's block alone (structure
+/// void). Indexed by `x`, `y`, `z` with `x` varying fastest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockVolume {
+    width: usize,
+    height: usize,
+    length: usize,
+    blocks: Vec<Option<Block>>,
+}
+impl BlockVolume {
+    /// Volume of `width` (x) by `height` (y) by `length` (z) blocks that leaves
+    /// everything alone.
+    pub fn new(width: usize, height: usize, length: usize) -> Self {
+        BlockVolume {
+            width,
+            height,
+            length,
+            blocks: vec![None; width * height * length],
+        }
+    }
+    /// Like `new` for sizes read from a file, refusing ones too big to allocate.
+    fn with_size(width: usize, height: usize, length: usize) -> anyhow::Result<Self> {
+        match width
+            .checked_mul(height)
+            .and_then(|v| v.checked_mul(length))
+        {
+            Some(volume) if volume <= MAX_VOLUME => Ok(Self::new(width, height, length)),
+            _ => bail!("{}x{}x{} is too big", width, height, length),
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn length(&self) -> usize {
+        self.length
+    }
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height && z < self.length,
+            "{} {} {} is outside the volume",
+            x,
+            y,
+            z
+        );
+        (y * self.length + z) * self.width + x
+    }
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<Block> {
+        self.blocks[self.index(x, y, z)]
+    }
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Option<Block>) {
+        let index = self.index(x, y, z);
+        self.blocks[index] = block;
+    }
+    /// The volume turned around the y axis, its blocks turned with it.
+    pub fn rotated(&self, registry: &BlockRegistry, rotation: Rotation) -> BlockVolume {
+        let turns = rotation.quarter_turns();
+        let (width, length) = if turns % 2 == 1 {
+            (self.length, self.width)
+        } else {
+            (self.width, self.length)
+        };
+        let mut rotated = BlockVolume::new(width, self.height, length);
+        for y in 0..self.height {
+            for z in 0..self.length {
+                for x in 0..self.width {
+                    let (to_x, to_z) = match turns {
+                        1 => (self.length - 1 - z, x),
+                        2 => (self.width - 1 - x, self.length - 1 - z),
+                        3 => (z, self.width - 1 - x),
+                        _ => (x, z),
+                    };
+                    let block = self.get(x, y, z).map(|b| registry.rotated(b, turns));
+                    rotated.set(to_x, y, to_z, block);
+                }
+            }
+        }
+        rotated
+    }
+}
+/// Turn around the y axis, clockwise seen from above like Minecraft's.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    CounterClockwise90,
+}
+impl Rotation {
+    pub fn quarter_turns(self) -> u8 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::CounterClockwise90 => 3,
+        }
+    }
+}
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Sponge schematic, written as version 2 which every tool reads.
+    Sponge,
+    Litematica,
+    /// Vanilla structure block file.
+    Structure,
+}
+impl Format {
+    /// Format of a file by its extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "schem" => Some(Format::Sponge),
+            "litematic" => Some(Format::Litematica),
+            "nbt" => Some(Format::Structure),
+            _ => None,
+        }
+    }
+}
+/// Reads a file in the format its extension says.
+pub fn load(
+    path: &Path,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<BlockVolume> {
+    let format = Format::from_path(path)
+        .with_context(|| format!("{} isn't a structure file", path.display()))?;
+    let data = fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let volume = match format {
+        Format::Sponge => read_schem(&data, registry, mapping),
+        Format::Litematica => read_litematic(&data, registry, mapping),
+        Format::Structure => read_structure(&data, registry, mapping),
+    };
+    volume.with_context(|| format!("couldn't load {}", path.display()))
+}
+/// Writes a file in the format its extension says, Litematica files named after it.
+pub fn save(
+    path: &Path,
+    volume: &BlockVolume,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<()> {
+    let format = Format::from_path(path)
+        .with_context(|| format!("{} isn't a structure file", path.display()))?;
+    let data = match format {
+        Format::Sponge => write_schem(volume, registry, mapping)?,
+        Format::Litematica => {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            write_litematic(volume, &name, registry, mapping)?
+        }
+        Format::Structure => write_structure(volume, registry, mapping)?,
+    };
+    fs::write(path, data).with_context(|| format!("couldn't write {}", path.display()))
+}
+pub fn read_schem(
+    data: &[u8],
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<BlockVolume> {
+    let (_, root) = nbt::read_gzip(data)?;
+    //version 3 nests everything one level deeper
+    let schematic = root
+        .get("Schematic")
+        .and_then(Tag::as_compound)
+        .unwrap_or(&root);
+    let version = schematic.int("Version")?;
+    let (palette, block_data) = match version {
+        1 | 2 => (
+            schematic.compound("Palette")?,
+            schematic.byte_array("BlockData")?,
+        ),
+        3 => {
+            let blocks = schematic.compound("Blocks")?;
+            (blocks.compound("Palette")?, blocks.byte_array("Data")?)
+        }
+        other => bail!("unsupported schematic version {}", other),
+    };
+    //sizes are unsigned shorts
+    let mut volume = BlockVolume::with_size(
+        schematic.int("Width")? as u16 as usize,
+        schematic.int("Height")? as u16 as usize,
+        schematic.int("Length")? as u16 as usize,
+    )?;
+
+    let mut unknown = HashSet::new();
+    let mut states = vec![];
+    for (name, id) in palette.iter() {
+        let id = id.as_i64().context("palette id isn't a number")?;
+        let id = usize::try_from(id)
+            .ok()
+            .filter(|id| *id <= u16::MAX as usize)
+            .with_context(|| format!("bad palette id {}", id))?;
+        if states.len() <= id {
+            states.resize(id + 1, None);
+        }
+        states[id] = Some(import_name(name, registry, mapping, &mut unknown));
+    }
+    anvil::log_unknown("schematic", unknown);
+
+    //palette ids as varints, in the same order as the volume
+    let mut bytes = block_data.iter().map(|b| *b as u8);
+    for index in 0..volume.blocks.len() {
+        let mut id = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = bytes.next().context("block data ends early")?;
+            id |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 28 {
+                bail!("block data varint too long");
+            }
+        }
+        let Some(Some(block)) = states.get(id) else {
+            bail!(
+                "block data uses palette id {} that isn't in the palette",
+                id
+            );
+        };
+        volume.blocks[index] = *block;
+    }
+    Ok(volume)
+}
+/// Sponge schematic version 2, blocks left alone written as air.
+pub fn write_schem(
+    volume: &BlockVolume,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<Vec<u8>> {
+    let size = |v: usize| -> anyhow::Result<Tag> {
+        let v = u16::try_from(v).context("schematics are at most 65535 blocks wide")?;
+        Ok(Tag::Short(v as i16))
+    };
+    //one palette entry for air, whether it was set or left alone
+    let (palette, indices) = palette_of(volume.blocks.iter().map(|b| b.unwrap_or(Block::AIR)));
+    let mut palette_tag = Compound::new();
+    for (id, block) in palette.iter().enumerate() {
+        palette_tag.insert(mapping.export_name(registry, *block), Tag::Int(id as i32));
+    }
+    let mut block_data = vec![];
+    for mut id in indices {
+        while id >= 0x80 {
+            block_data.push((id as u8 & 0x7f | 0x80) as i8);
+            id >>= 7;
+        }
+        block_data.push(id as i8);
+    }
+
+    let mut root = Compound::new();
+    root.insert("Version", Tag::Int(2));
+    root.insert("DataVersion", Tag::Int(anvil::DATA_VERSION));
+    root.insert("Width", size(volume.width)?);
+    root.insert("Height", size(volume.height)?);
+    root.insert("Length", size(volume.length)?);
+    root.insert("Offset", Tag::IntArray(vec![0, 0, 0]));
+    root.insert("PaletteMax", Tag::Int(palette.len() as i32));
+    root.insert("Palette", Tag::Compound(palette_tag));
+    root.insert("BlockData", Tag::ByteArray(block_data));
+    root.insert("BlockEntities", Tag::List(vec![]));
+    Ok(nbt::write_gzip("Schematic", &root))
+}
+/// Every region of the file in one volume, in the places they have relative to
+/// each other. Blocks between regions are left alone.
+pub fn read_litematic(
+    data: &[u8],
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<BlockVolume> {
+    let (_, root) = nbt::read_gzip(data)?;
+    let mut regions = vec![];
+    for (name, region) in root.compound("Regions")?.iter() {
+        let region = region
+            .as_compound()
+            .with_context(|| format!("region {} isn't a compound", name))?;
+        let position = xyz(region.compound("Position")?)?;
+        let size = xyz(region.compound("Size")?)?;
+        //a negative size extends from the position towards negative coordinates
+        let mut min = [0; 3];
+        let mut extent = [0; 3];
+        for axis in 0..3 {
+            min[axis] = if size[axis] < 0 {
+                position[axis] + size[axis] + 1
+            } else {
+                position[axis]
+            };
+            extent[axis] = size[axis].unsigned_abs() as usize;
+        }
+        regions.push((name, region, min, extent));
+    }
+    if regions.is_empty() {
+        bail!("no regions");
+    }
+    let mut min = [i64::MAX; 3];
+    let mut max = [i64::MIN; 3];
+    for (_, _, region_min, extent) in &regions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(region_min[axis]);
+            max[axis] = max[axis].max(region_min[axis] + extent[axis] as i64);
+        }
+    }
+    let size = |axis: usize| usize::try_from(max[axis] - min[axis]).unwrap_or(usize::MAX);
+    let mut volume = BlockVolume::with_size(size(0), size(1), size(2))?;
+
+    let mut unknown = HashSet::new();
+    for (name, region, region_min, [width, height, length]) in regions {
+        let palette = region
+            .list("BlockStatePalette")?
+            .iter()
+            .map(|entry| import_entry(entry, registry, mapping, &mut unknown))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("palette of region {}", name))?;
+        let longs = region.long_array("BlockStates")?;
+        //indices are packed tightly, spanning longs
+        let bits = litematica_bits(palette.len());
+        let count = width * height * length;
+        if longs.len() * 64 < count * bits {
+            bail!("block states of region {} end early", name);
+        }
+        let offset = [0, 1, 2].map(|axis| (region_min[axis] - min[axis]) as usize);
+        for y in 0..height {
+            for z in 0..length {
+                for x in 0..width {
+                    let index = (y * length + z) * width + x;
+                    let id = read_bits(longs, index * bits, bits);
+                    let Some(block) = palette.get(id) else {
+                        bail!(
+                            "region {} uses palette id {} that isn't in the palette",
+                            name,
+                            id
+                        );
+                    };
+                    volume.set(offset[0] + x, offset[1] + y, offset[2] + z, *block);
+                }
+            }
+        }
+    }
+    anvil::log_unknown("litematic", unknown);
+    Ok(volume)
+}
+/// Litematica file with a single region named `name`, blocks left alone written as air.
+pub fn write_litematic(
+    volume: &BlockVolume,
+    name: &str,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<Vec<u8>> {
+    let [width, height, length] =
+        [volume.width, volume.height, volume.length].map(|v| i32::try_from(v).unwrap_or(i32::MAX));
+    let size_tag = || {
+        let mut size = Compound::new();
+        size.insert("x", Tag::Int(width));
+        size.insert("y", Tag::Int(height));
+        size.insert("z", Tag::Int(length));
+        Tag::Compound(size)
+    };
+    //Litematica expects air first in the palette
+    let blocks: Vec<Block> = volume
+        .blocks
+        .iter()
+        .map(|b| b.unwrap_or(Block::AIR))
+        .collect();
+    let mut palette = vec![Block::AIR];
+    let indices: Vec<usize> = blocks
+        .iter()
+        .map(|block| match palette.iter().position(|b| b == block) {
+            Some(id) => id,
+            None => {
+                palette.push(*block);
+                palette.len() - 1
+            }
+        })
+        .collect();
+    let bits = litematica_bits(palette.len());
+    let mut longs = vec![0i64; (indices.len() * bits).div_ceil(64)];
+    for (i, id) in indices.into_iter().enumerate() {
+        write_bits(&mut longs, i * bits, bits, id);
+    }
+
+    let mut region = Compound::new();
+    let mut position = Compound::new();
+    for axis in ["x", "y", "z"] {
+        position.insert(axis, Tag::Int(0));
+    }
+    region.insert("Position", Tag::Compound(position));
+    region.insert("Size", size_tag());
+    region.insert(
+        "BlockStatePalette",
+        Tag::List(
+            palette
+                .iter()
+                .map(|b| mapping.export_entry(registry, *b))
+                .collect(),
+        ),
+    );
+    region.insert("BlockStates", Tag::LongArray(longs));
+    for list in [
+        "Entities",
+        "TileEntities",
+        "PendingBlockTicks",
+        "PendingFluidTicks",
+    ] {
+        region.insert(list, Tag::List(vec![]));
+    }
+    let mut regions = Compound::new();
+    regions.insert(name, Tag::Compound(region));
+
+    let mut metadata = Compound::new();
+    metadata.insert("Name", Tag::String(name.to_string()));
+    metadata.insert("Author", Tag::String(String::new()));
+    metadata.insert("Description", Tag::String(String::new()));
+    metadata.insert("RegionCount", Tag::Int(1));
+    metadata.insert("TotalVolume", Tag::Int(width * height * length));
+    metadata.insert(
+        "TotalBlocks",
+        Tag::Int(blocks.iter().filter(|b| !b.is_air()).count() as i32),
+    );
+    metadata.insert("TimeCreated", Tag::Long(0));
+    metadata.insert("TimeModified", Tag::Long(0));
+    metadata.insert("EnclosingSize", size_tag());
+
+    let mut root = Compound::new();
+    root.insert("Version", Tag::Int(6));
+    root.insert("SubVersion", Tag::Int(1));
+    root.insert("MinecraftDataVersion", Tag::Int(anvil::DATA_VERSION));
+    root.insert("Metadata", Tag::Compound(metadata));
+    root.insert("Regions", Tag::Compound(regions));
+    Ok(nbt::write_gzip("", &root))
+}
+/// Blocks not listed in the file are structure void, left alone when pasting.
+pub fn read_structure(
+    data: &[u8],
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<BlockVolume> {
+    let (_, root) = nbt::read_gzip(data)?;
+    let size = int_list(root.list("size")?)?;
+    let mut volume = BlockVolume::with_size(size[0], size[1], size[2])?;
+
+    //structures with random variants, like shipwrecks, have several palettes
+    let palette = match root.get("palette") {
+        Some(_) => root.list("palette")?,
+        None => root
+            .list("palettes")?
+            .first()
+            .and_then(Tag::as_list)
+            .context("palettes is empty")?,
+    };
+    let mut unknown = HashSet::new();
+    let palette = palette
+        .iter()
+        .map(|entry| import_entry(entry, registry, mapping, &mut unknown))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    anvil::log_unknown("structure", unknown);
+
+    for block in root.list("blocks")? {
+        let block = block.as_compound().context("block isn't a compound")?;
+        let [x, y, z] = int_list(block.list("pos")?)?;
+        if x >= volume.width || y >= volume.height || z >= volume.length {
+            bail!("block at {} {} {} is outside the structure", x, y, z);
+        }
+        let state = block.int("state")?;
+        let Some(state) = usize::try_from(state).ok().and_then(|s| palette.get(s)) else {
+            bail!("block uses state {} that isn't in the palette", state);
+        };
+        volume.set(x, y, z, *state);
+    }
+    Ok(volume)
+}
+/// Vanilla structure file, blocks left alone written as structure void.
+pub fn write_structure(
+    volume: &BlockVolume,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+) -> anyhow::Result<Vec<u8>> {
+    let (palette, indices) = palette_of(volume.blocks.iter().copied());
+    let mut blocks = vec![];
+    for (index, id) in indices.into_iter().enumerate() {
+        if palette[id].is_none() {
+            continue;
+        }
+        let x = index % volume.width;
+        let z = index / volume.width % volume.length;
+        let y = index / volume.width / volume.length;
+        let mut block = Compound::new();
+        block.insert(
+            "pos",
+            Tag::List(vec![
+                Tag::Int(x as i32),
+                Tag::Int(y as i32),
+                Tag::Int(z as i32),
+            ]),
+        );
+        block.insert("state", Tag::Int(id as i32));
+        blocks.push(Tag::Compound(block));
+    }
+    let palette = palette
+        .iter()
+        .map(|block| match block {
+            Some(block) => mapping.export_entry(registry, *block),
+            //never referenced, structure void is left out of the blocks
+            None => {
+                let mut entry = Compound::new();
+                entry.insert("Name", Tag::String(STRUCTURE_VOID.to_string()));
+                Tag::Compound(entry)
+            }
+        })
+        .collect();
+
+    let mut root = Compound::new();
+    root.insert("DataVersion", Tag::Int(anvil::DATA_VERSION));
+    let size = [volume.width, volume.height, volume.length]
+        .iter()
+        .map(|v| i32::try_from(*v).map(Tag::Int))
+        .collect::<Result<Vec<_>, _>>()
+        .context("structure too big")?;
+    root.insert("size", Tag::List(size));
+    root.insert("palette", Tag::List(palette));
+    root.insert("blocks", Tag::List(blocks));
+    root.insert("entities", Tag::List(vec![]));
+    Ok(nbt::write_gzip("", &root))
+}
+/// Distinct blocks and the index of each block into them.
+fn palette_of<T: Copy + PartialEq>(blocks: impl IntoIterator<Item = T>) -> (Vec<T>, Vec<usize>) {
+    let mut palette = vec![];
+    let indices = blocks
+        .into_iter()
+        .map(|block| match palette.iter().position(|b| *b == block) {
+            Some(id) => id,
+            None => {
+                palette.push(block);
+                palette.len() - 1
+            }
+        })
+        .collect();
+    (palette, indices)
+}
+/// Our state for a vanilla block state string, `None` for structure void.
+fn import_name(
+    name: &str,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+    unknown: &mut HashSet<String>,
+) -> Option<Block> {
+    if name == STRUCTURE_VOID {
+        return None;
+    }
+    Some(mapping.import_name(registry, name).unwrap_or_else(|| {
+        unknown.insert(name.to_string());
+        mapping.fallback
+    }))
+}
+/// Our state for a `Name` and `Properties` palette entry, `None` for structure void.
+fn import_entry(
+    entry: &Tag,
+    registry: &BlockRegistry,
+    mapping: &BlockMapping,
+    unknown: &mut HashSet<String>,
+) -> anyhow::Result<Option<Block>> {
+    let is_void = entry
+        .as_compound()
+        .and_then(|e| e.get("Name"))
+        .and_then(Tag::as_str)
+        == Some(STRUCTURE_VOID);
+    if is_void {
+        return Ok(None);
+    }
+    mapping.import_entry(registry, entry, unknown).map(Some)
+}
+fn xyz(compound: &Compound) -> anyhow::Result<[i64; 3]> {
+    Ok([compound.int("x")?, compound.int("y")?, compound.int("z")?])
+}
+/// Three non-negative ints, like a structure's size or a block's position.
+fn int_list(list: &[Tag]) -> anyhow::Result<[usize; 3]> {
+    let values = list
+        .iter()
+        .map(|v| v.as_i64().and_then(|v| usize::try_from(v).ok()))
+        .collect::<Option<Vec<usize>>>();
+    match values.as_deref() {
+        Some(&[x, y, z]) => Ok([x, y, z]),
+        _ => bail!("expected three non-negative numbers"),
+    }
+}
+/// Bits per block Litematica packs a palette of this size with.
+fn litematica_bits(palette_len: usize) -> usize {
+    let bits = (usize::BITS - palette_len.saturating_sub(1).leading_zeros()) as usize;
+    bits.max(2)
+}
+fn read_bits(longs: &[i64], start: usize, bits: usize) -> usize {
+    let (long, shift) = (start / 64, start % 64);
+    let mut value = (longs[long] as u64) >> shift;
+    if shift + bits > 64 {
+        value |= (longs[long + 1] as u64) << (64 - shift);
+    }
+    (value & ((1u64 << bits) - 1)) as usize
+}
+fn write_bits(longs: &mut [i64], start: usize, bits: usize, value: usize) {
+    let (long, shift) = (start / 64, start % 64);
+    longs[long] |= ((value as u64) << shift) as i64;
+    if shift + bits > 64 {
+        longs[long + 1] |= ((value as u64) >> (64 - shift)) as i64;
+    }
+}
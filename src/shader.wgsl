@@ -7,23 +7,64 @@ struct Camera {
 @group(1) @binding(0)
 var<uniform> camera: Camera;
 
+// brightness of each face, see lighting::Lighting, four to a vec4
+struct Lighting {
+    shades: array<vec4<f32>, 2>,
+}
+@group(2) @binding(0)
+var<uniform> lighting: Lighting;
+
+// a vertex packed into a u32, see mesh::Vertex, and the origin of its section
 struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
+    @location(0) packed: u32,
+    @location(1) origin: vec3<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) tile: u32,
+    @location(2) shade: f32,
 }
 
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
+    let packed = model.packed;
+    let position = vec3<f32>(
+        f32(packed & 31u),
+        f32((packed >> 5u) & 31u),
+        f32((packed >> 10u) & 31u),
+    );
+    let face = (packed >> 15u) & 7u;
+    let uv = (packed >> 18u) & 7u;
+    // texture coordinates are the position on the face's plane, turned as the
+    // block's texture lies on it
+    var plane = position.xy;
+    if face < 2u {
+        plane = position.xz;
+    } else if face < 4u {
+        plane = position.zy;
+    }
+    if (uv & 1u) != 0u {
+        plane = plane.yx;
+    }
+    if (uv & 2u) != 0u {
+        plane.x = -plane.x;
+    }
+    if (uv & 4u) != 0u {
+        plane.y = -plane.y;
+    }
+
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.tex_coords = plane;
+    out.tile = (packed >> 21u) & 255u;
+    // lit by the direction the face points, each level of ambient occlusion
+    // darkening the corner a bit more
+    let lit = lighting.shades[face / 4u][face % 4u];
+    out.shade = lit * (1.0 - 0.2 * f32((packed >> 29u) & 3u));
+    out.clip_position = camera.view_proj * vec4<f32>(model.origin + position, 1.0);
     return out;
 }
 
@@ -34,7 +75,32 @@ var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
 
+// the block's texture, shaded
+fn shaded_color(in: VertexOutput) -> vec4<f32> {
+    // the atlas is 16 by 16 tiles, merged quads repeat theirs once per block
+    let corner = vec2<f32>(f32(in.tile % 16u), f32(in.tile / 16u));
+    let uv = (corner + fract(in.tex_coords)) / 16.0;
+    let color = textureSample(t_diffuse, s_diffuse, uv);
+    return vec4<f32>(color.rgb * in.shade, color.a);
+}
+
+// one entry point for each of block::RenderLayer
+@fragment
+fn fs_opaque(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(shaded_color(in).rgb, 1.0);
+}
+
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shaded_color(in);
+    // see-through pixels of cutout textures like leaves and flowers
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shaded_color(in);
+}
@@ -2,6 +2,7 @@ use anyhow::*;
 use image::GenericImageView;
 
 pub struct Texture {
+    #[allow(dead_code)] // the view and sampler are built from it, so keep it alive with them
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
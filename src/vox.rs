@@ -0,0 +1,501 @@
+//! MagicaVoxel `.vox` models, read into a `BlockVolume` and written from one.
+//!
+//! A file is the magic `VOX `, a version and a `MAIN` chunk holding the others. Each
+//! chunk is a 4 byte id, the length of its content and of its children, then both.
+//! Every model is a `SIZE` chunk followed by an `XYZI` chunk listing its voxels as
+//! `x`, `y`, `z` and a palette index, 1 to 255. `RGBA` holds the palette and the
+//! `nTRN`, `nGRP` and `nSHP` nodes place the models in the scene. MagicaVoxel is
+//! z up while we are y up, so its y axis becomes our z axis, reversed to keep the
+//! model from being mirrored.
+
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+};
+
+use anyhow::{bail, Context};
+use image::RgbaImage;
+
+use crate::{
+    block::{Block, BlockRegistry},
+    schematic::BlockVolume,
+};
+
+const VERSION: i32 = 150;
+/// Models are at most this big along each axis, larger volumes are split.
+const MAX_MODEL_SIZE: usize = 256;
+/// Scenes bigger than this are refused instead of allocated, 256 blocks cubed.
+const MAX_VOLUME: usize = 1 << 24;
+/// Same as the renderer's atlas layout.
+const TILES_PER_ROW: u32 = 16;
+
+/// Average color of each atlas tile, to match voxel colors to blocks and back.
+pub struct BlockColors {
+    tiles: Vec<[u8; 3]>,
+    /// Every block type but air in its default state, with its top face color.
+    candidates: Vec<(Block, [u8; 3])>,
+}
+impl BlockColors {
+    /// Colors of the atlas the game renders with.
+    pub fn load_default(registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let atlas = image::load_from_memory(include_bytes!("texture_atlas.png"))?;
+        Ok(Self::from_atlas(&atlas.to_rgba8(), registry))
+    }
+    pub fn from_atlas(atlas: &RgbaImage, registry: &BlockRegistry) -> Self {
+        let tile_size = atlas.width() / TILES_PER_ROW;
+        let tiles = (0..TILES_PER_ROW * TILES_PER_ROW)
+            .map(|tile| {
+                let (left, top) = (
+                    tile % TILES_PER_ROW * tile_size,
+                    tile / TILES_PER_ROW * tile_size,
+                );
+                //weighted by alpha, so see-through pixels of leaves don't darken them
+                let mut sum = [0u64; 3];
+                let mut weight = 0u64;
+                for y in top..top + tile_size {
+                    for x in left..left + tile_size {
+                        let [r, g, b, a] = atlas.get_pixel(x, y).0;
+                        for (s, c) in sum.iter_mut().zip([r, g, b]) {
+                            *s += c as u64 * a as u64;
+                        }
+                        weight += a as u64;
+                    }
+                }
+                sum.map(|s| s.checked_div(weight).unwrap_or(0) as u8)
+            })
+            .collect();
+        let mut colors = BlockColors {
+            tiles,
+            candidates: vec![],
+        };
+        colors.candidates = registry
+            .iter()
+            .filter(|def| def.id != 0)
+            .map(|def| {
+                let block = registry.default_state(registry.expect(&def.name));
+                (block, colors.color(registry, block))
+            })
+            .collect();
+        colors
+    }
+    /// Average color of the block's top face.
+    pub fn color(&self, registry: &BlockRegistry, block: Block) -> [u8; 3] {
+        let tile = registry.model(block).textures.top as usize;
+        self.tiles.get(tile).copied().unwrap_or_default()
+    }
+    /// The block type whose top face is closest to the color, in its default state.
+    pub fn nearest(&self, color: [u8; 3]) -> Block {
+        let distance = |other: [u8; 3]| -> i32 {
+            color
+                .iter()
+                .zip(other)
+                .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+                .sum()
+        };
+        self.candidates
+            .iter()
+            .min_by_key(|(_, c)| distance(*c))
+            .map_or(Block::AIR, |(block, _)| *block)
+    }
+}
+/// Every model of the file in one volume, placed the way the scene places them.
+/// Voxels become the block closest to their color, empty space is left alone.
+pub fn read_vox(data: &[u8], colors: &BlockColors) -> anyhow::Result<BlockVolume> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.bytes(4)? != b"VOX " {
+        bail!("not a vox file");
+    }
+    let _version = reader.i32()?;
+    let (id, content, children) = reader.chunk()?;
+    if id != *b"MAIN" || !content.is_empty() {
+        bail!("vox file doesn't start with a MAIN chunk");
+    }
+
+    let mut models: Vec<([usize; 3], Vec<[u8; 4]>)> = vec![];
+    let mut size = None;
+    let mut palette = default_palette();
+    let mut nodes = HashMap::new();
+    let mut reader = Reader {
+        data: children,
+        pos: 0,
+    };
+    while reader.pos < children.len() {
+        let (id, content, _) = reader.chunk()?;
+        let mut chunk = Reader {
+            data: content,
+            pos: 0,
+        };
+        match &id {
+            b"SIZE" => {
+                let mut read = || -> anyhow::Result<usize> {
+                    usize::try_from(chunk.i32()?)
+                        .ok()
+                        .filter(|v| *v <= MAX_MODEL_SIZE)
+                        .context("bad model size")
+                };
+                size = Some([read()?, read()?, read()?]);
+            }
+            b"XYZI" => {
+                let size = size.take().context("XYZI without SIZE")?;
+                let count = chunk.i32()?;
+                let count = usize::try_from(count).context("bad voxel count")?;
+                let voxels = chunk.bytes(count.checked_mul(4).context("bad voxel count")?)?;
+                let voxels = voxels
+                    .chunks_exact(4)
+                    .map(|v| [v[0], v[1], v[2], v[3]])
+                    .collect();
+                models.push((size, voxels));
+            }
+            b"RGBA" => {
+                //entry i is the color of palette index i + 1
+                for i in 0..255 {
+                    let rgba = chunk.bytes(4)?;
+                    palette[i + 1] = [rgba[0], rgba[1], rgba[2]];
+                }
+            }
+            b"nTRN" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let child = chunk.i32()?;
+                let _reserved = chunk.i32()?;
+                let _layer = chunk.i32()?;
+                let frames = chunk.i32()?;
+                let mut translation = [0; 3];
+                if frames > 0 {
+                    let frame = chunk.dict()?;
+                    if frame.contains_key("_r") {
+                        log::warn!("vox node {} is rotated, only its position is used", node);
+                    }
+                    if let Some(t) = frame.get("_t") {
+                        let t: Vec<i64> = t
+                            .split_whitespace()
+                            .filter_map(|v| v.parse().ok())
+                            .collect();
+                        translation = t.try_into().ok().context("bad node translation")?;
+                    }
+                }
+                nodes.insert(node, Node::Transform { translation, child });
+            }
+            b"nGRP" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.i32()?;
+                let children = (0..count)
+                    .map(|_| chunk.i32())
+                    .collect::<anyhow::Result<_>>()?;
+                nodes.insert(node, Node::Group(children));
+            }
+            b"nSHP" => {
+                let node = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.i32()?;
+                let mut shapes = vec![];
+                for _ in 0..count {
+                    shapes.push(chunk.i32()?);
+                    let _attributes = chunk.dict()?;
+                }
+                nodes.insert(node, Node::Shape(shapes));
+            }
+            //materials, layers, cameras and the like
+            _ => {}
+        }
+    }
+    if models.is_empty() {
+        bail!("no models");
+    }
+
+    //lowest corner of each model, in MagicaVoxel's axes
+    let mut placed = vec![];
+    if nodes.is_empty() {
+        placed.extend((0..models.len()).map(|model| (model, [0; 3])));
+    } else {
+        place(&nodes, 0, [0; 3], &models, &mut placed, 0)?;
+    }
+    let mut min = [i64::MAX; 3];
+    let mut max = [i64::MIN; 3];
+    for (model, corner) in &placed {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(corner[axis]);
+            max[axis] = max[axis].max(corner[axis] + models[*model].0[axis] as i64);
+        }
+    }
+    if placed.is_empty() {
+        bail!("the scene has no models");
+    }
+    let [width, length, height] = [0, 1, 2].map(|axis| (max[axis] - min[axis]) as usize);
+    let volume_size = width
+        .checked_mul(length)
+        .and_then(|v| v.checked_mul(height));
+    if volume_size.is_none_or(|v| v > MAX_VOLUME) {
+        bail!("{}x{}x{} is too big", width, height, length);
+    }
+    let mut volume = BlockVolume::new(width, height, length);
+
+    let blocks: Vec<Block> = palette.iter().map(|c| colors.nearest(*c)).collect();
+    for (model, corner) in placed {
+        let (size, voxels) = &models[model];
+        for [x, y, z, index] in voxels {
+            let [x, y, z] = [*x, *y, *z].map(|v| v as usize);
+            if x >= size[0] || y >= size[1] || z >= size[2] || *index == 0 {
+                continue;
+            }
+            let [x, y, z] =
+                [0, 1, 2].map(|axis| (corner[axis] - min[axis]) as usize + [x, y, z][axis]);
+            volume.set(x, z, length - 1 - y, Some(blocks[*index as usize]));
+        }
+    }
+    Ok(volume)
+}
+/// Writes the volume as models of at most 256 blocks a side, every block colored
+/// like its top face. Fails if it has more than 255 different blocks.
+pub fn write_vox(
+    volume: &BlockVolume,
+    registry: &BlockRegistry,
+    colors: &BlockColors,
+) -> anyhow::Result<Vec<u8>> {
+    let (width, height, length) = (volume.width(), volume.height(), volume.length());
+    let mut palette: Vec<Block> = vec![];
+    let mut index_of = |block: Block| -> anyhow::Result<u8> {
+        let index = match palette.iter().position(|b| *b == block) {
+            Some(index) => index,
+            None => {
+                palette.push(block);
+                palette.len() - 1
+            }
+        };
+        if index >= 255 {
+            bail!("vox files can't hold more than 255 different blocks");
+        }
+        Ok(index as u8 + 1)
+    };
+
+    let mut children = vec![];
+    //root transform and the group holding every model, then a transform and shape
+    //for each model
+    let mut shapes = vec![];
+    let tiles = |len: usize| (0..len.max(1)).step_by(MAX_MODEL_SIZE);
+    //MagicaVoxel's axes, y being our z reversed
+    let size = [width, length, height];
+    for vz in tiles(size[2]) {
+        for vy in tiles(size[1]) {
+            for vx in tiles(size[0]) {
+                let corner = [vx, vy, vz];
+                let model_size =
+                    [0, 1, 2].map(|axis| (size[axis] - corner[axis]).min(MAX_MODEL_SIZE));
+                let mut voxels = vec![];
+                for z in 0..model_size[2] {
+                    for y in 0..model_size[1] {
+                        for x in 0..model_size[0] {
+                            let [x, y, z] = [vx + x, vy + y, vz + z];
+                            let block = volume.get(x, z, length - 1 - y);
+                            if let Some(block) = block.filter(|b| !b.is_air()) {
+                                voxels.extend_from_slice(&[
+                                    (x - vx) as u8,
+                                    (y - vy) as u8,
+                                    (z - vz) as u8,
+                                    index_of(block)?,
+                                ]);
+                            }
+                        }
+                    }
+                }
+                let mut content = vec![];
+                for v in model_size {
+                    content.extend_from_slice(&(v as i32).to_le_bytes());
+                }
+                write_chunk(&mut children, b"SIZE", &content);
+                let mut content = ((voxels.len() / 4) as i32).to_le_bytes().to_vec();
+                content.extend_from_slice(&voxels);
+                write_chunk(&mut children, b"XYZI", &content);
+                //models are placed by their center
+                let center = [0, 1, 2].map(|axis| corner[axis] + model_size[axis] / 2);
+                shapes.push(center);
+            }
+        }
+    }
+
+    let mut node = vec![];
+    write_transform(&mut node, 0, 1, [0; 3]);
+    write_chunk(&mut children, b"nTRN", &node);
+    let mut group = vec![];
+    group.extend_from_slice(&1i32.to_le_bytes());
+    write_dict(&mut group, &[]);
+    group.extend_from_slice(&(shapes.len() as i32).to_le_bytes());
+    for i in 0..shapes.len() {
+        group.extend_from_slice(&(2 + 2 * i as i32).to_le_bytes());
+    }
+    write_chunk(&mut children, b"nGRP", &group);
+    for (i, center) in shapes.iter().enumerate() {
+        let id = 2 + 2 * i as i32;
+        let mut node = vec![];
+        write_transform(&mut node, id, id + 1, center.map(|v| v as i64));
+        write_chunk(&mut children, b"nTRN", &node);
+        let mut shape = vec![];
+        shape.extend_from_slice(&(id + 1).to_le_bytes());
+        write_dict(&mut shape, &[]);
+        shape.extend_from_slice(&1i32.to_le_bytes());
+        shape.extend_from_slice(&(i as i32).to_le_bytes());
+        write_dict(&mut shape, &[]);
+        write_chunk(&mut children, b"nSHP", &shape);
+    }
+
+    let mut rgba = vec![0; 256 * 4];
+    for (i, block) in palette.iter().enumerate() {
+        let [r, g, b] = colors.color(registry, *block);
+        rgba[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+    }
+    write_chunk(&mut children, b"RGBA", &rgba);
+
+    let mut out = b"VOX ".to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(b"MAIN");
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    out.extend_from_slice(&children);
+    Ok(out)
+}
+enum Node {
+    Transform { translation: [i64; 3], child: i32 },
+    Group(Vec<i32>),
+    Shape(Vec<i32>),
+}
+/// Walks the scene from `node`, adding each model with its lowest corner.
+fn place(
+    nodes: &HashMap<i32, Node>,
+    node: i32,
+    translation: [i64; 3],
+    models: &[([usize; 3], Vec<[u8; 4]>)],
+    placed: &mut Vec<(usize, [i64; 3])>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    if depth > 64 {
+        bail!("scene nodes nested too deep");
+    }
+    match nodes.get(&node).context("scene refers to a missing node")? {
+        Node::Transform {
+            translation: t,
+            child,
+        } => {
+            let translation = [0, 1, 2].map(|axis| translation[axis] + t[axis]);
+            place(nodes, *child, translation, models, placed, depth + 1)?;
+        }
+        Node::Group(children) => {
+            for child in children {
+                place(nodes, *child, translation, models, placed, depth + 1)?;
+            }
+        }
+        Node::Shape(shapes) => {
+            for model in shapes {
+                let model = usize::try_from(*model)
+                    .ok()
+                    .filter(|m| *m < models.len())
+                    .context("scene refers to a missing model")?;
+                let size = models[model].0;
+                let corner = [0, 1, 2].map(|axis| translation[axis] - (size[axis] / 2) as i64);
+                placed.push((model, corner));
+            }
+        }
+    }
+    Ok(())
+}
+/// MagicaVoxel's palette for files without an `RGBA` chunk: a 6 level color cube
+/// without black, then ramps of red, green, blue and gray. Index 0 is unused.
+fn default_palette() -> [[u8; 3]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [[0; 3]; 256];
+    let mut i = 1;
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                if i < 216 {
+                    palette[i] = [r, g, b];
+                    i += 1;
+                }
+            }
+        }
+    }
+    for channel in [0, 1, 2] {
+        for v in RAMP {
+            palette[i][channel] = v;
+            i += 1;
+        }
+    }
+    for v in RAMP {
+        palette[i] = [v; 3];
+        i += 1;
+    }
+    palette
+}
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(content);
+}
+fn write_dict(out: &mut Vec<u8>, entries: &[(&str, &str)]) {
+    out.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+    for (key, value) in entries {
+        for s in [key, value] {
+            out.extend_from_slice(&(s.len() as i32).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+}
+fn write_transform(out: &mut Vec<u8>, id: i32, child: i32, translation: [i64; 3]) {
+    out.extend_from_slice(&id.to_le_bytes());
+    write_dict(out, &[]);
+    out.extend_from_slice(&child.to_le_bytes());
+    //reserved, then the layer
+    out.extend_from_slice(&(-1i32).to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(&1i32.to_le_bytes());
+    if translation == [0; 3] {
+        write_dict(out, &[]);
+    } else {
+        let t = format!("{} {} {}", translation[0], translation[1], translation[2]);
+        write_dict(out, &[("_t", &t)]);
+    }
+}
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let Some(bytes) = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+        else {
+            bail!("vox data ends early");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+    fn size(&mut self) -> anyhow::Result<usize> {
+        usize::try_from(self.i32()?).context("negative size")
+    }
+    /// Id, content and children of the next chunk.
+    fn chunk(&mut self) -> anyhow::Result<([u8; 4], &'a [u8], &'a [u8])> {
+        let id: [u8; 4] = self.bytes(4)?.try_into()?;
+        let content = self.size()?;
+        let children = self.size()?;
+        Ok((id, self.bytes(content)?, self.bytes(children)?))
+    }
+    fn dict(&mut self) -> anyhow::Result<HashMap<String, String>> {
+        let count = self.size()?;
+        let mut dict = HashMap::new();
+        for _ in 0..count {
+            let key = self.size()?;
+            let key = String::from_utf8_lossy(self.bytes(key)?).into_owned();
+            let value = self.size()?;
+            let value = String::from_utf8_lossy(self.bytes(value)?).into_owned();
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+}
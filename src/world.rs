@@ -0,0 +1,475 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    anvil::{self, BlockMapping},
+    biome::BiomeId,
+    block::{Block, BlockRegistry},
+    chunk::{Chunk, ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos, LocalBlockPos, CHUNK_SIZE},
+    region::RegionStorage,
+    schematic::{BlockVolume, Rotation},
+};
+
+/// Offsets of the chunks next to a chunk, in the order `ChunkSnapshot` keeps them.
+const NEIGHBORS: [[i32; 2]; 8] = [
+    [1, 0],
+    [-1, 0],
+    [0, 1],
+    [0, -1],
+    [1, 1],
+    [1, -1],
+    [-1, 1],
+    [-1, -1],
+];
+
+/// A chunk's blocks and the ones of the eight chunks around it, as they were when
+/// copied out of the world, for meshing it away from the world on another thread.
+#[derive(Clone, Debug)]
+pub struct ChunkSnapshot {
+    pub pos: ChunkPos,
+    pub blocks: ChunkBlocks,
+    /// `None` for neighbors that weren't loaded.
+    neighbors: [Option<ChunkBlocks>; 8],
+}
+impl ChunkSnapshot {
+    /// Blocks of the chunk or one around it, `None` for any other chunk.
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&ChunkBlocks> {
+        if pos == self.pos {
+            return Some(&self.blocks);
+        }
+        let offset = [pos.x - self.pos.x, pos.z - self.pos.z];
+        let index = NEIGHBORS.iter().position(|neighbor| *neighbor == offset)?;
+        self.neighbors[index].as_ref()
+    }
+    /// Like `World::get_block`, for the blocks in the snapshot.
+    pub fn get_block(&self, pos: BlockPos) -> Option<Block> {
+        let blocks = self.chunk(pos.chunk())?;
+        if !blocks.height().contains(pos.y) {
+            return None;
+        }
+        Some(blocks.get(pos.local()))
+    }
+}
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+    registry: Arc<BlockRegistry>,
+    height: WorldHeight,
+    /// Sections whose mesh no longer matches their blocks, by chunk and section index.
+    dirty: HashSet<(ChunkPos, usize)>,
+    /// Where chunks are saved, worlds without one are thrown away on exit.
+    storage: Option<RegionStorage>,
+    /// Feature blocks placed into chunks that weren't loaded yet, see `place_features`.
+    pending: HashMap<ChunkPos, FeatureBlocks>,
+    /// Feature blocks placed into loaded chunks that aren't saved with them, see
+    /// `unload_chunk`.
+    placed: HashMap<ChunkPos, FeatureBlocks>,
+}
+/// Feature blocks of one chunk, the one that fits where two land on the same block.
+type FeatureBlocks = HashMap<LocalBlockPos, Block>;
+impl World {
+    pub fn new(registry: Arc<BlockRegistry>, height: WorldHeight) -> Self {
+        World {
+            chunks: HashMap::new(),
+            registry,
+            height,
+            dirty: HashSet::new(),
+            storage: None,
+            pending: HashMap::new(),
+            placed: HashMap::new(),
+        }
+    }
+    pub fn set_storage(&mut self, storage: RegionStorage) {
+        self.storage = Some(storage);
+    }
+    /// The chunk's blocks as they were last saved, `None` if it was never saved or
+    /// can't be read, in which case it should be generated again.
+    pub fn load_saved(&mut self, pos: ChunkPos) -> Option<ChunkBlocks> {
+        let storage = self.storage.as_mut()?;
+        match storage.load_chunk(pos, &self.registry, self.height) {
+            Ok(blocks) => blocks,
+            Err(error) => {
+                log::error!("couldn't load chunk {:?}: {:?}", pos, error);
+                None
+            }
+        }
+    }
+    /// Saves every chunk changed since it was loaded or generated, returning how
+    /// many were written.
+    pub fn save_modified(&mut self) -> anyhow::Result<usize> {
+        let Some(storage) = self.storage.as_mut() else {
+            return Ok(0);
+        };
+        let mut saved = 0;
+        for (pos, chunk) in self.chunks.iter_mut().filter(|(_, chunk)| chunk.modified) {
+            storage.save_chunk(*pos, &chunk.blocks, &self.registry)?;
+            chunk.modified = false;
+            self.placed.remove(pos);
+            saved += 1;
+        }
+        Ok(saved)
+    }
+    /// Loads every chunk in the region directory of a Minecraft world, replacing the
+    /// chunks already there. They count as modified so they are saved with this world,
+    /// and are queued to be meshed. Returns how many chunks were imported.
+    pub fn import_anvil(
+        &mut self,
+        region_dir: &Path,
+        mapping: &BlockMapping,
+    ) -> anyhow::Result<usize> {
+        let mut imported = 0;
+        for entry in fs::read_dir(region_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "mca") {
+                continue;
+            }
+            for (pos, blocks) in anvil::read_region(&path, &self.registry, self.height, mapping)? {
+                let mut chunk = Chunk::new(blocks);
+                chunk.modified = true;
+                self.chunks.insert(pos, chunk);
+                self.mark_chunk_dirty(pos);
+                imported += 1;
+            }
+        }
+        Ok(imported)
+    }
+    /// Writes every chunk of the world, the loaded ones and the ones unloaded into
+    /// its storage, into region files Minecraft can open, replacing region files
+    /// already in the directory. Returns how many chunks were exported.
+    pub fn export_anvil(
+        &mut self,
+        region_dir: &Path,
+        mapping: &BlockMapping,
+    ) -> anyhow::Result<usize> {
+        fs::create_dir_all(region_dir)?;
+        let mut regions: HashMap<String, Vec<ChunkPos>> = HashMap::new();
+        let mut positions: Vec<ChunkPos> = self.chunks.keys().copied().collect();
+        if let Some(storage) = self.storage.as_mut() {
+            //loaded chunks are newer than their saves
+            let saved = storage.saved_chunks()?;
+            positions.extend(
+                saved
+                    .into_iter()
+                    .filter(|pos| !self.chunks.contains_key(pos)),
+            );
+        }
+        for pos in &positions {
+            regions
+                .entry(anvil::region_file_name(*pos))
+                .or_default()
+                .push(*pos);
+        }
+        for (name, chunks) in &regions {
+            //saved chunks are read back a region at a time, not the whole world at once
+            let mut saved = vec![];
+            if let Some(storage) = self.storage.as_mut() {
+                for pos in chunks {
+                    if self.chunks.contains_key(pos) {
+                        continue;
+                    }
+                    if let Some(blocks) = storage.load_chunk(*pos, &self.registry, self.height)? {
+                        saved.push((*pos, blocks));
+                    }
+                }
+            }
+            let loaded = chunks
+                .iter()
+                .filter_map(|pos| Some((*pos, &self.chunks.get(pos)?.blocks)));
+            anvil::write_region(
+                &region_dir.join(name),
+                loaded.chain(saved.iter().map(|(pos, blocks)| (*pos, blocks))),
+                &self.registry,
+                mapping,
+            )?;
+        }
+        Ok(positions.len())
+    }
+    pub fn height(&self) -> WorldHeight {
+        self.height
+    }
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+    pub fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+    /// Adds the chunk, with the feature blocks that were waiting for it.
+    pub fn insert_chunk(&mut self, pos: ChunkPos, mut chunk: Chunk) -> Option<Chunk> {
+        if let Some(pending) = self.pending.remove(&pos) {
+            let placed = self.placed.entry(pos).or_default();
+            for (local, block) in pending {
+                place_feature(&self.registry, &mut chunk.blocks, local, block);
+                add_feature(&self.registry, placed, local, block);
+            }
+        }
+        self.chunks.insert(pos, chunk)
+    }
+    /// Removes the chunk, saving it first if it was modified. Feature blocks other
+    /// chunks placed into it go back to waiting for it unless it was saved, since
+    /// generating it again only brings back its own features. Returns whether it was
+    /// unloaded, modified chunks stay loaded in a world without storage.
+    pub fn unload_chunk(&mut self, pos: ChunkPos) -> anyhow::Result<bool> {
+        let Some(chunk) = self.chunks.get(&pos) else {
+            return Ok(false);
+        };
+        if chunk.modified {
+            let Some(storage) = self.storage.as_mut() else {
+                return Ok(false);
+            };
+            storage.save_chunk(pos, &chunk.blocks, &self.registry)?;
+            self.placed.remove(&pos);
+        } else if let Some(placed) = self.placed.remove(&pos) {
+            let pending = self.pending.entry(pos).or_default();
+            for (local, block) in placed {
+                add_feature(&self.registry, pending, local, block);
+            }
+        }
+        self.chunks.remove(&pos);
+        self.dirty.retain(|(dirty, _)| *dirty != pos);
+        //the chunks around it have faces towards it again
+        self.mark_chunk_dirty(pos);
+        Ok(true)
+    }
+    /// Copies of the chunk's blocks and the ones of the chunks next to it, `None` if
+    /// the chunk isn't loaded.
+    pub fn snapshot(&self, pos: ChunkPos) -> Option<ChunkSnapshot> {
+        let copy = |pos: ChunkPos| Some(self.chunks.get(&pos)?.blocks.clone());
+        Some(ChunkSnapshot {
+            pos,
+            blocks: copy(pos)?,
+            neighbors: NEIGHBORS.map(|[dx, dz]| copy(pos.offset(dx, dz))),
+        })
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+    pub fn chunk_positions(&self) -> Vec<ChunkPos> {
+        self.chunks.keys().copied().collect()
+    }
+    /// Block at the given world coordinates, or `None` if its chunk isn't loaded
+    /// or `y` is outside the world height.
+    pub fn get_block(&self, pos: BlockPos) -> Option<Block> {
+        if !self.height.contains(pos.y) {
+            return None;
+        }
+        let chunk = self.chunk(pos.chunk())?;
+        Some(chunk.blocks.get(pos.local()))
+    }
+    /// Y of the highest block of the column that isn't air, `None` if there is none
+    /// or the chunk isn't loaded.
+    pub fn highest_block(&self, x: i32, z: i32) -> Option<i32> {
+        let column = BlockPos::new(x, 0, z);
+        let chunk = self.chunk(column.chunk())?;
+        let local = column.local();
+        (self.height.min_y..self.height.max_y())
+            .rev()
+            .find(|y| !chunk.blocks.get(LocalBlockPos { y: *y, ..local }).is_air())
+    }
+    /// Biome of the column the block is in.
+    pub fn biome(&self, pos: BlockPos) -> Option<BiomeId> {
+        let local = pos.local();
+        let chunk = self.chunk(pos.chunk())?;
+        Some(chunk.blocks.biome(local.x as usize, local.z as usize))
+    }
+    /// Changes the block and marks the meshes that show it for rebuilding, which
+    /// includes the neighboring sections when the block is on a section border since
+    /// their faces against it are culled. Returns the old block, or `None` if the
+    /// chunk isn't loaded or `y` is outside the world height.
+    pub fn set_block(&mut self, pos: BlockPos, block: Block) -> Option<Block> {
+        if !self.height.contains(pos.y) {
+            return None;
+        }
+        let chunk = self.chunks.get_mut(&pos.chunk())?;
+        let old = chunk.blocks.set(pos.local(), block);
+        if old != block {
+            chunk.modified = true;
+            self.mark_block_dirty(pos);
+        }
+        Some(old)
+    }
+    /// Places blocks of generated features like trees. Each goes into air, or into a
+    /// replaceable block if it isn't replaceable itself or has a higher state id, so
+    /// trunks grow through the leaves of other trees and overlapping leaves come out
+    /// the same whichever tree comes first. Blocks in chunks that aren't loaded wait
+    /// until the chunk is inserted. Features come from the seed, so unlike
+    /// `set_block` this isn't a change to save.
+    pub fn place_features(&mut self, blocks: impl IntoIterator<Item = (BlockPos, Block)>) {
+        for (pos, block) in blocks {
+            if !self.height.contains(pos.y) {
+                continue;
+            }
+            let Some(chunk) = self.chunks.get_mut(&pos.chunk()) else {
+                let pending = self.pending.entry(pos.chunk()).or_default();
+                add_feature(&self.registry, pending, pos.local(), block);
+                continue;
+            };
+            let placed = self.placed.entry(pos.chunk()).or_default();
+            add_feature(&self.registry, placed, pos.local(), block);
+            if place_feature(&self.registry, &mut chunk.blocks, pos.local(), block) {
+                self.mark_block_dirty(pos);
+            }
+        }
+    }
+    /// Feature blocks waiting for their chunks to be inserted.
+    pub fn pending_count(&self) -> usize {
+        self.pending.values().map(HashMap::len).sum()
+    }
+    /// Marks the section of a changed block dirty, and the neighboring sections when
+    /// the block is on a section border since their faces against it are culled.
+    /// Diagonal ones too, the block darkens the corners of faces next to it.
+    fn mark_block_dirty(&mut self, pos: BlockPos) {
+        for dy in -1..=1 {
+            let y = pos.y + dy;
+            if !self.height.contains(y) {
+                continue;
+            }
+            let index = self.height.section_index(y);
+            for dz in -1..=1 {
+                for dx in -1..=1 {
+                    self.mark_dirty(pos.offset(dx, dy, dz).chunk(), index);
+                }
+            }
+        }
+    }
+    /// Turns the volume and places it with its lowest corner at `origin`, skipping
+    /// blocks it leaves alone and ones in chunks that aren't loaded. Returns how many
+    /// blocks were placed.
+    pub fn paste(&mut self, volume: &BlockVolume, origin: BlockPos, rotation: Rotation) -> usize {
+        let volume = volume.rotated(&self.registry, rotation);
+        let mut placed = 0;
+        for y in 0..volume.height() {
+            for z in 0..volume.length() {
+                for x in 0..volume.width() {
+                    let Some(block) = volume.get(x, y, z) else {
+                        continue;
+                    };
+                    let pos = origin.offset(x as i32, y as i32, z as i32);
+                    if self.set_block(pos, block).is_some() {
+                        placed += 1;
+                    }
+                }
+            }
+        }
+        placed
+    }
+    /// Blocks of the chunks from `min` to `max`, both included, over the whole world
+    /// height. Chunks that aren't loaded are left empty.
+    pub fn copy_chunks(&self, min: ChunkPos, max: ChunkPos) -> BlockVolume {
+        let (min_x, min_z) = ChunkPos::new(min.x.min(max.x), min.z.min(max.z)).origin();
+        let (max_x, max_z) = ChunkPos::new(min.x.max(max.x), min.z.max(max.z)).origin();
+        self.copy(
+            BlockPos::new(min_x, self.height.min_y, min_z),
+            BlockPos::new(
+                max_x + CHUNK_SIZE - 1,
+                self.height.max_y() - 1,
+                max_z + CHUNK_SIZE - 1,
+            ),
+        )
+    }
+    /// Blocks of the box between two corners, both included. Blocks in chunks that
+    /// aren't loaded or outside the world height are left empty.
+    pub fn copy(&self, a: BlockPos, b: BlockPos) -> BlockVolume {
+        let min = BlockPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = BlockPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let mut volume = BlockVolume::new(
+            (max.x - min.x + 1) as usize,
+            (max.y - min.y + 1) as usize,
+            (max.z - min.z + 1) as usize,
+        );
+        for y in 0..volume.height() {
+            for z in 0..volume.length() {
+                for x in 0..volume.width() {
+                    let pos = min.offset(x as i32, y as i32, z as i32);
+                    volume.set(x, y, z, self.get_block(pos));
+                }
+            }
+        }
+        volume
+    }
+    /// Queues the section's mesh to be rebuilt, if its chunk is loaded.
+    pub fn mark_dirty(&mut self, pos: ChunkPos, index: usize) {
+        if self.chunks.contains_key(&pos) {
+            self.dirty.insert((pos, index));
+        }
+    }
+    /// Queues every section of the chunk to be meshed, and those of the chunks around
+    /// it since their faces on the border with it are culled and shaded against it.
+    pub fn mark_chunk_dirty(&mut self, pos: ChunkPos) {
+        for index in 0..self.height.sections {
+            self.mark_dirty(pos, index);
+            for [dx, dz] in NEIGHBORS {
+                self.mark_dirty(pos.offset(dx, dz), index);
+            }
+        }
+    }
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.len()
+    }
+    /// Removes and returns up to `budget` dirty sections, the ones closest to
+    /// `near` first. Sections of chunks `ready` turns down stay dirty.
+    pub fn take_dirty(
+        &mut self,
+        near: ChunkPos,
+        budget: usize,
+        ready: impl Fn(ChunkPos) -> bool,
+    ) -> Vec<(ChunkPos, usize)> {
+        let mut dirty: Vec<(ChunkPos, usize)> = self
+            .dirty
+            .iter()
+            .copied()
+            .filter(|(pos, _)| ready(*pos))
+            .collect();
+        dirty.sort_by_key(|(pos, _)| (pos.x - near.x).abs().max((pos.z - near.z).abs()));
+        dirty.truncate(budget);
+        for section in &dirty {
+            self.dirty.remove(section);
+        }
+        dirty
+    }
+}
+/// Places one feature block, see `World::place_features`. Returns whether it was
+/// placed.
+fn place_feature(
+    registry: &BlockRegistry,
+    blocks: &mut ChunkBlocks,
+    local: LocalBlockPos,
+    block: Block,
+) -> bool {
+    let old = blocks.get(local);
+    let fits = feature_fits(registry, old, block) && old != block;
+    if fits {
+        blocks.set(local, block);
+    }
+    fits
+}
+/// Whether feature block `new` goes where `old` is, see `World::place_features`.
+fn feature_fits(registry: &BlockRegistry, old: Block, new: Block) -> bool {
+    old.is_air()
+        || (registry.is_replaceable(old) && (!registry.is_replaceable(new) || new.0 > old.0))
+}
+/// Adds a feature block to the chunk's, keeping the one that fits where there
+/// already is one.
+fn add_feature(
+    registry: &BlockRegistry,
+    features: &mut FeatureBlocks,
+    local: LocalBlockPos,
+    block: Block,
+) {
+    match features.entry(local) {
+        Entry::Occupied(mut old) => {
+            if feature_fits(registry, *old.get(), block) {
+                old.insert(block);
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(block);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Terrain generation. A generator turns a seed and a chunk position into blocks, the
+//! same seed always giving the same blocks.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, Context};
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::{
+    biome::{BiomeDef, BiomeId, Biomes},
+    block::{Block, BlockRegistry},
+    cave::{CaveSettings, Caves, Worm},
+    chunk::{ChunkBlocks, WorldHeight, CHUNK_WIDTH},
+    coords::{BlockPos, ChunkPos, LocalBlockPos},
+    decoration::{Decorator, FeatureDef},
+    lake::{Lake, LakeSettings, Lakes},
+    ore::{OreRule, Ores},
+};
+
+/// Fills chunks with the terrain of a world. Chunks can be generated in any order
+/// and from any thread.
+pub trait WorldGenerator: Send + Sync {
+    fn seed(&self) -> u64;
+    fn generate(&self, pos: ChunkPos) -> ChunkBlocks;
+    /// Blocks of features like trees that start in the chunk, given the blocks
+    /// `generate` made for it. They can reach into neighboring chunks, see
+    /// `World::place_features`.
+    fn decorate(&self, _pos: ChunkPos, _blocks: &ChunkBlocks) -> Vec<(BlockPos, Block)> {
+        vec![]
+    }
+}
+/// Octaves of one fractal noise, see `FractalNoise`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoiseSettings {
+    /// Frequency of the first octave, in noise cycles per block.
+    pub scale: f64,
+    pub octaves: u32,
+    /// Frequency multiplier from one octave to the next.
+    pub lacunarity: f64,
+    /// Amplitude multiplier from one octave to the next.
+    pub persistence: f64,
+}
+impl NoiseSettings {
+    pub(crate) fn validate(&self, name: &str) -> anyhow::Result<()> {
+        if !(1..=16).contains(&self.octaves) {
+            bail!(
+                "{} octaves has to be from 1 to 16, not {}",
+                name,
+                self.octaves
+            );
+        }
+        for (field, value) in [
+            ("scale", self.scale),
+            ("lacunarity", self.lacunarity),
+            ("persistence", self.persistence),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                bail!("{} {} has to be above 0, not {}", name, field, value);
+            }
+        }
+        Ok(())
+    }
+}
+/// Settings of `NoiseGenerator`, as stored in `worldgen.ron`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratorSettings {
+    pub terrain: NoiseSettings,
+    /// Points `(noise, height)` sorted by noise, the terrain height being linear
+    /// between them and flat past the ends.
+    pub height_curve: Vec<(f64, f64)>,
+    pub temperature: NoiseSettings,
+    pub humidity: NoiseSettings,
+    /// See `Biomes::height_modifiers`.
+    pub biome_blend: f64,
+    pub biomes: Vec<BiomeDef>,
+    pub caves: CaveSettings,
+    /// Every air block below this is water once the terrain is shaped, caves carved
+    /// after that stay dry.
+    pub sea_level: i32,
+    pub lakes: LakeSettings,
+}
+impl GeneratorSettings {
+    /// Settings the game ships with.
+    pub fn load_default() -> anyhow::Result<Self> {
+        Self::from_ron(include_str!("worldgen.ron")).context("failed to load worldgen.ron")
+    }
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::from_ron(&source).with_context(|| format!("failed to load {}", path.display()))
+    }
+    pub fn from_ron(source: &str) -> anyhow::Result<Self> {
+        let settings: GeneratorSettings = ron::from_str(source)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+    fn validate(&self) -> anyhow::Result<()> {
+        self.terrain.validate("terrain")?;
+        self.temperature.validate("temperature")?;
+        self.humidity.validate("humidity")?;
+        if self.height_curve.is_empty() {
+            bail!("height_curve needs at least one point");
+        }
+        if self
+            .height_curve
+            .iter()
+            .any(|(noise, height)| !noise.is_finite() || !height.is_finite())
+        {
+            bail!("height_curve has to be finite");
+        }
+        if self.height_curve.windows(2).any(|w| w[0].0 >= w[1].0) {
+            bail!("height_curve has to be sorted by noise value");
+        }
+        Biomes::new(self.biomes.clone(), self.biome_blend)?;
+        self.caves.validate()?;
+        self.lakes.validate()?;
+        Ok(())
+    }
+    /// Terrain height for a noise value.
+    pub fn height_at(&self, noise: f64) -> f64 {
+        let curve = &self.height_curve;
+        let after = curve.partition_point(|(n, _)| *n <= noise);
+        if after == 0 {
+            return curve[0].1;
+        }
+        if after == curve.len() {
+            return curve[curve.len() - 1].1;
+        }
+        let ((n0, h0), (n1, h1)) = (curve[after - 1], curve[after]);
+        h0 + (h1 - h0) * (noise - n0) / (n1 - n0)
+    }
+}
+/// Octaves of Perlin noise summed together, each one finer and weaker than the one
+/// before, scaled back to roughly -1 to 1.
+pub struct FractalNoise {
+    octaves: Vec<Perlin>,
+    scale: f64,
+    lacunarity: f64,
+    persistence: f64,
+}
+impl FractalNoise {
+    /// Noise of its own for each `salt`, so different uses of one world seed don't
+    /// line up.
+    pub fn new(seed: u64, salt: u64, settings: &NoiseSettings) -> Self {
+        FractalNoise {
+            octaves: (0..settings.octaves as u64)
+                .map(|octave| Perlin::new(derive_seed(seed, salt, octave)))
+                .collect(),
+            scale: settings.scale,
+            lacunarity: settings.lacunarity,
+            persistence: settings.persistence,
+        }
+    }
+    pub fn get(&self, x: f64, z: f64) -> f64 {
+        let mut frequency = self.scale;
+        let mut amplitude = 1.0;
+        let (mut sum, mut total) = (0.0, 0.0);
+        for octave in &self.octaves {
+            sum += octave.get([x * frequency, z * frequency]) * amplitude;
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+        sum / total
+    }
+    pub fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut frequency = self.scale;
+        let mut amplitude = 1.0;
+        let (mut sum, mut total) = (0.0, 0.0);
+        for octave in &self.octaves {
+            sum += octave.get([x * frequency, y * frequency, z * frequency]) * amplitude;
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+        sum / total
+    }
+}
+/// Seed of one octave of one noise, mixed so nearby inputs give unrelated seeds.
+fn derive_seed(seed: u64, salt: u64, octave: u64) -> u32 {
+    let z = seed
+        .wrapping_add(salt.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(octave.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+    (mix(z) >> 32) as u32
+}
+/// The splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+/// Random numbers for one use of the world seed in one chunk, the same whichever
+/// order chunks are generated in.
+pub struct ChunkRng {
+    state: u64,
+}
+impl ChunkRng {
+    pub fn new(seed: u64, salt: u64, pos: ChunkPos) -> Self {
+        let pos = (pos.x as u32 as u64) | (pos.z as u32 as u64) << 32;
+        ChunkRng {
+            state: mix(seed ^ mix(salt.wrapping_add(mix(pos)))),
+        }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+    /// From 0 up to but not including 1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// From `min` to `max`, both included.
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        let span = (max as i64 - min as i64 + 1) as u64;
+        (min as i64 + (self.next_u64() % span) as i64) as i32
+    }
+}
+/// Blocks of one biome's columns, from the top down, and its caves.
+struct SurfaceRules {
+    surface: Block,
+    subsurface: Block,
+    subsurface_depth: i32,
+    stone: Block,
+    cave_density: f64,
+    cave_size: f64,
+}
+/// Terrain from a height map of fractal noise, shaped and covered by the biome of
+/// each column.
+pub struct NoiseGenerator {
+    seed: u64,
+    settings: GeneratorSettings,
+    height: WorldHeight,
+    terrain: FractalNoise,
+    temperature: FractalNoise,
+    humidity: FractalNoise,
+    biomes: Biomes,
+    rules: HashMap<BiomeId, SurfaceRules>,
+    caves: Caves,
+    lakes: Lakes,
+    water: Block,
+    ores: Ores,
+    decorator: Decorator,
+}
+impl NoiseGenerator {
+    pub fn new(
+        seed: u64,
+        settings: GeneratorSettings,
+        registry: &BlockRegistry,
+        height: WorldHeight,
+    ) -> anyhow::Result<Self> {
+        let block = |name: &str| -> anyhow::Result<Block> {
+            let block_type = registry
+                .by_name(name)
+                .with_context(|| format!("unknown block {}", name))?;
+            Ok(registry.default_state(block_type))
+        };
+        let biomes = Biomes::new(settings.biomes.clone(), settings.biome_blend)?;
+        let mut rules = HashMap::new();
+        for def in biomes.iter() {
+            let resolve = || -> anyhow::Result<SurfaceRules> {
+                Ok(SurfaceRules {
+                    surface: block(&def.surface_block)?,
+                    subsurface: block(&def.subsurface_block)?,
+                    subsurface_depth: def.subsurface_depth as i32,
+                    stone: block(&def.stone_block)?,
+                    cave_density: def.cave_density,
+                    cave_size: def.cave_size,
+                })
+            };
+            let resolved = resolve().with_context(|| format!("in biome {}", def.name))?;
+            rules.insert(BiomeId(def.id), resolved);
+        }
+        let max_size = biomes.iter().map(|def| def.cave_size).fold(0.0, f64::max);
+        Ok(NoiseGenerator {
+            seed,
+            height,
+            caves: Caves::new(seed, settings.caves.clone(), max_size),
+            lakes: Lakes::new(seed, settings.lakes.clone()),
+            water: block("water")?,
+            ores: Ores::new(seed, Ores::default_rules()?, registry)?,
+            decorator: Decorator::new(seed, Decorator::default_features()?, &biomes, registry)?,
+            terrain: FractalNoise::new(seed, 0, &settings.terrain),
+            temperature: FractalNoise::new(seed, 1, &settings.temperature),
+            humidity: FractalNoise::new(seed, 2, &settings.humidity),
+            biomes,
+            rules,
+            settings,
+        })
+    }
+    /// Places ores by `rules` instead of the ones in `ores.ron`.
+    pub fn with_ores(
+        mut self,
+        rules: Vec<OreRule>,
+        registry: &BlockRegistry,
+    ) -> anyhow::Result<Self> {
+        self.ores = Ores::new(self.seed, rules, registry)?;
+        Ok(self)
+    }
+    /// Grows features by `defs` instead of the ones in `features.ron`.
+    pub fn with_features(
+        mut self,
+        defs: Vec<FeatureDef>,
+        registry: &BlockRegistry,
+    ) -> anyhow::Result<Self> {
+        self.decorator = Decorator::new(self.seed, defs, &self.biomes, registry)?;
+        Ok(self)
+    }
+    pub fn settings(&self) -> &GeneratorSettings {
+        &self.settings
+    }
+    pub fn height(&self) -> WorldHeight {
+        self.height
+    }
+    pub fn biomes(&self) -> &Biomes {
+        &self.biomes
+    }
+    /// Temperature and humidity of the column.
+    pub fn climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let (x, z) = (x as f64, z as f64);
+        (self.temperature.get(x, z), self.humidity.get(x, z))
+    }
+    pub fn biome_at(&self, x: i32, z: i32) -> BiomeId {
+        let (temperature, humidity) = self.climate(x, z);
+        BiomeId(self.biomes.biome_at(temperature, humidity).id)
+    }
+    pub fn caves(&self) -> &Caves {
+        &self.caves
+    }
+    pub fn lakes(&self) -> &Lakes {
+        &self.lakes
+    }
+    pub fn ores(&self) -> &Ores {
+        &self.ores
+    }
+    pub fn decorator(&self) -> &Decorator {
+        &self.decorator
+    }
+    /// Y of the first air block above the height map terrain of the column, clamped
+    /// to the world. Overhangs and caves change the terrain around it.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let (temperature, humidity) = self.climate(x, z);
+        let (offset, scale) = self.biomes.height_modifiers(temperature, humidity);
+        let middle = self.settings.height_at(0.0);
+        let noise = self.terrain.get(x as f64, z as f64);
+        let height = middle + offset + (self.settings.height_at(noise) - middle) * scale;
+        (height.floor() as i32).clamp(self.height.min_y, self.height.max_y())
+    }
+    /// Worms starting in the chunk, as many and as wide as the biome at its center
+    /// makes them.
+    pub fn worms(&self, pos: ChunkPos) -> Vec<Worm> {
+        let center = pos.block(LocalBlockPos::new(8, 0, 8));
+        let rules = &self.rules[&self.biome_at(center.x, center.z)];
+        if rules.cave_density == 0.0 {
+            return vec![];
+        }
+        self.caves.worms(pos, rules.cave_density, rules.cave_size)
+    }
+    /// Lakes starting in the chunk.
+    pub fn lakes_from(&self, pos: ChunkPos) -> Vec<Lake> {
+        let surface_height = |x, z| self.surface_height(x, z);
+        self.lakes
+            .lakes(pos, surface_height, self.settings.sea_level)
+    }
+}
+impl WorldGenerator for NoiseGenerator {
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+    fn generate(&self, pos: ChunkPos) -> ChunkBlocks {
+        let mut blocks = ChunkBlocks::new(self.height);
+        let caves = self.caves.settings();
+        let overhang = self.caves.overhang_grid(pos, self.height);
+        let cavern = self.caves.cavern_grid(pos, self.height);
+        let band = caves.overhang_depth.ceil() as i32;
+        for z in 0..CHUNK_WIDTH {
+            for x in 0..CHUNK_WIDTH {
+                let column = pos.block(LocalBlockPos::new(x, 0, z));
+                let biome = self.biome_at(column.x, column.z);
+                blocks.set_biome(x, z, biome);
+                let rules = &self.rules[&biome];
+                let top = self.surface_height(column.x, column.z);
+                //from the top down, counting how deep under air each block is
+                let mut depth = 0;
+                let highest = (top + band).max(self.settings.sea_level);
+                for y in (self.height.min_y..highest.min(self.height.max_y())).rev() {
+                    let solid = y < top - band
+                        || (y as f64) < top as f64 + overhang.get(x, y, z) * caves.overhang_depth;
+                    if !solid {
+                        depth = 0;
+                        if y < self.settings.sea_level {
+                            blocks.set(LocalBlockPos::new(x, y, z), self.water);
+                        }
+                        continue;
+                    }
+                    let block = if depth == 0 {
+                        rules.surface
+                    } else if depth <= rules.subsurface_depth {
+                        rules.subsurface
+                    } else {
+                        rules.stone
+                    };
+                    blocks.set(LocalBlockPos::new(x, y, z), block);
+                    depth += 1;
+                }
+                //caverns stay under the surface, whatever overhangs do above
+                if rules.cave_density > 0.0 {
+                    let threshold = caves.cavern_threshold / rules.cave_density;
+                    let floor = (caves.min_y + 1).max(self.height.min_y);
+                    let roof = top - band - caves.cavern_cover as i32;
+                    for y in floor..roof {
+                        if cavern.get(x, y, z) > threshold {
+                            blocks.set(LocalBlockPos::new(x, y, z), Block::AIR);
+                        }
+                    }
+                }
+            }
+        }
+        for source in self.caves.sources(pos) {
+            for worm in self.worms(source) {
+                self.caves.carve_worm(&worm, pos, &mut blocks, self.water);
+            }
+        }
+        for source in self.lakes.sources(pos) {
+            for lake in self.lakes_from(source) {
+                self.lakes.fill(&lake, pos, &mut blocks, self.water);
+            }
+        }
+        self.ores.place(pos, &mut blocks);
+        blocks
+    }
+    fn decorate(&self, pos: ChunkPos, blocks: &ChunkBlocks) -> Vec<(BlockPos, Block)> {
+        self.decorator.decorate(pos, blocks)
+    }
+}
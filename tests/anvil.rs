@@ -0,0 +1,215 @@
+//! Reads the region files written by `fixtures/anvil/make_fixtures.py` and round
+//! trips chunks through our writer.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use game_engine::{
+    anvil::{self, BlockMapping},
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    world::World,
+};
+
+fn fixture(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/anvil")
+        .join(path)
+}
+fn read(
+    registry: &BlockRegistry,
+    path: &str,
+    mapping: &BlockMapping,
+) -> HashMap<ChunkPos, ChunkBlocks> {
+    anvil::read_region(&fixture(path), registry, WorldHeight::default(), mapping)
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+fn state(registry: &BlockRegistry, name: &str) -> Block {
+    registry.parse_state(name).unwrap()
+}
+fn assert_same_blocks(a: &ChunkBlocks, b: &ChunkBlocks) {
+    let height = a.height();
+    for y in height.min_y..height.max_y() {
+        for z in 0..16 {
+            for x in 0..16 {
+                let pos = LocalBlockPos::new(x, y, z);
+                assert_eq!(a.get(pos), b.get(pos), "at {:?}", pos);
+            }
+        }
+    }
+}
+
+#[test]
+fn reads_only_finished_chunks() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let chunks = read(
+        &registry,
+        "world/region/r.0.0.mca",
+        &BlockMapping::vanilla(),
+    );
+    let mut positions: Vec<_> = chunks.keys().map(|p| (p.x, p.z)).collect();
+    positions.sort();
+    assert_eq!(positions, vec![(0, 0), (1, 0), (3, 0), (31, 31)]);
+    //a status without the namespace is finished too
+    let sand = state(&registry, "sand");
+    let chunk = &chunks[&ChunkPos::new(3, 0)];
+    assert_eq!(chunk.get(LocalBlockPos::new(7, 5, 7)), sand);
+}
+
+#[test]
+fn maps_vanilla_blocks_onto_the_registry() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let chunks = read(
+        &registry,
+        "world/region/r.0.0.mca",
+        &BlockMapping::vanilla(),
+    );
+    let chunk = &chunks[&ChunkPos::new(0, 0)];
+    let at = |x, y, z| registry.state_name(chunk.get(LocalBlockPos::new(x, y, z)));
+
+    //single state sections have no data
+    assert_eq!(at(3, -64, 9), "stone");
+    assert_eq!(at(15, -49, 15), "stone");
+    assert_eq!(at(0, -48, 0), "air");
+
+    //4 bits per block
+    assert_eq!(at(7, 0, 3), "grass");
+    assert_eq!(at(5, 1, 5), "oak_log[axis=x]");
+    assert_eq!(at(5, 1, 6), "air");
+    assert_eq!(at(15, 15, 15), "stone");
+    assert_eq!(at(15, 14, 15), "air");
+
+    //5 bits per block, the top 4 bits of every long unused
+    for i in 0..4096 {
+        let (x, z, y) = (i % 16, i / 16 % 16, (i / 256) as i32 + 16);
+        let expected = match i % 20 {
+            level @ 0..=15 => format!("water[level={}]", level),
+            16 => "oak_log[axis=y]".to_string(),
+            17 => "oak_log[axis=z]".to_string(),
+            18 => "air".to_string(),
+            _ => "stone".to_string(),
+        };
+        assert_eq!(at(x, y, z), expected, "block {}", i);
+    }
+
+    //section 20 is above the world
+    assert_eq!(at(0, 319, 0), "air");
+
+    let water = &chunks[&ChunkPos::new(1, 0)];
+    assert_eq!(
+        registry.state_name(water.get(LocalBlockPos::new(4, 10, 4))),
+        "water[level=3]"
+    );
+    let planks = &chunks[&ChunkPos::new(31, 31)];
+    assert_eq!(
+        registry.state_name(planks.get(LocalBlockPos::new(0, -16, 0))),
+        "oak_planks"
+    );
+}
+
+#[test]
+fn unknown_blocks_use_the_fallback() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let debris = LocalBlockPos::new(0, 2, 9);
+
+    let chunks = read(
+        &registry,
+        "world/region/r.0.0.mca",
+        &BlockMapping::vanilla(),
+    );
+    assert_eq!(chunks[&ChunkPos::new(0, 0)].get(debris), Block::AIR);
+
+    let mapping = BlockMapping {
+        fallback: state(&registry, "oak_planks"),
+        ..BlockMapping::vanilla()
+    };
+    let chunks = read(&registry, "world/region/r.0.0.mca", &mapping);
+    assert_eq!(
+        chunks[&ChunkPos::new(0, 0)].get(debris),
+        state(&registry, "oak_planks")
+    );
+}
+
+#[test]
+fn reads_negative_regions() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let chunks = read(
+        &registry,
+        "world/region/r.-1.-1.mca",
+        &BlockMapping::vanilla(),
+    );
+    let chunk = &chunks[&ChunkPos::new(-1, -1)];
+    assert_eq!(
+        chunk.get(LocalBlockPos::new(15, -1, 15)),
+        state(&registry, "oak_log[axis=z]")
+    );
+    assert_eq!(chunk.get(LocalBlockPos::new(14, -1, 15)), Block::AIR);
+}
+
+#[test]
+fn rejects_worlds_from_before_1_18() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let error = anvil::read_region(
+        &fixture("old/r.0.0.mca"),
+        &registry,
+        WorldHeight::default(),
+        &BlockMapping::vanilla(),
+    )
+    .unwrap_err();
+    assert!(format!("{:?}", error).contains("1.18"), "{:?}", error);
+}
+
+#[test]
+fn exported_chunks_read_back_the_same() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let mapping = BlockMapping::vanilla();
+    let mut world = World::new(registry.clone(), WorldHeight::default());
+    let imported = world
+        .import_anvil(&fixture("world/region"), &mapping)
+        .unwrap();
+    assert_eq!(imported, 5);
+
+    let dir = std::env::temp_dir().join(format!("anvil_export_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(world.export_anvil(&dir, &mapping).unwrap(), 5);
+
+    let mut reimported = World::new(registry, WorldHeight::default());
+    assert_eq!(reimported.import_anvil(&dir, &mapping).unwrap(), 5);
+    for (pos, chunk) in world.chunks() {
+        assert_same_blocks(&chunk.blocks, &reimported.chunk(*pos).unwrap().blocks);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exports_vanilla_names() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let mapping = BlockMapping::vanilla();
+    assert_eq!(
+        mapping.export_state(&registry, state(&registry, "grass")),
+        ("minecraft:grass_block".to_string(), vec![])
+    );
+    assert_eq!(
+        mapping.export_state(&registry, state(&registry, "oak_log[axis=z]")),
+        (
+            "minecraft:oak_log".to_string(),
+            vec![("axis".to_string(), "z".to_string())]
+        )
+    );
+    //vanilla only has colored glass
+    let glass = state(&registry, "stained_glass");
+    assert_eq!(
+        mapping.export_state(&registry, glass),
+        ("minecraft:light_blue_stained_glass".to_string(), vec![])
+    );
+    assert_eq!(
+        mapping.import_name(&registry, "minecraft:light_blue_stained_glass"),
+        Some(glass)
+    );
+}
@@ -0,0 +1,139 @@
+//! Biomes of the default settings: where they are picked, how their heights blend
+//! and that chunks keep them.
+
+use std::{collections::HashSet, sync::Arc};
+
+use game_engine::{
+    biome::{BiomeId, Biomes},
+    block::BlockRegistry,
+    chunk::{Chunk, WorldHeight},
+    coords::{BlockPos, ChunkPos},
+    region::RegionStorage,
+    world::World,
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn generator(registry: &BlockRegistry) -> NoiseGenerator {
+    let settings = GeneratorSettings::load_default().unwrap();
+    NoiseGenerator::new(1, settings, registry, WorldHeight::default()).unwrap()
+}
+fn biomes() -> Biomes {
+    let settings = GeneratorSettings::load_default().unwrap();
+    Biomes::new(settings.biomes, settings.biome_blend).unwrap()
+}
+fn assert_close(a: (f64, f64), b: (f64, f64)) {
+    assert!(
+        (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn every_biome_appears() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let generator = generator(&registry);
+    let mut found = HashSet::new();
+    for z in (-4096..4096).step_by(128) {
+        for x in (-4096..4096).step_by(128) {
+            found.insert(generator.biome_at(x, z));
+        }
+    }
+    let all: HashSet<_> = generator
+        .biomes()
+        .iter()
+        .map(|def| BiomeId(def.id))
+        .collect();
+    assert_eq!(found, all);
+}
+
+#[test]
+fn picks_by_climate() {
+    let biomes = biomes();
+    let name = |temperature, humidity| biomes.biome_at(temperature, humidity).name.as_str();
+    assert_eq!(name(0.0, 0.0), "plains");
+    assert_eq!(name(0.9, -0.5), "desert");
+    assert_eq!(name(-0.9, -0.5), "taiga");
+    assert_eq!(name(0.0, -0.9), "mountains");
+    assert_eq!(name(0.9, 0.3), "beach");
+    assert_eq!(name(-0.9, 0.9), "ocean");
+    //climates past the ranges go to the closest biome
+    assert_eq!(name(3.0, -3.0), "desert");
+}
+
+#[test]
+fn heights_blend_near_borders_only() {
+    let biomes = biomes();
+    //deep inside a biome its own modifiers are used
+    assert_close(biomes.height_modifiers(0.0, 0.0), (2.0, 0.6));
+    assert_close(biomes.height_modifiers(0.0, -0.9), (22.0, 2.5));
+    //on a border both sides count the same
+    assert_close(biomes.height_modifiers(0.0, -0.3), (12.0, 1.55));
+    assert_close(biomes.height_modifiers(0.0, 0.33), (-7.0, 0.2));
+    //and in between they change gradually
+    let mut last = biomes.height_modifiers(0.0, -0.5);
+    for step in 1..=1000 {
+        let modifiers = biomes.height_modifiers(0.0, -0.5 + step as f64 * 0.001);
+        assert!(
+            (modifiers.0 - last.0).abs() < 0.5,
+            "{:?} {:?}",
+            last,
+            modifiers
+        );
+        last = modifiers;
+    }
+}
+
+#[test]
+fn no_cliffs_at_biome_borders() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let generator = generator(&registry);
+    let mut borders = 0;
+    for z in [-1500, 0, 700] {
+        for x in -2048..2048 {
+            if generator.biome_at(x, z) == generator.biome_at(x + 1, z) {
+                continue;
+            }
+            borders += 1;
+            let step = generator.surface_height(x, z) - generator.surface_height(x + 1, z);
+            assert!(step.abs() <= 3, "{} blocks at {} {}", step, x, z);
+        }
+    }
+    assert!(borders >= 10, "only crossed {} borders", borders);
+}
+
+#[test]
+fn chunks_remember_their_biomes() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let generator = generator(&registry);
+    let pos = ChunkPos::new(-7, 3);
+    let blocks = generator.generate(pos);
+
+    let mut world = World::new(registry.clone(), WorldHeight::default());
+    world.insert_chunk(pos, Chunk::new(blocks.clone()));
+    let (x, z) = pos.origin();
+    for (dx, dz) in [(0, 0), (15, 3), (7, 15)] {
+        let block = BlockPos::new(x + dx, 100, z + dz);
+        assert_eq!(
+            world.biome(block),
+            Some(generator.biome_at(block.x, block.z))
+        );
+    }
+    assert_eq!(world.biome(BlockPos::new(0, 0, 0)), None);
+
+    let dir = std::env::temp_dir().join(format!("biome_save_{}", std::process::id()));
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    storage.save_chunk(pos, &blocks, &registry).unwrap();
+    let loaded = RegionStorage::open(&dir)
+        .unwrap()
+        .load_chunk(pos, &registry, WorldHeight::default())
+        .unwrap()
+        .unwrap();
+    for z in 0..16 {
+        for x in 0..16 {
+            assert_eq!(loaded.biome(x, z), blocks.biome(x, z));
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
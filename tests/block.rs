@@ -0,0 +1,68 @@
+//! Block states: properties interned into state ids, parsed and turned.
+
+use game_engine::block::{BlockRegistry, RenderLayer};
+
+#[test]
+fn parsed_states_are_the_interned_ones() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let log = registry.default_state(registry.expect("oak_log"));
+    assert_eq!(registry.property(log, "axis"), Some("y"));
+    let along_x = registry.with_property(log, "axis", "x").unwrap();
+    assert_ne!(along_x, log);
+    assert_eq!(registry.parse_state("oak_log[axis=x]"), Some(along_x));
+    assert_eq!(registry.parse_state("oak_log"), Some(log));
+    assert_eq!(
+        registry.parse_state(&registry.state_name(along_x)),
+        Some(along_x)
+    );
+    //setting a property to the value it has is the same state
+    assert_eq!(registry.with_property(along_x, "axis", "x"), Some(along_x));
+    assert_eq!(registry.block_type(along_x), registry.block_type(log));
+}
+
+#[test]
+fn unknown_properties_and_values_are_rejected() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let log = registry.default_state(registry.expect("oak_log"));
+    assert_eq!(registry.with_property(log, "axis", "w"), None);
+    assert_eq!(registry.with_property(log, "facing", "north"), None);
+    assert_eq!(registry.parse_state("oak_log[axis=w]"), None);
+    assert_eq!(registry.parse_state("oak_log[facing=north]"), None);
+    assert_eq!(registry.parse_state("oak_log[axis]"), None);
+    assert_eq!(registry.parse_state("oak_log[axis=x"), None);
+    assert_eq!(registry.parse_state("no_such_block"), None);
+    //blocks without properties have none to set
+    let stone = registry.default_state(registry.expect("stone"));
+    assert_eq!(registry.with_property(stone, "axis", "x"), None);
+    assert_eq!(registry.property(stone, "axis"), None);
+}
+
+#[test]
+fn turning_logs_swaps_their_horizontal_axis() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let [x, y, z] = ["x", "y", "z"].map(|axis| {
+        registry
+            .parse_state(&format!("oak_log[axis={}]", axis))
+            .unwrap()
+    });
+    assert_eq!(registry.rotated(x, 1), z);
+    assert_eq!(registry.rotated(z, 1), x);
+    assert_eq!(registry.rotated(x, 3), z);
+    //half turns and upright logs stay the same
+    assert_eq!(registry.rotated(x, 2), x);
+    for turns in 0..4 {
+        assert_eq!(registry.rotated(y, turns), y);
+    }
+    let stone = registry.default_state(registry.expect("stone"));
+    assert_eq!(registry.rotated(stone, 1), stone);
+}
+
+#[test]
+fn blocks_drawn_see_through_dont_hide_whats_behind_them() {
+    let registry = BlockRegistry::load_default().unwrap();
+    for def in registry.iter() {
+        if def.layer != RenderLayer::Opaque {
+            assert!(def.transparent, "{} is drawn see-through", def.name);
+        }
+    }
+}
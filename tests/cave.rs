@@ -0,0 +1,177 @@
+//! Overhangs, caverns and worms of the default generator, with biome cave settings
+//! changed to compare against.
+
+use game_engine::{
+    block::BlockRegistry,
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+/// Generator with the settings changed, and no sea or lakes to fill caves in.
+fn generator(change: impl Fn(&mut GeneratorSettings)) -> NoiseGenerator {
+    let registry = BlockRegistry::load_default().unwrap();
+    let mut settings = GeneratorSettings::load_default().unwrap();
+    settings.sea_level = -64;
+    settings.lakes.per_chunk = 0.0;
+    change(&mut settings);
+    NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).unwrap()
+}
+fn with_caves(density: f64, size: f64) -> impl Fn(&mut GeneratorSettings) {
+    move |settings| {
+        for biome in &mut settings.biomes {
+            biome.cave_density = density;
+            biome.cave_size = size;
+        }
+    }
+}
+fn is_air(blocks: &ChunkBlocks, x: usize, y: i32, z: usize) -> bool {
+    blocks.get(LocalBlockPos::new(x, y, z)).is_air()
+}
+/// Air blocks well under the surface of the chunk.
+fn cave_air(generator: &NoiseGenerator, pos: ChunkPos) -> usize {
+    let blocks = generator.generate(pos);
+    let (origin_x, origin_z) = pos.origin();
+    let mut air = 0;
+    for z in 0..16 {
+        for x in 0..16 {
+            let top = generator.surface_height(origin_x + x as i32, origin_z + z as i32);
+            air += (-64..top - 5).filter(|y| is_air(&blocks, x, *y, z)).count();
+        }
+    }
+    air
+}
+
+#[test]
+fn worms_carve_across_chunk_borders() {
+    let generator = generator(|_| {});
+    let min_y = generator.caves().settings().min_y;
+    let mut from_elsewhere = 0;
+    for pos in [
+        ChunkPos::new(0, 0),
+        ChunkPos::new(1, 0),
+        ChunkPos::new(1, 1),
+    ] {
+        let blocks = generator.generate(pos);
+        let (origin_x, origin_z) = pos.origin();
+        for source in generator.caves().sources(pos) {
+            for worm in generator.worms(source) {
+                let mut carved = false;
+                for point in worm.points {
+                    //blocks well inside each ball
+                    let [x, y, z] = point.pos.map(|v| v.floor() as i32);
+                    let (x, z) = (x - origin_x, z - origin_z);
+                    if point.radius < 1.5
+                        || !(0..16).contains(&x)
+                        || !(0..16).contains(&z)
+                        || y <= min_y
+                    {
+                        continue;
+                    }
+                    assert!(
+                        is_air(&blocks, x as usize, y, z as usize),
+                        "{:?} {:?}",
+                        pos,
+                        point
+                    );
+                    carved = true;
+                }
+                if carved && source != pos {
+                    from_elsewhere += 1;
+                }
+            }
+        }
+    }
+    assert!(from_elsewhere > 0);
+}
+
+#[test]
+fn worms_are_the_same_from_every_chunk() {
+    let generator = generator(|_| {});
+    let sources: Vec<_> = generator.caves().sources(ChunkPos::new(0, 0)).collect();
+    assert!(sources.contains(&ChunkPos::new(3, -2)));
+    let first = generator.worms(ChunkPos::new(3, -2));
+    assert_eq!(generator.worms(ChunkPos::new(3, -2)), first);
+    assert!(sources.iter().any(|pos| !generator.worms(*pos).is_empty()));
+}
+
+#[test]
+fn biomes_set_how_many_caves() {
+    let none = generator(with_caves(0.0, 1.0));
+    let some = generator(with_caves(1.0, 1.0));
+    let more = generator(with_caves(3.0, 1.0));
+    let count = |generator: &NoiseGenerator| -> usize {
+        [
+            ChunkPos::new(0, 0),
+            ChunkPos::new(-3, 2),
+            ChunkPos::new(5, 5),
+        ]
+        .iter()
+        .map(|pos| cave_air(generator, *pos))
+        .sum()
+    };
+    assert_eq!(count(&none), 0);
+    let (some, more) = (count(&some), count(&more));
+    assert!(some > 0);
+    assert!(more > some * 2, "{} {}", some, more);
+}
+
+#[test]
+fn biomes_set_how_wide_tunnels_are() {
+    let narrow = generator(with_caves(1.0, 1.0));
+    let wide = generator(with_caves(1.0, 2.0));
+    let mut compared = 0;
+    for x in -4..4 {
+        let pos = ChunkPos::new(x, 0);
+        for (a, b) in narrow.worms(pos).iter().zip(wide.worms(pos)) {
+            for (a, b) in a.points.iter().zip(b.points) {
+                assert_eq!(a.pos, b.pos);
+                assert!((a.radius * 2.0 - b.radius).abs() < 1e-9);
+                compared += 1;
+            }
+        }
+    }
+    assert!(compared > 0);
+}
+
+#[test]
+fn overhangs_leave_air_under_ground() {
+    let flat = generator(|settings| {
+        with_caves(0.0, 1.0)(settings);
+        settings.caves.overhang_depth = 0.0;
+    });
+    let bent = generator(with_caves(0.0, 1.0));
+    let mut overhangs = 0;
+    //they are rare, so look through a few chunks
+    for pos in (-3..3).flat_map(|z| (-3..3).map(move |x| ChunkPos::new(x, z))) {
+        let (flat_blocks, bent_blocks) = (flat.generate(pos), bent.generate(pos));
+        let (origin_x, origin_z) = pos.origin();
+        for z in 0..16 {
+            for x in 0..16 {
+                //without overhangs the terrain is the height map
+                let top = flat.surface_height(origin_x + x as i32, origin_z + z as i32);
+                for y in top - 8..top + 8 {
+                    assert_eq!(is_air(&flat_blocks, x, y, z), y >= top);
+                }
+                let under_ground = (top - 8..top + 8)
+                    .any(|y| is_air(&bent_blocks, x, y, z) && !is_air(&bent_blocks, x, y + 1, z));
+                overhangs += under_ground as usize;
+            }
+        }
+    }
+    assert!(overhangs > 0);
+}
+
+#[test]
+fn the_floor_is_never_carved() {
+    let generator = generator(with_caves(8.0, 3.0));
+    let min_y = generator.caves().settings().min_y;
+    let blocks = generator.generate(ChunkPos::new(-2, 7));
+    for y in -64..=min_y {
+        for z in 0..16 {
+            for x in 0..16 {
+                assert!(!is_air(&blocks, x, y, z));
+            }
+        }
+    }
+}
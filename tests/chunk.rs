@@ -0,0 +1,125 @@
+//! Palette-encoded block storage of chunks and their sections.
+
+use game_engine::{
+    block::Block,
+    chunk::{ChunkBlocks, PalettedContainer, WorldHeight, SECTION_VOLUME},
+    coords::LocalBlockPos,
+};
+
+/// Checks every entry of the container against `expected`.
+fn assert_values(container: &PalettedContainer<u16>, expected: &[u16]) {
+    assert_eq!(container.len(), expected.len());
+    for (i, value) in expected.iter().enumerate() {
+        assert_eq!(container.get(i), *value, "entry {}", i);
+    }
+}
+
+#[test]
+fn palette_grows_and_repacks_every_value() {
+    let mut container = PalettedContainer::new(SECTION_VOLUME, 0u16);
+    let mut expected = vec![0u16; SECTION_VOLUME];
+    assert_eq!(container.bits_per_entry(), 0);
+    assert_eq!(container.uniform(), Some(0));
+    //values spread over the container, so every long holds a few of them
+    let mut next = 1;
+    for (palette_len, bits) in [(2, 1), (3, 2), (4, 2), (17, 5), (32, 5), (257, 9)] {
+        while container.palette().len() < palette_len {
+            let index = (next as usize * 97) % SECTION_VOLUME;
+            container.set(index, next);
+            expected[index] = next;
+            next += 1;
+        }
+        assert_eq!(container.bits_per_entry(), bits);
+        assert_eq!(
+            container.packed().len(),
+            SECTION_VOLUME.div_ceil(64 / bits as usize)
+        );
+        assert_values(&container, &expected);
+    }
+    assert_eq!(container.uniform(), None);
+
+    //the packed form reads back the same
+    let copy = PalettedContainer::from_parts(
+        SECTION_VOLUME,
+        container.palette().to_vec(),
+        container.bits_per_entry(),
+        container.packed().to_vec(),
+    )
+    .unwrap();
+    assert_values(&copy, &expected);
+}
+
+#[test]
+fn filled_containers_are_a_single_value_again() {
+    let mut container = PalettedContainer::new(SECTION_VOLUME, 0u16);
+    for i in 0..40 {
+        container.set(i * 3, i as u16);
+    }
+    assert_eq!(container.bits_per_entry(), 6);
+    container.fill(7);
+    assert_eq!(container.bits_per_entry(), 0);
+    assert!(container.packed().is_empty());
+    assert_eq!(container.uniform(), Some(7));
+    assert_values(&container, &[7; SECTION_VOLUME]);
+    //and grow from there as before
+    assert_eq!(container.set(5, 8), 7);
+    assert_eq!(container.bits_per_entry(), 1);
+    assert_eq!([container.get(4), container.get(5)], [7, 8]);
+}
+
+#[test]
+fn broken_packed_data_is_rejected() {
+    let len = 64;
+    //a long of 64 one bit entries
+    assert!(PalettedContainer::from_parts(len, vec![1u16, 2], 1, vec![u64::MAX]).is_some());
+    //index 3 is past the end of a palette of three
+    let data = vec![0b11, 0];
+    assert!(PalettedContainer::from_parts(len, vec![1u16, 2, 3], 2, data.clone()).is_none());
+    assert!(PalettedContainer::from_parts(len, vec![1u16, 2, 3, 4], 2, data).is_some());
+    //palettes too big for the bits, empty ones and data of the wrong length
+    assert!(PalettedContainer::from_parts(len, vec![1u16, 2, 3], 1, vec![0]).is_none());
+    assert!(PalettedContainer::from_parts(len, Vec::<u16>::new(), 0, vec![]).is_none());
+    assert!(PalettedContainer::from_parts(len, vec![1u16, 2], 1, vec![0, 0]).is_none());
+    assert!(PalettedContainer::from_parts(len, vec![1u16], 0, vec![]).is_some());
+}
+
+#[test]
+fn blocks_at_section_borders_stay_apart() {
+    let height = WorldHeight::default();
+    let mut blocks = ChunkBlocks::new(height);
+    //the lowest and highest layer of every section, at opposite corners
+    let mut placed = vec![];
+    for index in 0..height.sections {
+        let min_y = height.section_min_y(index);
+        placed.push((LocalBlockPos::new(0, min_y, 0), Block(index as u16 * 2 + 1)));
+        placed.push((
+            LocalBlockPos::new(15, min_y + 15, 15),
+            Block(index as u16 * 2 + 2),
+        ));
+    }
+    for (pos, block) in &placed {
+        assert_eq!(blocks.set(*pos, *block), Block::AIR);
+    }
+    for (pos, block) in &placed {
+        assert_eq!(blocks.get(*pos), *block);
+        //the blocks right next to them across the border are still air
+        for y in [pos.y - 1, pos.y + 1] {
+            let next = LocalBlockPos::new(pos.x as usize, y, pos.z as usize);
+            assert_eq!(blocks.get(next), Block::AIR);
+        }
+    }
+    for (index, section) in blocks.sections().iter().enumerate() {
+        assert_eq!(section.get(0, 0, 0), Block(index as u16 * 2 + 1));
+        assert_eq!(section.get(15, 15, 15), Block(index as u16 * 2 + 2));
+        assert_eq!(section.blocks().palette().len(), 3);
+    }
+    //outside the world is air
+    assert_eq!(
+        blocks.get(LocalBlockPos::new(0, height.max_y(), 0)),
+        Block::AIR
+    );
+    assert_eq!(
+        blocks.get(LocalBlockPos::new(0, height.min_y - 1, 0)),
+        Block::AIR
+    );
+}
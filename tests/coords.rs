@@ -0,0 +1,67 @@
+//! Block, chunk and entity coordinates, rounding towards negative infinity.
+
+use game_engine::coords::{BlockPos, ChunkPos, EntityPos, LocalBlockPos};
+
+#[test]
+fn negative_blocks_are_in_negative_chunks() {
+    for (coordinate, chunk, local) in [
+        (-1, -1, 15),
+        (-16, -1, 0),
+        (-17, -2, 15),
+        (0, 0, 0),
+        (15, 0, 15),
+        (16, 1, 0),
+    ] {
+        let pos = BlockPos::new(coordinate, -5, coordinate);
+        assert_eq!(
+            pos.chunk(),
+            ChunkPos::new(chunk, chunk),
+            "block {}",
+            coordinate
+        );
+        assert_eq!(
+            pos.local(),
+            LocalBlockPos::new(local, -5, local),
+            "block {}",
+            coordinate
+        );
+        //x and z are independent of each other
+        let mixed = BlockPos::new(coordinate, 0, 3);
+        assert_eq!(mixed.chunk(), ChunkPos::new(chunk, 0));
+        assert_eq!(mixed.local(), LocalBlockPos::new(local, 0, 3));
+    }
+}
+
+#[test]
+fn entity_positions_round_down_to_blocks() {
+    let pos = EntityPos::new(-0.5, -0.5, -0.5);
+    assert_eq!(pos.block(), BlockPos::new(-1, -1, -1));
+    assert_eq!(pos.chunk(), ChunkPos::new(-1, -1));
+    assert_eq!(
+        EntityPos::new(0.5, 64.0, -16.0).block(),
+        BlockPos::new(0, 64, -16)
+    );
+    assert_eq!(
+        EntityPos::new(-16.01, 0.0, 15.99).chunk(),
+        ChunkPos::new(-2, 0)
+    );
+}
+
+#[test]
+fn blocks_go_back_to_their_chunk() {
+    for chunk in [
+        ChunkPos::new(-1, -1),
+        ChunkPos::new(-3, 2),
+        ChunkPos::new(4, -7),
+    ] {
+        assert_eq!(chunk.origin(), (chunk.x * 16, chunk.z * 16));
+        for (x, z) in [(0, 0), (15, 15), (7, 12)] {
+            let local = LocalBlockPos::new(x, -20, z);
+            let pos = chunk.block(local);
+            assert_eq!(pos.chunk(), chunk);
+            assert_eq!(pos.local(), local);
+        }
+    }
+    let pos = BlockPos::new(-33, 10, -1);
+    assert_eq!(pos.chunk().block(pos.local()), pos);
+}
@@ -0,0 +1,253 @@
+//! Trees, boulders and flowers: where they grow, and that blocks reaching into
+//! other chunks end up the same whichever order chunks are made in.
+
+use std::sync::Arc;
+
+use game_engine::{
+    biome::{BiomeId, Biomes},
+    block::{Block, BlockRegistry},
+    chunk::{Chunk, ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos, LocalBlockPos},
+    decoration::Decorator,
+    world::World,
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn block(registry: &BlockRegistry, name: &str) -> Block {
+    registry.default_state(registry.expect(name))
+}
+/// A chunk of stone with its top layer at y 0 made of `top`.
+fn flat_chunk(registry: &BlockRegistry, top: &str, biome: u8) -> ChunkBlocks {
+    let mut blocks = ChunkBlocks::new(WorldHeight::default());
+    for z in 0..16 {
+        for x in 0..16 {
+            for y in -4..0 {
+                blocks.set(LocalBlockPos::new(x, y, z), block(registry, "stone"));
+            }
+            blocks.set(LocalBlockPos::new(x, 0, z), block(registry, top));
+            blocks.set_biome(x, z, BiomeId(biome));
+        }
+    }
+    blocks
+}
+/// Decorator with every biome trying `per_chunk` of one feature.
+fn decorator(registry: &BlockRegistry, feature: &str, per_chunk: f64) -> Decorator {
+    let mut settings = GeneratorSettings::load_default().unwrap();
+    for biome in &mut settings.biomes {
+        biome.features = vec![(feature.to_string(), per_chunk)];
+    }
+    let biomes = Biomes::new(settings.biomes, settings.biome_blend).unwrap();
+    Decorator::new(1, Decorator::default_features().unwrap(), &biomes, registry).unwrap()
+}
+/// Generates and decorates the chunks, in order.
+fn load(generator: &NoiseGenerator, world: &mut World, chunks: &[ChunkPos]) {
+    for pos in chunks {
+        let blocks = generator.generate(*pos);
+        let features = generator.decorate(*pos, &blocks);
+        world.insert_chunk(*pos, Chunk::new(blocks));
+        world.place_features(features);
+    }
+}
+
+#[test]
+fn chunk_order_doesnt_change_decorations() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let settings = GeneratorSettings::load_default().unwrap();
+    let generator = NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).unwrap();
+    //a chunk in the middle of a forest
+    let taiga = generator.biomes().by_name("taiga").unwrap().id;
+    let center = (0..200)
+        .map(|x| ChunkPos::new(x, -x))
+        .find(|pos| {
+            (-1..=1).all(|dz| {
+                (-1..=1).all(|dx| {
+                    let (x, z) = pos.offset(dx, dz).origin();
+                    generator.biome_at(x + 8, z + 8) == BiomeId(taiga)
+                })
+            })
+        })
+        .unwrap();
+    let area: Vec<ChunkPos> = (-2..=2)
+        .flat_map(|dz| (-2..=2).map(move |dx| center.offset(dx, dz)))
+        .collect();
+    let mut forwards = World::new(registry.clone(), WorldHeight::default());
+    load(&generator, &mut forwards, &area);
+    let mut backwards = World::new(registry.clone(), WorldHeight::default());
+    let mut reversed = area.clone();
+    reversed.reverse();
+    //half of them first, so features of those wait for the rest
+    load(&generator, &mut backwards, &reversed[..12]);
+    assert!(backwards.pending_count() > 0);
+    load(&generator, &mut backwards, &reversed[12..]);
+
+    let leaves = block(&registry, "spruce_leaves");
+    let mut crossed = 0;
+    for pos in (-1..=1).flat_map(|dz| (-1..=1).map(move |dx| center.offset(dx, dz))) {
+        let plain = generator.generate(pos);
+        let own: Vec<BlockPos> = generator
+            .decorate(pos, &plain)
+            .into_iter()
+            .map(|(at, _)| at)
+            .collect();
+        let (a, b) = (
+            &forwards.chunk(pos).unwrap().blocks,
+            &backwards.chunk(pos).unwrap().blocks,
+        );
+        for y in -64..320 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let local = LocalBlockPos::new(x, y, z);
+                    assert_eq!(a.get(local), b.get(local), "{:?}", pos.block(local));
+                    let from_elsewhere = a.get(local) == leaves
+                        && plain.get(local) != leaves
+                        && !own.contains(&pos.block(local));
+                    crossed += from_elsewhere as usize;
+                }
+            }
+        }
+        assert!(!forwards.chunk(pos).unwrap().modified);
+    }
+    assert!(crossed > 0);
+}
+
+#[test]
+fn blocks_wait_for_their_chunk() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let pos = ChunkPos::new(0, 0);
+    let leaves = block(&registry, "oak_leaves");
+    let mut world = World::new(registry.clone(), WorldHeight::default());
+    world.insert_chunk(pos, Chunk::new(flat_chunk(&registry, "grass", 0)));
+    let outside = BlockPos::new(-1, 1, 3);
+    let inside = BlockPos::new(0, 1, 3);
+    world.place_features(vec![(inside, leaves), (outside, leaves)]);
+    assert_eq!(world.get_block(inside), Some(leaves));
+    assert_eq!(world.pending_count(), 1);
+    //blocks under the ground or out of the world never show up
+    world.place_features(vec![(BlockPos::new(3, 0, 3), leaves)]);
+    world.place_features(vec![(BlockPos::new(-1, 400, 3), leaves)]);
+    assert_eq!(
+        world.get_block(BlockPos::new(3, 0, 3)),
+        Some(block(&registry, "grass"))
+    );
+    assert_eq!(world.pending_count(), 1);
+
+    world.insert_chunk(
+        pos.offset(-1, 0),
+        Chunk::new(flat_chunk(&registry, "grass", 0)),
+    );
+    assert_eq!(world.get_block(outside), Some(leaves));
+    assert_eq!(world.pending_count(), 0);
+    assert!(!world.chunk(pos.offset(-1, 0)).unwrap().modified);
+}
+
+#[test]
+fn logs_grow_through_leaves() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let (log, oak, birch) = (
+        block(&registry, "oak_log"),
+        block(&registry, "oak_leaves"),
+        block(&registry, "birch_leaves"),
+    );
+    for order in [[log, oak, birch], [oak, birch, log], [birch, log, oak]] {
+        let mut world = World::new(registry.clone(), WorldHeight::default());
+        world.insert_chunk(
+            ChunkPos::new(0, 0),
+            Chunk::new(flat_chunk(&registry, "grass", 0)),
+        );
+        let leaf_only = BlockPos::new(5, 5, 5);
+        for block in order {
+            world.place_features(vec![(BlockPos::new(4, 4, 4), block)]);
+            if block != log {
+                world.place_features(vec![(leaf_only, block)]);
+            }
+        }
+        assert_eq!(world.get_block(BlockPos::new(4, 4, 4)), Some(log));
+        //overlapping leaves agree too
+        assert_eq!(
+            world.get_block(leaf_only),
+            Some(if oak.0 > birch.0 { oak } else { birch })
+        );
+    }
+}
+
+#[test]
+fn features_grow_on_their_ground() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let pos = ChunkPos::new(2, -3);
+    let oaks = decorator(&registry, "oak", 6.0);
+    assert!(oaks
+        .decorate(pos, &flat_chunk(&registry, "sand", 0))
+        .is_empty());
+    let placed = oaks.decorate(pos, &flat_chunk(&registry, "grass", 0));
+    let log = block(&registry, "oak_log");
+    let logs: Vec<BlockPos> = placed
+        .iter()
+        .filter(|(_, block)| *block == log)
+        .map(|(at, _)| *at)
+        .collect();
+    //every trunk starts on the ground inside the chunk
+    let roots: Vec<&BlockPos> = logs.iter().filter(|at| at.y == 1).collect();
+    assert!(!roots.is_empty() && roots.len() <= 6);
+    for at in &logs {
+        assert_eq!(at.chunk(), pos);
+        assert!((1..=6).contains(&at.y));
+    }
+    assert_eq!(
+        oaks.decorate(pos, &flat_chunk(&registry, "grass", 0)),
+        placed
+    );
+
+    let cacti =
+        decorator(&registry, "cactus", 4.0).decorate(pos, &flat_chunk(&registry, "sand", 1));
+    assert!(!cacti.is_empty());
+    assert!(cacti.iter().all(|(at, block)| {
+        *block == self::block(&registry, "cactus") && (1..=3).contains(&at.y)
+    }));
+    //flowers stay in the chunk, on top of the grass
+    let flowers =
+        decorator(&registry, "flowers", 2.0).decorate(pos, &flat_chunk(&registry, "grass", 0));
+    assert!(!flowers.is_empty());
+    assert!(flowers.iter().all(|(at, _)| at.chunk() == pos && at.y == 1));
+}
+
+#[test]
+fn bad_features_are_errors() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let settings = GeneratorSettings::load_default().unwrap();
+    let biomes = Biomes::new(settings.biomes.clone(), settings.biome_blend).unwrap();
+    let source = include_str!("../src/features.ron");
+    let features = Decorator::features_from_ron(source).unwrap();
+    assert!(Decorator::new(1, features, &biomes, &registry).is_ok());
+    for (from, to) in [
+        ("\"oak_leaves\"", "\"maple_leaves\""),
+        ("trunk: (4, 6)", "trunk: (6, 4)"),
+        ("trunk: (4, 6)", "trunk: (0, 6)"),
+        ("radius: 2)", "radius: 20)"),
+        ("radius: (1.2, 2.2)", "radius: (0.0, 2.2)"),
+        ("count: 10", "count: 100"),
+        ("on: [\"sand\"]", "on: []"),
+        ("name: \"birch\"", "name: \"oak\""),
+        //plains still lists it
+        ("name: \"flowers\"", "name: \"roses\""),
+    ] {
+        assert!(source.contains(from), "{}", from);
+        let source = source.replacen(from, to, 1);
+        let features = Decorator::features_from_ron(&source).unwrap();
+        assert!(
+            Decorator::new(1, features, &biomes, &registry).is_err(),
+            "{}",
+            to
+        );
+    }
+    assert!(
+        Decorator::features_from_ron(&source.replacen("trunk: (4, 6)", "height: (4, 6)", 1))
+            .is_err()
+    );
+
+    let source = include_str!("../src/worldgen.ron");
+    let from = "(\"cactus\", 0.6)";
+    assert!(source.contains(from));
+    for to in ["(\"cactus\", -1.0)", "(\"cactus\", 100.0)"] {
+        assert!(GeneratorSettings::from_ron(&source.replacen(from, to, 1)).is_err());
+    }
+}
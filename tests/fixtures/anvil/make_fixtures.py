@@ -0,0 +1,221 @@
+#!/usr/bin/env python3
+"""Writes the Anvil region files tests/anvil.rs reads, laid out the way vanilla
+Minecraft 1.20 saves them. Kept separate from the Rust writer so the tests don't
+check our reader against our own mistakes. Run from this directory."""
+
+import gzip
+import os
+import struct
+import zlib
+
+END, BYTE, SHORT, INT, LONG, BYTE_ARRAY, STRING, LIST, COMPOUND, INT_ARRAY, LONG_ARRAY = (
+    0, 1, 2, 3, 4, 7, 8, 9, 10, 11, 12)
+
+
+class Byte(int):
+    pass
+
+
+class Short(int):
+    pass
+
+
+class ByteArray(bytes):
+    pass
+
+
+class IntArray(list):
+    pass
+
+
+class Long(int):
+    pass
+
+
+class LongArray(list):
+    pass
+
+
+def tag_id(value):
+    if isinstance(value, Byte):
+        return BYTE
+    if isinstance(value, Short):
+        return SHORT
+    if isinstance(value, Long):
+        return LONG
+    if isinstance(value, int):
+        return INT
+    if isinstance(value, str):
+        return STRING
+    if isinstance(value, ByteArray):
+        return BYTE_ARRAY
+    if isinstance(value, IntArray):
+        return INT_ARRAY
+    if isinstance(value, LongArray):
+        return LONG_ARRAY
+    if isinstance(value, list):
+        return LIST
+    if isinstance(value, dict):
+        return COMPOUND
+    raise TypeError(value)
+
+
+def string(s):
+    data = s.encode()
+    return struct.pack(">H", len(data)) + data
+
+
+def payload(value):
+    kind = tag_id(value)
+    if kind == BYTE:
+        return struct.pack(">b", value)
+    if kind == SHORT:
+        return struct.pack(">h", value)
+    if kind == LONG:
+        return struct.pack(">q", value)
+    if kind == INT:
+        return struct.pack(">i", value)
+    if kind == STRING:
+        return string(value)
+    if kind == BYTE_ARRAY:
+        return struct.pack(">i", len(value)) + value
+    if kind == INT_ARRAY:
+        return struct.pack(">i", len(value)) + b"".join(struct.pack(">i", v) for v in value)
+    if kind == LONG_ARRAY:
+        return struct.pack(">i", len(value)) + b"".join(struct.pack(">q", v) for v in value)
+    if kind == LIST:
+        element = tag_id(value[0]) if value else END
+        return bytes([element]) + struct.pack(">i", len(value)) + b"".join(payload(v) for v in value)
+    out = b""
+    for name, v in value.items():
+        out += bytes([tag_id(v)]) + string(name) + payload(v)
+    return out + bytes([END])
+
+
+def nbt(root, name=""):
+    return bytes([COMPOUND]) + string(name) + payload(root)
+
+
+def pack(indices, bits):
+    """Palette indices packed into signed longs, entries never spanning two longs."""
+    per_long = 64 // bits
+    longs = []
+    for start in range(0, len(indices), per_long):
+        value = 0
+        for i, index in enumerate(indices[start:start + per_long]):
+            value |= index << (i * bits)
+        longs.append(value - (1 << 64) if value >= 1 << 63 else value)
+    return LongArray(longs)
+
+
+def block(name, **properties):
+    entry = {"Name": "minecraft:" + name}
+    if properties:
+        entry["Properties"] = properties
+    return entry
+
+
+def section(y, palette, indices=None, bits=4):
+    states = {"palette": palette}
+    if indices is not None:
+        states["data"] = pack(indices, bits)
+    return {
+        "Y": Byte(y),
+        "block_states": states,
+        "biomes": {"palette": ["minecraft:plains"]},
+    }
+
+
+def chunk(x, z, sections, status="minecraft:full", version=3465):
+    return {
+        "DataVersion": version,
+        "xPos": x,
+        "zPos": z,
+        "yPos": -4,
+        "Status": status,
+        "LastUpdate": Long(0),
+        "sections": sections,
+    }
+
+
+def region(path, chunks):
+    """chunks: list of (x, z, compression, root)"""
+    header = bytearray(8192)
+    body = bytearray()
+    for x, z, compression, root in chunks:
+        raw = nbt(root)
+        data = {1: lambda d: gzip.compress(d, mtime=0), 2: zlib.compress, 3: lambda d: d}[compression](raw)
+        sector = 2 + len(body) // 4096
+        body += struct.pack(">IB", len(data) + 1, compression) + data
+        body += bytes(-len(body) % 4096)
+        count = 2 + len(body) // 4096 - sector
+        index = (x % 32 + (z % 32) * 32) * 4
+        header[index:index + 4] = struct.pack(">I", sector << 8 | count)
+        header[4096 + index:4096 + index + 4] = struct.pack(">I", 1700000000)
+    with open(path, "wb") as f:
+        f.write(header + body)
+
+
+def mixed_indices():
+    """Section 0 of chunk (0, 0): grass floor, a diagonal of logs along x, ancient
+    debris along the x = 0 edge and one stone in the top corner."""
+    indices = []
+    for i in range(4096):
+        x, z, y = i % 16, i // 16 % 16, i // 256
+        if y == 0:
+            indices.append(1)
+        elif y == 1 and x == z:
+            indices.append(2)
+        elif y == 2 and x == 0:
+            indices.append(4)
+        elif (x, y, z) == (15, 15, 15):
+            indices.append(3)
+        else:
+            indices.append(0)
+    return indices
+
+
+def main():
+    os.makedirs("world/region", exist_ok=True)
+    os.makedirs("old", exist_ok=True)
+
+    mixed = [
+        block("air"),
+        block("grass_block", snowy="false"),
+        block("oak_log", axis="x"),
+        block("stone"),
+        block("ancient_debris"),
+    ]
+    # 20 states, 5 bits each so 12 to a long with 4 bits left over
+    wide = [block("water", level=str(level)) for level in range(16)]
+    wide += [block("oak_log", axis="y"), block("oak_log", axis="z"), block("cave_air"), block("stone")]
+    region("world/region/r.0.0.mca", [
+        (0, 0, 2, chunk(0, 0, [
+            # lighting only section below the world, like vanilla writes
+            {"Y": Byte(-5), "SkyLight": LongArray([])},
+            section(-4, [block("stone")]),
+            section(0, mixed, mixed_indices()),
+            section(1, wide, [i % 20 for i in range(4096)], bits=5),
+            section(20, [block("stone")]),
+        ])),
+        (1, 0, 1, chunk(1, 0, [section(0, [block("water", level="3")])])),
+        (2, 0, 2, chunk(2, 0, [section(0, [block("stone")])], status="minecraft:features")),
+        # statuses without the namespace, as some versions and tools write them
+        (3, 0, 2, chunk(3, 0, [section(0, [block("sand")])], status="full")),
+        (4, 0, 2, chunk(4, 0, [section(0, [block("stone")])], status="features")),
+        (31, 31, 3, chunk(31, 31, [section(-1, [block("oak_planks")])])),
+    ])
+    corner = [1 if i == 4095 else 0 for i in range(4096)]
+    region("world/region/r.-1.-1.mca", [
+        (-1, -1, 2, chunk(-1, -1, [
+            section(-1, [block("air"), block("oak_log", axis="z")], corner),
+        ])),
+    ])
+    # 1.16 layout, sections under Level
+    region("old/r.0.0.mca", [
+        (0, 0, 2, {"DataVersion": 2586, "Level": {"xPos": 0, "zPos": 0, "Status": "full"}}),
+    ])
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,182 @@
+#!/usr/bin/env python3
+"""Writes the same small build as each structure file format tests/schematic.rs
+reads. Run from this directory."""
+
+import gzip
+import os
+import sys
+
+sys.path.insert(0, os.path.join(os.path.dirname(os.path.abspath(__file__)), "..", "anvil"))
+from make_fixtures import ByteArray, IntArray, Long, LongArray, Short, block, nbt  # noqa: E402
+
+WIDTH, HEIGHT, LENGTH = 3, 4, 5
+
+
+def expected(x, y, z):
+    """The build, 3 wide (x), 4 high (y) and 5 long (z)."""
+    if y == 0:
+        return "minecraft:stone"
+    if y == 1 and x == 1:
+        return "minecraft:oak_log[axis=x]"
+    if y == 2 and (x, z) == (0, 0):
+        return "minecraft:diamond_block"
+    if y == 3 and (x, z) == (2, 4):
+        return "minecraft:grass_block[snowy=false]"
+    return "minecraft:air"
+
+
+def positions():
+    """Every position, x fastest then z then y."""
+    for y in range(HEIGHT):
+        for z in range(LENGTH):
+            for x in range(WIDTH):
+                yield x, y, z
+
+
+def entry(state):
+    """Name and Properties compound of a block state string."""
+    name, _, properties = state.partition("[")
+    result = {"Name": name}
+    if properties:
+        result["Properties"] = dict(p.split("=") for p in properties.rstrip("]").split(","))
+    return result
+
+
+def write(path, root, name=""):
+    with open(path, "wb") as f:
+        f.write(gzip.compress(nbt(root, name), mtime=0))
+
+
+def varints(values):
+    out = bytearray()
+    for value in values:
+        while value >= 0x80:
+            out.append(value & 0x7f | 0x80)
+            value >>= 7
+        out.append(value)
+    return ByteArray(out)
+
+
+def sponge_palette():
+    # unused entries push air past 127, so it takes two bytes as a varint
+    palette = {"minecraft:filler_%d" % i: i for i in range(129)}
+    for state in sorted({expected(*p) for p in positions()}, key=lambda s: s != "minecraft:stone"):
+        palette[state] = len(palette)
+    return palette
+
+
+def schem_v2():
+    palette = sponge_palette()
+    write("house_v2.schem", {
+        "Version": 2,
+        "DataVersion": 3465,
+        "Width": Short(WIDTH),
+        "Height": Short(HEIGHT),
+        "Length": Short(LENGTH),
+        "Offset": IntArray([0, 0, 0]),
+        "PaletteMax": len(palette),
+        "Palette": palette,
+        "BlockData": varints(palette[expected(*p)] for p in positions()),
+        "BlockEntities": [],
+    }, name="Schematic")
+
+
+def schem_v3():
+    palette = sponge_palette()
+    write("house_v3.schem", {"Schematic": {
+        "Version": 3,
+        "DataVersion": 3465,
+        "Width": Short(WIDTH),
+        "Height": Short(HEIGHT),
+        "Length": Short(LENGTH),
+        "Offset": IntArray([0, 0, 0]),
+        "Blocks": {
+            "Palette": palette,
+            "Data": varints(palette[expected(*p)] for p in positions()),
+            "BlockEntities": [],
+        },
+    }})
+
+
+def pack_tight(indices, bits):
+    """Litematica packs indices back to back, spanning longs."""
+    value = 0
+    for i, index in enumerate(indices):
+        value |= index << (i * bits)
+    count = (len(indices) * bits + 63) // 64
+    longs = []
+    for i in range(count):
+        long = value >> (i * 64) & (1 << 64) - 1
+        longs.append(long - (1 << 64) if long >= 1 << 63 else long)
+    return LongArray(longs)
+
+
+def litematic_region(position, size, layers, palette, bits):
+    indices = []
+    for y in layers:
+        for z in range(LENGTH):
+            for x in range(WIDTH):
+                indices.append(palette.index(expected(x, y, z)))
+    return {
+        "Position": dict(zip("xyz", position)),
+        "Size": dict(zip("xyz", size)),
+        "BlockStatePalette": [entry(state) for state in palette],
+        "BlockStates": pack_tight(indices, bits),
+        "Entities": [],
+        "TileEntities": [],
+        "PendingBlockTicks": [],
+        "PendingFluidTicks": [],
+    }
+
+
+def litematic():
+    bottom = ["minecraft:air", "minecraft:stone", "minecraft:oak_log[axis=x]"]
+    # five entries need 3 bits, 30 blocks of them spanning two longs
+    top = ["minecraft:air", "minecraft:diamond_block", "minecraft:grass_block[snowy=false]",
+           "minecraft:glass", "minecraft:sand"]
+    size = {"x": WIDTH, "y": HEIGHT, "z": LENGTH}
+    write("house.litematic", {
+        "Version": 6,
+        "MinecraftDataVersion": 3465,
+        "Metadata": {
+            "Name": "house",
+            "Author": "fixtures",
+            "RegionCount": 2,
+            "EnclosingSize": size,
+            "TimeCreated": Long(0),
+        },
+        "Regions": {
+            "bottom": litematic_region((0, 0, 0), (WIDTH, 2, LENGTH), range(0, 2), bottom, 2),
+            # a negative size reaches back from the position, this covers y 2 and 3
+            "top": litematic_region((WIDTH - 1, 3, LENGTH - 1), (-WIDTH, -2, -LENGTH), range(2, 4), top, 3),
+        },
+    })
+
+
+def structure():
+    palette = ["minecraft:air", "minecraft:stone", "minecraft:oak_log[axis=x]",
+               "minecraft:diamond_block", "minecraft:grass_block[snowy=false]"]
+    blocks = []
+    for x, y, z in positions():
+        # structure void, left out of the blocks
+        if (x, y, z) == (1, 3, 1):
+            continue
+        blocks.append({"pos": [x, y, z], "state": palette.index(expected(x, y, z))})
+    write("house.nbt", {
+        "DataVersion": 3465,
+        "size": [WIDTH, HEIGHT, LENGTH],
+        "palette": [entry(state) for state in palette],
+        "blocks": blocks,
+        "entities": [],
+    })
+
+
+def main():
+    schem_v2()
+    schem_v3()
+    litematic()
+    structure()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,79 @@
+#!/usr/bin/env python3
+"""Writes the MagicaVoxel files tests/vox.rs reads. Run from this directory."""
+
+import struct
+
+
+def i32(value):
+    return struct.pack("<i", value)
+
+
+def chunk(id, content=b"", children=b""):
+    return id + i32(len(content)) + i32(len(children)) + content + children
+
+
+def string(s):
+    return i32(len(s)) + s.encode()
+
+
+def dict_(entries):
+    return i32(len(entries)) + b"".join(string(k) + string(v) for k, v in entries.items())
+
+
+def model(size, voxels):
+    xyzi = i32(len(voxels)) + b"".join(bytes(v) for v in voxels)
+    return chunk(b"SIZE", b"".join(i32(v) for v in size)) + chunk(b"XYZI", xyzi)
+
+
+def transform(node, child, frame):
+    return chunk(b"nTRN", i32(node) + dict_({}) + i32(child) + i32(-1) + i32(0) + i32(1) + dict_(frame))
+
+
+def group(node, children):
+    return chunk(b"nGRP", i32(node) + dict_({}) + i32(len(children)) + b"".join(i32(c) for c in children))
+
+
+def shape(node, model_id):
+    return chunk(b"nSHP", i32(node) + dict_({}) + i32(1) + i32(model_id) + dict_({}))
+
+
+def vox(path, children):
+    with open(path, "wb") as f:
+        f.write(b"VOX " + i32(200) + chunk(b"MAIN", children=children))
+
+
+def scene():
+    """Two models moved apart by the scene graph, colored from the default palette."""
+    vox("scene.vox", b"".join([
+        # the second voxel is past the model's size and left out
+        model((2, 1, 3), [(0, 0, 0, 2), (1, 0, 2, 250), (2, 0, 0, 7)]),
+        model((1, 1, 1), [(0, 0, 0, 226)]),
+        transform(0, 1, {}),
+        group(1, [2, 4]),
+        # models are placed by their center, rounded down
+        transform(2, 3, {"_t": "1 0 1"}),
+        shape(3, 0),
+        transform(4, 5, {"_t": "-3 5 0", "_r": "4"}),
+        shape(5, 1),
+        chunk(b"LAYR", i32(0) + dict_({}) + i32(-1)),
+    ]))
+
+
+def palette():
+    """One model and no scene graph, with its own palette."""
+    colors = [(0, 0, 0, 255)] * 256
+    colors[0] = (0x10, 0x20, 0x30, 255)
+    colors[254] = (0xf0, 0xe0, 0xd0, 255)
+    vox("palette.vox", b"".join([
+        model((1, 1, 2), [(0, 0, 0, 1), (0, 0, 1, 255)]),
+        chunk(b"RGBA", b"".join(bytes(c) for c in colors)),
+    ]))
+
+
+def main():
+    scene()
+    palette()
+
+
+if __name__ == "__main__":
+    main()
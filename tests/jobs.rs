@@ -0,0 +1,127 @@
+//! The worker pool chunks are generated and meshed on.
+
+use std::{
+    sync::{mpsc, Mutex},
+    time::{Duration, Instant},
+};
+
+use game_engine::{
+    coords::ChunkPos,
+    jobs::{JobPool, Panicked},
+};
+
+/// Results of the jobs finished since the last poll, none of which may have panicked.
+fn poll<R: Send + 'static>(pool: &JobPool<i32, R>) -> Vec<R> {
+    pool.poll()
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|panicked| panic!("{:?}", panicked)))
+        .collect()
+}
+/// Polls until `count` results came back, or panics after a while.
+fn wait_for<R: Send + 'static>(pool: &JobPool<i32, R>, count: usize) -> Vec<R> {
+    let start = Instant::now();
+    let mut results = vec![];
+    while results.len() < count {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "jobs never finished"
+        );
+        results.extend(poll(pool));
+        std::thread::yield_now();
+    }
+    results
+}
+
+#[test]
+fn every_job_finishes() {
+    let pool = JobPool::new(3, |pos: ChunkPos, job: i32| (pos, job * 2));
+    assert_eq!(pool.threads(), 3);
+    for i in 0..100 {
+        pool.push(ChunkPos::new(i, -i), i);
+    }
+    let mut results = wait_for(&pool, 100);
+    results.sort_by_key(|(_, doubled)| *doubled);
+    let expected: Vec<_> = (0..100).map(|i| (ChunkPos::new(i, -i), i * 2)).collect();
+    assert_eq!(results, expected);
+    assert_eq!(pool.queued(), 0);
+}
+
+#[test]
+fn nearest_jobs_run_first() {
+    //without workers jobs run in `poll`, a few at a time
+    let pool = JobPool::new(0, |pos: ChunkPos, _: i32| pos.x);
+    for x in [9, -3, 5, 1, 12, -7, 0, 4] {
+        pool.push(ChunkPos::new(x, 0), 0);
+    }
+    pool.set_focus(ChunkPos::new(4, 0));
+    assert_eq!(poll(&pool), [4, 5, 1, 0]);
+    pool.set_focus(ChunkPos::new(-8, 0));
+    assert_eq!(poll(&pool), [-7, -3, 9, 12]);
+    assert!(poll(&pool).is_empty());
+
+    //a worker busy with one job takes the nearest of the ones queued meanwhile
+    let (release, wait) = mpsc::channel::<()>();
+    let wait = Mutex::new(wait);
+    let pool = JobPool::new(1, move |pos: ChunkPos, block: i32| {
+        if block == 1 {
+            wait.lock().unwrap().recv().unwrap();
+        }
+        pos.z
+    });
+    pool.push(ChunkPos::new(0, 100), 1);
+    while pool.queued() > 0 {
+        std::thread::yield_now();
+    }
+    for z in [30, -20, 10, 55] {
+        pool.push(ChunkPos::new(0, z), 0);
+    }
+    pool.set_focus(ChunkPos::new(0, 40));
+    release.send(()).unwrap();
+    assert_eq!(wait_for(&pool, 5), [100, 30, 55, 10, -20]);
+}
+
+#[test]
+fn queued_jobs_can_be_dropped() {
+    let pool = JobPool::new(0, |pos: ChunkPos, job: i32| (pos.x, job));
+    for x in 0..10 {
+        pool.push(ChunkPos::new(x, 0), x * 10);
+    }
+    pool.retain(|pos, job| pos.x % 2 == 0 && *job != 40);
+    assert_eq!(pool.queued(), 4);
+    let mut results = poll(&pool);
+    results.extend(poll(&pool));
+    assert_eq!(results, [(0, 0), (2, 20), (6, 60), (8, 80)]);
+}
+
+#[test]
+fn panicking_jobs_are_reported() {
+    let work = |pos: ChunkPos, job: i32| {
+        if job < 0 {
+            panic!("job {} at {:?}", job, pos);
+        }
+        job
+    };
+    for threads in [0, 1] {
+        let pool = JobPool::new(threads, work);
+        pool.push(ChunkPos::new(0, 0), -1);
+        pool.push(ChunkPos::new(1, 0), 2);
+        let start = Instant::now();
+        let mut results = vec![];
+        while results.len() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            results.extend(pool.poll());
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            results[0],
+            Err(Panicked {
+                pos: ChunkPos::new(0, 0),
+                message: "job -1 at ChunkPos { x: 0, z: 0 }".to_string(),
+            })
+        );
+        //the worker goes on with the next job
+        assert_eq!(results[1], Ok(2));
+        pool.push(ChunkPos::new(2, 0), 3);
+        assert_eq!(wait_for(&pool, 1), [3]);
+    }
+}
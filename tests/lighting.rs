@@ -0,0 +1,90 @@
+//! How bright faces are lit by the direction they point.
+
+use game_engine::{
+    lighting::{Lighting, FACES},
+    mesh::{face_normal, Vertex},
+};
+
+const TOP: usize = 0;
+const BOTTOM: usize = 1;
+const LEFT: usize = 2;
+const RIGHT: usize = 3;
+const BACK: usize = 4;
+const FRONT: usize = 5;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-5
+}
+
+#[test]
+fn faces_have_unit_normals() {
+    let normals = [
+        (TOP, [0.0, 1.0, 0.0]),
+        (BOTTOM, [0.0, -1.0, 0.0]),
+        (LEFT, [-1.0, 0.0, 0.0]),
+        (RIGHT, [1.0, 0.0, 0.0]),
+        (BACK, [0.0, 0.0, -1.0]),
+        (FRONT, [0.0, 0.0, 1.0]),
+    ];
+    for (face, normal) in normals {
+        assert_eq!(face_normal(face as u32), normal);
+    }
+    for face in 0..FACES as u32 {
+        let normal = face_normal(face);
+        assert!(close(normal.iter().map(|n| n * n).sum(), 1.0));
+        assert!(close(normal[1], 0.0) || face < 2);
+        //carried by every vertex of the face
+        assert_eq!(Vertex::new([3, 16, 0], face, 0, 9, 2).normal(), normal);
+    }
+}
+
+#[test]
+fn classic_lighting_shades_faces_by_direction() {
+    let shades = Lighting::Classic.shades();
+    assert_eq!(shades[TOP], 1.0);
+    assert_eq!(shades[BOTTOM], 0.5);
+    assert_eq!([shades[BACK], shades[FRONT]], [0.8; 2]);
+    assert_eq!([shades[LEFT], shades[RIGHT]], [0.6; 2]);
+}
+
+#[test]
+fn faces_towards_the_sun_are_brighter() {
+    let overhead = Lighting::Sun {
+        direction: [0.0, 3.0, 0.0],
+        ambient: 0.25,
+    };
+    let shades = overhead.shades();
+    assert_eq!(shades[TOP], 1.0);
+    //sides and bottoms only get the ambient light
+    assert_eq!(shades[BOTTOM..=FRONT], [0.25; 5]);
+
+    let low = overhead.with_sun([1.0, 1.0, 0.0]);
+    let shades = low.shades();
+    assert!(close(shades[TOP], shades[RIGHT]));
+    assert!(shades[RIGHT] > shades[FRONT]);
+    assert_eq!([shades[LEFT], shades[BOTTOM]], [0.25; 2]);
+    //the sides of crosses are lit from either side
+    assert!(shades[6] > 0.25 && close(shades[6], shades[7]));
+    for shade in shades {
+        assert!((0.25..=1.0).contains(&shade));
+    }
+}
+
+#[test]
+fn sun_lighting_can_be_changed_piece_by_piece() {
+    assert!(matches!(Lighting::default(), Lighting::Sun { .. }));
+    let lit = Lighting::Classic.with_ambient(1.0);
+    assert_eq!(lit.shades(), [1.0; FACES]);
+    assert_eq!(
+        lit.with_sun([0.0, -1.0, 0.0]),
+        Lighting::Sun {
+            direction: [0.0, -1.0, 0.0],
+            ambient: 1.0
+        }
+    );
+    let dark = Lighting::Sun {
+        direction: [0.0, 0.0, 0.0],
+        ambient: -2.0,
+    };
+    assert_eq!(dark.shades(), [0.0; FACES]);
+}
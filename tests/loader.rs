@@ -0,0 +1,309 @@
+//! Chunks streaming in and out around the player.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use game_engine::{
+    anvil::{self, BlockMapping},
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos, LocalBlockPos},
+    loader::{ChunkLoader, RenderDistance},
+    region::RegionStorage,
+    world::World,
+    worldgen::WorldGenerator,
+};
+
+fn block(registry: &BlockRegistry, name: &str) -> Block {
+    registry.default_state(registry.expect(name))
+}
+/// Stone up to y 0, with a leaf block every chunk puts into the one west of it.
+struct Flat {
+    stone: Block,
+    leaves: Block,
+}
+impl WorldGenerator for Flat {
+    fn seed(&self) -> u64 {
+        0
+    }
+    fn generate(&self, _pos: ChunkPos) -> ChunkBlocks {
+        let mut blocks = ChunkBlocks::new(WorldHeight::default());
+        for z in 0..16 {
+            for x in 0..16 {
+                blocks.set(LocalBlockPos::new(x, 0, z), self.stone);
+            }
+        }
+        blocks
+    }
+    fn decorate(&self, pos: ChunkPos, _blocks: &ChunkBlocks) -> Vec<(BlockPos, Block)> {
+        let (x, z) = pos.origin();
+        vec![(BlockPos::new(x - 1, 1, z + 5), self.leaves)]
+    }
+}
+fn setup(load: i32) -> (World, ChunkLoader, Block) {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let leaves = block(&registry, "oak_leaves");
+    let generator = Flat {
+        stone: block(&registry, "stone"),
+        leaves,
+    };
+    let world = World::new(registry.clone(), WorldHeight::default());
+    let loader = ChunkLoader::new(Arc::new(generator), registry, RenderDistance::new(load));
+    (world, loader, leaves)
+}
+/// Moves the player to `center` and waits for the chunks around it.
+fn move_to(world: &mut World, loader: &mut ChunkLoader, center: ChunkPos) {
+    let start = Instant::now();
+    loader.update(world, center, 0);
+    while loader.generating() > 0 {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "chunks never loaded"
+        );
+        std::thread::yield_now();
+        loader.update(world, center, 0);
+    }
+}
+fn loaded(world: &World) -> Vec<ChunkPos> {
+    let mut chunks = world.chunk_positions();
+    chunks.sort_by_key(|pos| (pos.x, pos.z));
+    chunks
+}
+
+#[test]
+fn chunks_load_and_unload_around_the_player() {
+    let (mut world, mut loader, _) = setup(2);
+    let distance = loader.distance();
+    assert_eq!(distance.unload, 4);
+    let origin = ChunkPos::new(0, 0);
+    move_to(&mut world, &mut loader, origin);
+    let mut expected = distance.chunks(origin);
+    expected.sort_by_key(|pos| (pos.x, pos.z));
+    assert_eq!(loaded(&world), expected);
+    assert_eq!(expected.len(), 13);
+    assert_eq!(distance.chunks(origin)[0], origin);
+
+    //walking away loads the chunks ahead, keeping the ones behind for a while
+    move_to(&mut world, &mut loader, ChunkPos::new(3, 0));
+    assert!(world.chunk(ChunkPos::new(5, 0)).is_some());
+    assert!(world.chunk(ChunkPos::new(-1, 0)).is_some());
+    assert!(world.chunk(ChunkPos::new(-2, 0)).is_none());
+    for pos in world.chunk_positions() {
+        assert!(distance.keeps(ChunkPos::new(3, 0), pos));
+    }
+    move_to(&mut world, &mut loader, ChunkPos::new(20, 20));
+    assert_eq!(world.chunk_positions().len(), 13);
+}
+
+#[test]
+fn chunks_dont_thrash_at_the_border() {
+    let (mut world, mut loader, _) = setup(3);
+    move_to(&mut world, &mut loader, ChunkPos::new(0, 0));
+    move_to(&mut world, &mut loader, ChunkPos::new(1, 0));
+    let both = loaded(&world);
+    //stepping back and forth over a chunk border loads and unloads nothing
+    for _ in 0..4 {
+        for x in [0, 1] {
+            move_to(&mut world, &mut loader, ChunkPos::new(x, 0));
+            assert_eq!(loaded(&world), both);
+        }
+    }
+}
+
+#[test]
+fn modified_chunks_are_saved_when_unloaded() {
+    let (mut world, mut loader, _) = setup(2);
+    let dir = std::env::temp_dir().join(format!("loader_save_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    world.set_storage(RegionStorage::open(&dir).unwrap());
+    move_to(&mut world, &mut loader, ChunkPos::new(0, 0));
+    let cobblestone = block(world.registry(), "cobblestone");
+    let at = BlockPos::new(3, 7, -2);
+    world.set_block(at, cobblestone);
+
+    move_to(&mut world, &mut loader, ChunkPos::new(10, 0));
+    assert!(world.chunk(at.chunk()).is_none());
+    move_to(&mut world, &mut loader, ChunkPos::new(0, 0));
+    assert_eq!(world.get_block(at), Some(cobblestone));
+    //it matches its save again
+    assert!(!world.chunk(at.chunk()).unwrap().modified);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn features_from_loaded_neighbors_come_back() {
+    let (mut world, mut loader, leaves) = setup(2);
+    move_to(&mut world, &mut loader, ChunkPos::new(0, 0));
+    //put into chunk 0 by chunk 1
+    let at = BlockPos::new(15, 1, 5);
+    assert_eq!(world.get_block(at), Some(leaves));
+    //chunk 0 unloads while chunk 1 stays
+    move_to(&mut world, &mut loader, ChunkPos::new(5, 0));
+    assert!(world.chunk(ChunkPos::new(0, 0)).is_none());
+    assert!(world.chunk(ChunkPos::new(1, 0)).is_some());
+    move_to(&mut world, &mut loader, ChunkPos::new(0, 0));
+    assert_eq!(world.get_block(at), Some(leaves));
+    assert!(!world.chunk(ChunkPos::new(0, 0)).unwrap().modified);
+}
+
+#[test]
+fn imported_chunks_are_meshed() {
+    let (mut world, mut loader, _) = setup(1);
+    let dir = std::env::temp_dir().join(format!("loader_import_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mapping = BlockMapping::vanilla();
+    {
+        let registry = world.registry();
+        let generator = Flat {
+            stone: block(registry, "stone"),
+            leaves: Block::AIR,
+        };
+        let chunks: Vec<(ChunkPos, ChunkBlocks)> = (0..3)
+            .flat_map(|z| (0..3).map(move |x| ChunkPos::new(x, z)))
+            .map(|pos| (pos, generator.generate(pos)))
+            .collect();
+        anvil::write_region(
+            &dir.join("r.0.0.mca"),
+            chunks.iter().map(|(pos, blocks)| (*pos, blocks)),
+            registry,
+            &mapping,
+        )
+        .unwrap();
+    }
+    assert_eq!(world.import_anvil(&dir, &mapping).unwrap(), 9);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    //every chunk the loader wants is imported, so none are generated to mark it dirty
+    let center = ChunkPos::new(1, 1);
+    let start = Instant::now();
+    loop {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the imported chunk was never meshed"
+        );
+        loader.update(&mut world, center, 64);
+        assert_eq!(loader.generating(), 0);
+        let meshes = loader.take_meshes(usize::MAX);
+        if meshes
+            .iter()
+            .any(|(pos, _, mesh)| *pos == center && mesh.is_some())
+        {
+            break;
+        }
+        std::thread::yield_now();
+    }
+}
+
+#[test]
+fn chunks_unloaded_into_storage_are_exported() {
+    let (mut world, mut loader, _) = setup(1);
+    let dir = std::env::temp_dir().join(format!("loader_export_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    world.set_storage(RegionStorage::open(dir.join("save")).unwrap());
+    let mapping = BlockMapping::vanilla();
+    //a 3x3 block of chunks, each with a cobblestone block where no other has one
+    let cobblestone = block(world.registry(), "cobblestone");
+    let marker = |pos: ChunkPos| LocalBlockPos::new((pos.x * 3 + pos.z) as usize, 5, 0);
+    let imported: Vec<ChunkPos> = (0..3)
+        .flat_map(|z| (0..3).map(move |x| ChunkPos::new(x, z)))
+        .collect();
+    let chunks: Vec<(ChunkPos, ChunkBlocks)> = imported
+        .iter()
+        .map(|pos| {
+            let mut blocks = ChunkBlocks::new(WorldHeight::default());
+            blocks.set(marker(*pos), cobblestone);
+            (*pos, blocks)
+        })
+        .collect();
+    let vanilla = dir.join("vanilla");
+    std::fs::create_dir_all(&vanilla).unwrap();
+    anvil::write_region(
+        &vanilla.join("r.0.0.mca"),
+        chunks.iter().map(|(pos, blocks)| (*pos, blocks)),
+        world.registry(),
+        &mapping,
+    )
+    .unwrap();
+    world.import_anvil(&vanilla, &mapping).unwrap();
+
+    //walking away unloads every imported chunk into storage
+    move_to(&mut world, &mut loader, ChunkPos::new(20, 20));
+    for pos in &imported {
+        assert!(world.chunk(*pos).is_none());
+    }
+    let loaded = world.chunk_positions().len();
+    assert_eq!(world.export_anvil(&vanilla, &mapping).unwrap(), 9 + loaded);
+
+    let mut reimported = World::new(
+        Arc::new(BlockRegistry::load_default().unwrap()),
+        WorldHeight::default(),
+    );
+    assert_eq!(
+        reimported.import_anvil(&vanilla, &mapping).unwrap(),
+        9 + loaded
+    );
+    for pos in &imported {
+        let chunk = reimported.chunk(*pos).unwrap();
+        assert_eq!(chunk.blocks.get(marker(*pos)), cobblestone);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// `Flat`, except that generating the chunk `at` panics.
+struct Broken {
+    flat: Flat,
+    at: ChunkPos,
+}
+impl WorldGenerator for Broken {
+    fn seed(&self) -> u64 {
+        0
+    }
+    fn generate(&self, pos: ChunkPos) -> ChunkBlocks {
+        assert_ne!(pos, self.at, "broken chunk");
+        self.flat.generate(pos)
+    }
+    fn decorate(&self, pos: ChunkPos, blocks: &ChunkBlocks) -> Vec<(BlockPos, Block)> {
+        self.flat.decorate(pos, blocks)
+    }
+}
+
+#[test]
+fn chunks_failing_to_generate_dont_hold_up_the_rest() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let broken = ChunkPos::new(1, 0);
+    let generator = Broken {
+        flat: Flat {
+            stone: block(&registry, "stone"),
+            leaves: Block::AIR,
+        },
+        at: broken,
+    };
+    let mut world = World::new(registry.clone(), WorldHeight::default());
+    let mut loader = ChunkLoader::new(Arc::new(generator), registry, RenderDistance::new(1));
+    loader.wait_for(&mut world, broken);
+    assert!(world.chunk(broken).is_none());
+
+    //its neighbor is meshed without it
+    let origin = ChunkPos::new(0, 0);
+    let start = Instant::now();
+    loop {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the neighbor was never meshed"
+        );
+        loader.update(&mut world, broken, 64);
+        let meshes = loader.take_meshes(usize::MAX);
+        if meshes
+            .iter()
+            .any(|(pos, _, mesh)| *pos == origin && mesh.is_some())
+        {
+            break;
+        }
+        std::thread::yield_now();
+    }
+    assert_eq!(loader.generating(), 0);
+    assert!(world.chunk(broken).is_none());
+}
@@ -0,0 +1,548 @@
+//! Section meshes of known block layouts, counted in faces, with both meshers.
+
+use std::sync::Arc;
+
+use game_engine::{
+    block::{Block, BlockRegistry, RenderLayer},
+    chunk::{Chunk, ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos},
+    mesh::{face_is_seen, mesh_section, ChunkMeshData, Mesher, TranslucentQuads, Vertex},
+    world::World,
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn block(registry: &BlockRegistry, name: &str) -> Block {
+    registry.default_state(registry.expect(name))
+}
+/// An empty chunk at the origin, with the eight empty chunks around it if
+/// `neighbors`.
+fn empty_world(neighbors: bool) -> World {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let mut world = World::new(registry, WorldHeight::default());
+    for dz in -1..=1 {
+        for dx in -1..=1 {
+            if neighbors || (dx, dz) == (0, 0) {
+                let blocks = ChunkBlocks::new(WorldHeight::default());
+                world.insert_chunk(ChunkPos::new(dx, dz), Chunk::new(blocks));
+            }
+        }
+    }
+    world
+}
+/// Stone at y 0 from -16 to 32 on both axes, all the chunks of an `empty_world`.
+fn floor(world: &mut World) {
+    for z in -16..32 {
+        for x in -16..32 {
+            set(world, x, 0, z, "stone");
+        }
+    }
+}
+/// Occlusion of the corners of the floor's top face at the block's column, in the
+/// order of their positions.
+fn floor_corners(mesh: &ChunkMeshData, x: u32, z: u32) -> Vec<([u32; 3], u32)> {
+    let quad = mesh
+        .vertices
+        .chunks(4)
+        .find(|quad| {
+            quad.iter().all(|vertex| {
+                let [vx, vy, vz] = vertex.position();
+                vertex.face() == 0
+                    && vy == 1
+                    && (x..=x + 1).contains(&vx)
+                    && (z..=z + 1).contains(&vz)
+            })
+        })
+        .unwrap();
+    let mut corners: Vec<([u32; 3], u32)> = quad
+        .iter()
+        .map(|vertex| (vertex.position(), vertex.occlusion()))
+        .collect();
+    corners.sort();
+    corners
+}
+fn set(world: &mut World, x: i32, y: i32, z: i32, name: &str) {
+    let block = block(world.registry(), name);
+    world.set_block(BlockPos::new(x, y, z), block);
+}
+/// Mesh of the section at y 0 of the chunk at the origin.
+fn mesh(world: &World, mesher: Mesher) -> Option<ChunkMeshData> {
+    let snapshot = world.snapshot(ChunkPos::new(0, 0)).unwrap();
+    let index = snapshot.blocks.height().section_index(0);
+    mesh_section(&snapshot, world.registry(), index, mesher)
+}
+/// Faces of the simple and the greedy mesh, every face being two triangles.
+fn faces(world: &World) -> [usize; 2] {
+    Mesher::ALL.map(|mesher| mesh(world, mesher).map_or(0, |mesh| mesh.indices.len() / 6))
+}
+/// Sum of the areas of the mesh's quads.
+fn area(mesh: &ChunkMeshData) -> f32 {
+    let length = |a: [u32; 3], b: [u32; 3]| {
+        (0..3)
+            .map(|i| (a[i] as f32 - b[i] as f32).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+    mesh.vertices
+        .chunks(4)
+        .map(|quad| {
+            //the first two corners are across the quad from each other
+            let (a, b, c) = (quad[0].position(), quad[1].position(), quad[2].position());
+            length(a, c) * length(c, b)
+        })
+        .sum()
+}
+
+#[test]
+fn lone_block_has_six_faces() {
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "stone");
+    assert_eq!(faces(&world), [6, 6]);
+    let mesh = mesh(&world, Mesher::Greedy).unwrap();
+    assert_eq!(mesh.vertices.len(), 24);
+    assert_eq!(area(&mesh), 6.0);
+}
+
+#[test]
+fn touching_faces_are_hidden() {
+    let mut world = empty_world(true);
+    for x in 0..4 {
+        set(&mut world, x, 3, 3, "stone");
+    }
+    assert_eq!(faces(&world), [18, 6]);
+
+    //a whole layer, with the sides towards the neighboring chunks
+    let mut world = empty_world(true);
+    for z in 0..16 {
+        for x in 0..16 {
+            set(&mut world, x, 0, z, "stone");
+        }
+    }
+    assert_eq!(faces(&world), [576, 6]);
+}
+
+#[test]
+fn unloaded_neighbors_hide_faces() {
+    let mut world = empty_world(false);
+    for z in 0..16 {
+        for x in 0..16 {
+            set(&mut world, x, 0, z, "stone");
+        }
+    }
+    assert_eq!(faces(&world), [512, 2]);
+    let mut world = empty_world(false);
+    set(&mut world, 0, 5, 7, "stone");
+    assert_eq!(faces(&world), [5, 5]);
+}
+
+#[test]
+fn different_textures_dont_merge() {
+    let mut world = empty_world(true);
+    for (x, name) in ["stone", "dirt", "stone", "dirt"].iter().enumerate() {
+        set(&mut world, x as i32, 3, 3, name);
+    }
+    assert_eq!(faces(&world), [18, 18]);
+    //grass under more grass has dirt sides
+    let mut world = empty_world(true);
+    for y in 0..3 {
+        set(&mut world, 3, y, 3, "grass");
+    }
+    assert_eq!(faces(&world), [14, 10]);
+}
+
+#[test]
+fn water_hides_only_water() {
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "water");
+    set(&mut world, 4, 3, 3, "water");
+    assert_eq!(faces(&world), [10, 6]);
+    //the stone's top shows through the water, the water's bottom is hidden
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "stone");
+    set(&mut world, 3, 4, 3, "water");
+    assert_eq!(faces(&world), [11, 11]);
+}
+
+#[test]
+fn leaves_show_whats_behind_them() {
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "oak_leaves");
+    set(&mut world, 4, 3, 3, "oak_leaves");
+    assert_eq!(faces(&world), [10, 6]);
+    //leaves of another tree and the stone under them are seen through them, and
+    //the other way around
+    set(&mut world, 5, 3, 3, "birch_leaves");
+    set(&mut world, 3, 2, 3, "stone");
+    assert_eq!(faces(&world), [4 + 5 + 6 + 6, 18]);
+}
+
+#[test]
+fn crosses_are_two_planes_seen_from_both_sides() {
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "stone");
+    set(&mut world, 3, 4, 3, "dandelion");
+    for mesher in Mesher::ALL {
+        let mesh = mesh(&world, mesher).unwrap();
+        //the stone keeps its top face
+        assert_eq!(mesh.indices.len(), 6 * 6 + 4 * 6);
+        assert_eq!(mesh.vertices.len(), 6 * 4 + 2 * 4);
+    }
+}
+
+#[test]
+fn logs_merge_along_their_axis() {
+    let mut world = empty_world(true);
+    let registry = world.registry();
+    let log = registry
+        .with_property(block(registry, "oak_log"), "axis", "x")
+        .unwrap();
+    for x in 2..5 {
+        world.set_block(BlockPos::new(x, 3, 3), log);
+    }
+    assert_eq!(faces(&world), [14, 6]);
+    //the bark repeats once per block along the log
+    let mesh = mesh(&world, Mesher::Greedy).unwrap();
+    assert_eq!(area(&mesh), 14.0);
+    let long = mesh
+        .vertices
+        .chunks(4)
+        .filter(|quad| {
+            let tex_span = |i: usize| {
+                let coords = quad.iter().map(|vertex| vertex.tex_coords()[i]);
+                coords.clone().max().unwrap() - coords.min().unwrap()
+            };
+            quad[0].tile() == 5 && [tex_span(0), tex_span(1)].contains(&3)
+        })
+        .count();
+    assert_eq!(long, 4);
+}
+
+#[test]
+fn buried_sections_are_skipped() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let stone = block(&registry, "stone");
+    let mut world = World::new(registry, WorldHeight::default());
+    for (dx, dz) in [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+        let mut blocks = ChunkBlocks::new(WorldHeight::default());
+        blocks.fill(stone);
+        world.insert_chunk(ChunkPos::new(dx, dz), Chunk::new(blocks));
+    }
+    assert_eq!(faces(&world), [0, 0]);
+    //digging a hole shows its walls
+    world.set_block(BlockPos::new(3, 3, 3), Block::AIR);
+    assert_eq!(faces(&world), [6, 6]);
+}
+
+#[test]
+fn greedy_meshes_cover_the_same_faces() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let settings = GeneratorSettings::load_default().unwrap();
+    let generator = NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).unwrap();
+    let mut world = World::new(registry.clone(), WorldHeight::default());
+    for z in -1..=1 {
+        for x in -1..=1 {
+            let pos = ChunkPos::new(x, z);
+            let blocks = generator.generate(pos);
+            let features = generator.decorate(pos, &blocks);
+            world.insert_chunk(pos, Chunk::new(blocks));
+            world.place_features(features);
+        }
+    }
+    let snapshot = world.snapshot(ChunkPos::new(0, 0)).unwrap();
+    let (mut simple, mut greedy) = (0, 0);
+    for index in 0..snapshot.blocks.height().sections {
+        let [a, b] = Mesher::ALL.map(|mesher| mesh_section(&snapshot, &registry, index, mesher));
+        assert_eq!(a.is_some(), b.is_some());
+        let (Some(a), Some(b)) = (a, b) else {
+            continue;
+        };
+        assert!((area(&a) - area(&b)).abs() < 0.01, "section {}", index);
+        simple += a.vertices.len();
+        greedy += b.vertices.len();
+    }
+    assert!(greedy < simple);
+}
+
+#[test]
+fn vertices_pack_into_four_bytes() {
+    assert_eq!(std::mem::size_of::<Vertex>(), 4);
+    let vertex = Vertex::new([16, 0, 9], 5, Vertex::FLIP_U, 255, 3);
+    assert_eq!(vertex.position(), [16, 0, 9]);
+    assert_eq!(
+        (
+            vertex.face(),
+            vertex.uv(),
+            vertex.tile(),
+            vertex.occlusion()
+        ),
+        (5, Vertex::FLIP_U, 255, 3)
+    );
+    //on a face towards +z the texture follows x and y
+    assert_eq!(vertex.tex_coords(), [-16, 0]);
+
+    //vertices are relative to their section
+    let mut world = empty_world(true);
+    set(&mut world, 3, 20, 3, "stone");
+    let snapshot = world.snapshot(ChunkPos::new(0, 0)).unwrap();
+    let index = snapshot.blocks.height().section_index(20);
+    let mesh = mesh_section(&snapshot, world.registry(), index, Mesher::Simple).unwrap();
+    assert_eq!(mesh.origin, [0, 16, 0]);
+    for vertex in &mesh.vertices {
+        let [x, y, z] = vertex.position();
+        assert!((3..=4).contains(&x) && (4..=5).contains(&y) && (3..=4).contains(&z));
+    }
+    //every face shows the whole tile once
+    for quad in mesh.vertices.chunks(4) {
+        for i in 0..2 {
+            let coords = quad.iter().map(|vertex| vertex.tex_coords()[i]);
+            assert_eq!(coords.clone().max().unwrap() - coords.min().unwrap(), 1);
+        }
+    }
+}
+
+#[test]
+fn corners_next_to_blocks_are_darker() {
+    let mut world = empty_world(true);
+    floor(&mut world);
+    set(&mut world, 5, 1, 5, "stone");
+    let top = mesh(&world, Mesher::Simple).unwrap();
+    let dark: Vec<[u32; 3]> = top
+        .vertices
+        .iter()
+        .filter(|vertex| vertex.face() == 0 && vertex.position()[1] == 1)
+        .filter(|vertex| vertex.occlusion() > 0)
+        .map(|vertex| vertex.position())
+        .collect();
+    //two corners of each floor face next to the block, one of the diagonal ones
+    assert_eq!(dark.len(), 4 * 2 + 4);
+    for [x, _, z] in dark {
+        assert!((5..=6).contains(&x) && (5..=6).contains(&z));
+    }
+    assert_eq!(
+        floor_corners(&top, 4, 4),
+        [
+            ([4, 1, 4], 0),
+            ([4, 1, 5], 0),
+            ([5, 1, 4], 0),
+            ([5, 1, 5], 1)
+        ]
+    );
+    //the block's own top is out in the open
+    assert!(top
+        .vertices
+        .iter()
+        .filter(|vertex| vertex.position()[1] == 2)
+        .all(|vertex| vertex.occlusion() == 0));
+
+    //in an inside corner the block diagonally across doesn't matter
+    set(&mut world, 6, 1, 4, "stone");
+    let top = mesh(&world, Mesher::Simple).unwrap();
+    assert_eq!(floor_corners(&top, 5, 4)[3], ([6, 1, 5], 3));
+    set(&mut world, 5, 1, 5, "air");
+    set(&mut world, 6, 1, 5, "stone");
+    let top = mesh(&world, Mesher::Simple).unwrap();
+    assert_eq!(floor_corners(&top, 5, 4)[3], ([6, 1, 5], 2));
+    //water lets the light through
+    set(&mut world, 6, 1, 4, "water");
+    set(&mut world, 6, 1, 5, "water");
+    let top = mesh(&world, Mesher::Simple).unwrap();
+    assert_eq!(floor_corners(&top, 5, 4)[3], ([6, 1, 5], 0));
+}
+
+#[test]
+fn blocks_in_chunks_around_darken_corners() {
+    let mut world = empty_world(true);
+    floor(&mut world);
+    //in the chunk diagonally across the corner of the one at the origin
+    set(&mut world, -1, 1, -1, "stone");
+    set(&mut world, 16, 1, 3, "stone");
+    let top = mesh(&world, Mesher::Simple).unwrap();
+    assert_eq!(floor_corners(&top, 0, 0)[0], ([0, 1, 0], 1));
+    assert_eq!(floor_corners(&top, 15, 3)[2], ([16, 1, 3], 1));
+
+    //and changing them remeshes the chunk
+    world.take_dirty(ChunkPos::new(0, 0), usize::MAX, |_| true);
+    set(&mut world, -1, 1, -1, "air");
+    let dirty = world.take_dirty(ChunkPos::new(0, 0), usize::MAX, |_| true);
+    let index = world.height().section_index(0);
+    assert!(dirty.contains(&(ChunkPos::new(0, 0), index)));
+    assert!(dirty.contains(&(ChunkPos::new(-1, -1), index)));
+}
+
+#[test]
+fn quads_split_along_their_lighter_diagonal() {
+    let mut world = empty_world(true);
+    floor(&mut world);
+    for (x, z) in [(5, 5), (9, 3), (10, 3), (2, 12)] {
+        set(&mut world, x, 1, z, "stone");
+    }
+    for mesher in Mesher::ALL {
+        let mesh = mesh(&world, mesher).unwrap();
+        let mut split = 0;
+        for (quad, indices) in mesh.vertices.chunks(4).zip(mesh.indices.chunks(6)) {
+            let occlusion = |i: u32| quad[(i % 4) as usize].occlusion();
+            let (first, second) = (&indices[..3], &indices[3..]);
+            //the two corners both triangles have, and the other two
+            let shared: Vec<u32> = first
+                .iter()
+                .copied()
+                .filter(|i| second.contains(i))
+                .collect();
+            let other: Vec<u32> = (0..4)
+                .map(|i| indices[0] - indices[0] % 4 + i)
+                .filter(|i| !shared.contains(i))
+                .collect();
+            assert_eq!((shared.len(), other.len()), (2, 2));
+            let dark = |corners: &[u32]| corners.iter().map(|i| occlusion(*i)).sum::<u32>();
+            assert!(dark(&shared) <= dark(&other));
+            split += (dark(&shared) < dark(&other)) as usize;
+            //both triangles still face out of the quad, or they would be culled
+            for triangle in indices.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| {
+                    let [x, y, z] = quad[(triangle[i] % 4) as usize].position();
+                    [x as i32, y as i32, z as i32]
+                });
+                let (u, v) = (
+                    [0, 1, 2].map(|i| b[i] - a[i]),
+                    [0, 1, 2].map(|i| c[i] - a[i]),
+                );
+                let cross = [
+                    u[1] * v[2] - u[2] * v[1],
+                    u[2] * v[0] - u[0] * v[2],
+                    u[0] * v[1] - u[1] * v[0],
+                ];
+                let normal = match quad[0].face() {
+                    0 => [0, 1, 0],
+                    1 => [0, -1, 0],
+                    2 => [-1, 0, 0],
+                    3 => [1, 0, 0],
+                    4 => [0, 0, -1],
+                    _ => [0, 0, 1],
+                };
+                assert!((0..3).map(|i| cross[i] * normal[i]).sum::<i32>() > 0);
+            }
+        }
+        assert!(split > 0);
+    }
+}
+
+#[test]
+fn faces_are_hidden_by_what_both_blocks_are() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let [air, stone, water, leaves, dandelion] =
+        ["air", "stone", "water", "oak_leaves", "dandelion"].map(|name| block(&registry, name));
+    let still = registry.with_property(water, "level", "3").unwrap();
+    let seen = |block, neighbor| face_is_seen(&registry, block, neighbor);
+    assert!(seen(stone, air) && seen(leaves, air) && seen(water, air));
+    //opaque blocks show through see-through ones
+    assert!(seen(stone, water) && seen(stone, dandelion) && seen(stone, leaves));
+    assert!(!seen(stone, stone) && !seen(water, stone) && !seen(leaves, stone));
+    //see-through blocks hide their own kind only, whatever its state
+    assert!(!seen(water, water) && !seen(water, still) && !seen(leaves, leaves));
+    assert!(seen(leaves, water) && seen(water, leaves));
+    assert!(seen(water, dandelion) && seen(dandelion, water));
+}
+
+#[test]
+fn faces_go_into_the_layer_of_their_block() {
+    let mut world = empty_world(true);
+    set(&mut world, 3, 3, 3, "stone");
+    set(&mut world, 3, 4, 3, "dandelion");
+    set(&mut world, 8, 3, 3, "oak_leaves");
+    set(&mut world, 12, 3, 3, "water");
+    let registry = world.registry();
+    let tiles = |name: &str| {
+        let model = registry.model(block(registry, name));
+        [model.textures.top, model.textures.side]
+    };
+    for mesher in Mesher::ALL {
+        let mesh = mesh(&world, mesher).unwrap();
+        //the dandelion's two planes are seen from both sides
+        let faces = RenderLayer::ALL.map(|layer| mesh.layer(layer).len() / 6);
+        assert_eq!(faces, [6, 6 + 4, 6]);
+        assert_eq!(mesh.layer_ends[2], mesh.indices.len());
+        let layer_tiles = |layer: RenderLayer| {
+            let mut tiles: Vec<u32> = mesh
+                .layer(layer)
+                .iter()
+                .map(|index| mesh.vertices[*index as usize].tile())
+                .collect();
+            tiles.sort();
+            tiles.dedup();
+            tiles
+        };
+        assert_eq!(layer_tiles(RenderLayer::Opaque), [tiles("stone")[0]]);
+        assert_eq!(
+            layer_tiles(RenderLayer::Cutout),
+            [tiles("oak_leaves")[0], tiles("dandelion")[1]]
+        );
+        assert_eq!(layer_tiles(RenderLayer::Translucent), [tiles("water")[0]]);
+    }
+}
+
+#[test]
+fn translucent_quads_sort_back_to_front() {
+    let mut world = empty_world(true);
+    set(&mut world, 2, 3, 3, "water");
+    set(&mut world, 12, 3, 3, "water");
+    set(&mut world, 7, 3, 3, "stone");
+    let mesh = mesh(&world, Mesher::Greedy).unwrap();
+    let quads = TranslucentQuads::new(&mesh);
+    let center = |quad: &[u32]| {
+        let mut center = [0.0; 3];
+        for index in quad {
+            let position = mesh.vertices[*index as usize].position();
+            for axis in 0..3 {
+                center[axis] += position[axis] as f32 / 6.0;
+            }
+        }
+        center
+    };
+    for eye in [[0.5, 3.5, 3.5], [15.0, 8.0, -2.0], [7.5, 3.5, 3.5]] {
+        let sorted = quads.sorted(eye);
+        let mut indices = sorted.clone();
+        indices.sort();
+        let mut expected = mesh.layer(RenderLayer::Translucent).to_vec();
+        expected.sort();
+        assert_eq!(indices, expected);
+        let distances: Vec<f32> = sorted
+            .chunks(6)
+            .map(|quad| {
+                let center = center(quad);
+                (0..3).map(|axis| (center[axis] - eye[axis]).powi(2)).sum()
+            })
+            .collect();
+        assert_eq!(distances.len(), 12);
+        assert!(distances.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+    //the far block's quads come first
+    let first = center(&quads.sorted([0.5, 3.5, 3.5])[..6]);
+    assert!(first[0] > 12.0);
+    let first = center(&quads.sorted([15.5, 3.5, 3.5])[..6]);
+    assert!(first[0] < 3.0);
+}
+
+#[test]
+fn stained_glass_is_sorted_with_the_water() {
+    let mut world = empty_world(true);
+    set(&mut world, 2, 3, 3, "stained_glass");
+    set(&mut world, 12, 3, 3, "water");
+    let mesh = mesh(&world, Mesher::Greedy).unwrap();
+    assert!(mesh.layer(RenderLayer::Opaque).is_empty());
+    assert_eq!(mesh.layer(RenderLayer::Translucent).len(), 12 * 6);
+    let registry = world.registry();
+    let tile = |name: &str| registry.model(block(registry, name)).textures.side;
+    let quads = TranslucentQuads::new(&mesh);
+    //from either side the other block's faces are drawn first, behind the near ones
+    for (eye, far, near) in [
+        ([0.5, 3.5, 3.5], "water", "stained_glass"),
+        ([15.5, 3.5, 3.5], "stained_glass", "water"),
+    ] {
+        let sorted = quads.sorted(eye);
+        let tiles: Vec<u32> = sorted
+            .iter()
+            .map(|index| mesh.vertices[*index as usize].tile())
+            .collect();
+        assert_eq!(tiles[..36], [tile(far); 36]);
+        assert_eq!(tiles[36..], [tile(near); 36]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Ore veins: where the rules let them go and that the same seed places the same
+//! ones.
+
+use game_engine::{
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    ore::{OreRule, Ores},
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn rule(block: &str, vein_size: u32, count: u32, height: (i32, i32)) -> OreRule {
+    OreRule {
+        block: block.to_string(),
+        vein_size,
+        count,
+        height,
+        host: "stone".to_string(),
+    }
+}
+/// A chunk of 64 blocks of stone under 32 of dirt.
+fn stone_chunk(registry: &BlockRegistry) -> ChunkBlocks {
+    let stone = registry.default_state(registry.expect("stone"));
+    let dirt = registry.default_state(registry.expect("dirt"));
+    let mut blocks = ChunkBlocks::new(WorldHeight::new(-64, 32));
+    for y in -64..32 {
+        for z in 0..16 {
+            for x in 0..16 {
+                let block = if y < 0 { stone } else { dirt };
+                blocks.set(LocalBlockPos::new(x, y, z), block);
+            }
+        }
+    }
+    blocks
+}
+/// Heights of every block of the ore.
+fn heights(registry: &BlockRegistry, blocks: &ChunkBlocks, name: &str) -> Vec<i32> {
+    let ore: Block = registry.default_state(registry.expect(name));
+    let height = blocks.height();
+    let mut heights = vec![];
+    for y in height.min_y..height.max_y() {
+        for z in 0..16 {
+            for x in 0..16 {
+                if blocks.get(LocalBlockPos::new(x, y, z)) == ore {
+                    heights.push(y);
+                }
+            }
+        }
+    }
+    heights
+}
+
+#[test]
+fn same_seed_same_ores() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let place = |seed, pos| {
+        let ores = Ores::new(seed, Ores::default_rules().unwrap(), &registry).unwrap();
+        let mut blocks = stone_chunk(&registry);
+        ores.place(pos, &mut blocks);
+        let counts: Vec<usize> = ores.count(&blocks).iter().map(|(_, n)| *n).collect();
+        (heights(&registry, &blocks, "iron_ore"), counts)
+    };
+    let first = place(1, ChunkPos::new(3, -4));
+    assert!(first.1.iter().all(|count| *count > 0), "{:?}", first.1);
+    assert_eq!(place(1, ChunkPos::new(3, -4)), first);
+    assert_ne!(place(2, ChunkPos::new(3, -4)), first);
+    assert_ne!(place(1, ChunkPos::new(4, -4)), first);
+}
+
+#[test]
+fn veins_stay_in_their_host_and_height() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let rules = vec![
+        rule("coal_ore", 1, 40, (-20, -20)),
+        rule("iron_ore", 8, 10, (-10, -5)),
+        //only dirt up there
+        rule("gold_ore", 8, 50, (5, 25)),
+    ];
+    let ores = Ores::new(7, rules, &registry).unwrap();
+    let mut blocks = stone_chunk(&registry);
+    ores.place(ChunkPos::new(0, 0), &mut blocks);
+
+    let coal = heights(&registry, &blocks, "coal_ore");
+    assert!(!coal.is_empty() && coal.len() <= 40);
+    assert!(coal.iter().all(|y| *y == -20));
+    let iron = heights(&registry, &blocks, "iron_ore");
+    assert!(!iron.is_empty() && iron.len() <= 80);
+    //a vein wanders at most its size away from where it starts
+    assert!(iron.iter().all(|y| (-18..=3).contains(y) && *y < 0));
+    assert!(heights(&registry, &blocks, "gold_ore").is_empty());
+}
+
+#[test]
+fn generated_chunks_have_ores_at_their_heights() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let settings = GeneratorSettings::load_default().unwrap();
+    let generator = NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).unwrap();
+    let mut diamonds = 0;
+    for pos in [ChunkPos::new(0, 0), ChunkPos::new(-6, 2)] {
+        let blocks = generator.generate(pos);
+        let counts = generator.ores().count(&blocks);
+        let names: Vec<&str> = counts.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["coal_ore", "iron_ore", "gold_ore", "diamond_ore"]);
+        let diamond = heights(&registry, &blocks, "diamond_ore");
+        assert!(
+            diamond.iter().all(|y| (-66..=-26).contains(y)),
+            "{:?}",
+            diamond
+        );
+        diamonds += diamond.len();
+    }
+    assert!(diamonds > 0);
+
+    //and a generator can be given other rules
+    let generator = generator
+        .with_ores(vec![rule("gold_ore", 4, 0, (0, 0))], &registry)
+        .unwrap();
+    let counts = generator
+        .ores()
+        .count(&generator.generate(ChunkPos::new(0, 0)));
+    assert_eq!(counts, [("gold_ore", 0)]);
+}
+
+#[test]
+fn report_has_a_row_per_chunk() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let settings = GeneratorSettings::load_default().unwrap();
+    let generator = NoiseGenerator::new(1, settings, &registry, WorldHeight::default())
+        .unwrap()
+        .with_ores(
+            vec![
+                rule("coal_ore", 6, 10, (0, 20)),
+                rule("iron_ore", 6, 10, (-20, 0)),
+            ],
+            &registry,
+        )
+        .unwrap();
+    let chunks = [ChunkPos::new(0, 0), ChunkPos::new(-1, 2)];
+    let report = generator.ores().report(&generator, chunks.iter().copied());
+    let lines: Vec<Vec<&str>> = report
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(lines.len(), 4, "{}", report);
+    assert_eq!(lines[0], ["chunk", "coal_ore", "iron_ore"]);
+    assert_eq!(lines[2][..2], ["-1", "2"]);
+    assert_eq!(lines[3][0], "average");
+    for (column, ore) in [(2, 0), (3, 1)] {
+        let counts: Vec<usize> = chunks
+            .iter()
+            .map(|pos| generator.ores().count(&generator.generate(*pos))[ore].1)
+            .collect();
+        assert_eq!(lines[1][column].parse::<usize>().unwrap(), counts[0]);
+        assert_eq!(lines[2][column].parse::<usize>().unwrap(), counts[1]);
+        let average = (counts[0] + counts[1]) as f64 / 2.0;
+        assert_eq!(lines[3][column - 1], format!("{:.1}", average));
+    }
+}
+
+#[test]
+fn bad_rules_are_errors() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let source = include_str!("../src/ores.ron");
+    assert!(Ores::new(1, Ores::rules_from_ron(source).unwrap(), &registry).is_ok());
+    for (from, to) in [
+        ("\"coal_ore\"", "\"ruby_ore\""),
+        ("host: \"stone\"", "host: \"coal_ore\""),
+        ("vein_size: 12", "vein_size: 0"),
+        ("count: 24", "count: 1000"),
+        ("height: (-16, 96)", "height: (96, -16)"),
+    ] {
+        assert!(source.contains(from), "{}", from);
+        let source = source.replacen(from, to, 1);
+        let rules = Ores::rules_from_ron(&source).unwrap();
+        assert!(Ores::new(1, rules, &registry).is_err(), "{}", to);
+    }
+    assert!(Ores::rules_from_ron(&source.replacen("count", "amount", 1)).is_err());
+}
@@ -0,0 +1,221 @@
+//! Chunks saved to region files and loaded back.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use game_engine::{
+    biome::BiomeId,
+    block::{Block, BlockRegistry},
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    region::{RegionStorage, DATA_VERSION, FORMAT_VERSION},
+};
+
+/// An empty directory for a test's region files.
+fn save_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("region_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+/// Region file of the chunks around the origin.
+fn region_file(dir: &Path) -> PathBuf {
+    dir.join("r.0.0.wcr")
+}
+fn block(registry: &BlockRegistry, name: &str) -> Block {
+    registry.parse_state(name).unwrap()
+}
+/// A layer of stone, which compresses into a single sector.
+fn small_chunk(registry: &BlockRegistry) -> ChunkBlocks {
+    let mut blocks = ChunkBlocks::new(WorldHeight::default());
+    let stone = block(registry, "stone");
+    for z in 0..16 {
+        for x in 0..16 {
+            blocks.set(LocalBlockPos::new(x, 0, z), stone);
+        }
+    }
+    blocks
+}
+/// Every block state scattered over the whole chunk, which barely compresses.
+fn big_chunk(registry: &BlockRegistry) -> ChunkBlocks {
+    let height = WorldHeight::default();
+    let mut blocks = ChunkBlocks::new(height);
+    let states = registry.state_count() as u32;
+    let mut random = 12345u32;
+    for y in height.min_y..height.max_y() {
+        for z in 0..16 {
+            for x in 0..16 {
+                random = random.wrapping_mul(1103515245).wrapping_add(12345);
+                let block = Block(((random >> 16) % states) as u16);
+                blocks.set(LocalBlockPos::new(x, y, z), block);
+            }
+        }
+    }
+    blocks
+}
+fn assert_same(a: &ChunkBlocks, b: &ChunkBlocks) {
+    let height = a.height();
+    assert_eq!(height, b.height());
+    for y in height.min_y..height.max_y() {
+        for z in 0..16 {
+            for x in 0..16 {
+                let pos = LocalBlockPos::new(x, y, z);
+                assert_eq!(a.get(pos), b.get(pos), "block at {:?}", pos);
+            }
+        }
+    }
+    for z in 0..16 {
+        for x in 0..16 {
+            assert_eq!(a.biome(x, z), b.biome(x, z));
+        }
+    }
+}
+fn file_len(dir: &Path) -> u64 {
+    fs::metadata(region_file(dir)).unwrap().len()
+}
+
+#[test]
+fn saved_chunks_load_back() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let height = WorldHeight::default();
+    let dir = save_dir("round_trip");
+    let mut blocks = big_chunk(&registry);
+    blocks.set(
+        LocalBlockPos::new(3, 70, 9),
+        block(&registry, "oak_log[axis=z]"),
+    );
+    blocks.set_biome(4, 5, BiomeId(3));
+    let pos = ChunkPos::new(-1, 2);
+    {
+        let mut storage = RegionStorage::open(&dir).unwrap();
+        storage.save_chunk(pos, &blocks, &registry).unwrap();
+        storage
+            .save_chunk(ChunkPos::new(0, 0), &small_chunk(&registry), &registry)
+            .unwrap();
+    }
+    //read by a storage that didn't write them
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    let loaded = storage.load_chunk(pos, &registry, height).unwrap().unwrap();
+    assert_same(&loaded, &blocks);
+    let loaded = storage
+        .load_chunk(ChunkPos::new(0, 0), &registry, height)
+        .unwrap()
+        .unwrap();
+    assert_same(&loaded, &small_chunk(&registry));
+    let mut saved = storage.saved_chunks().unwrap();
+    saved.sort_by_key(|pos| (pos.x, pos.z));
+    assert_eq!(saved, [pos, ChunkPos::new(0, 0)]);
+    //never saved, in a region file that exists and in one that doesn't
+    assert!(storage
+        .load_chunk(ChunkPos::new(1, 0), &registry, height)
+        .unwrap()
+        .is_none());
+    assert!(storage
+        .load_chunk(ChunkPos::new(100, 100), &registry, height)
+        .unwrap()
+        .is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Writes a region file holding only chunk (0, 0), with the header entry and
+/// record given.
+fn write_raw_region(dir: &Path, format: u16, sector: u32, length: u32, record: &[u8]) {
+    fs::create_dir_all(dir).unwrap();
+    let mut file = b"WCRG".to_vec();
+    file.extend_from_slice(&format.to_le_bytes());
+    file.extend_from_slice(&[0, 0]);
+    file.extend_from_slice(&sector.to_le_bytes());
+    file.extend_from_slice(&length.to_le_bytes());
+    file.resize(3 * 4096, 0);
+    file.extend_from_slice(record);
+    fs::write(region_file(dir), file).unwrap();
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let height = WorldHeight::default();
+    let origin = ChunkPos::new(0, 0);
+
+    let dir = save_dir("format_version");
+    RegionStorage::open(&dir)
+        .unwrap()
+        .save_chunk(origin, &small_chunk(&registry), &registry)
+        .unwrap();
+    let mut file = fs::read(region_file(&dir)).unwrap();
+    assert_eq!(&file[4..6], &FORMAT_VERSION.to_le_bytes());
+    file[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    fs::write(region_file(&dir), file).unwrap();
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    assert!(storage.load_chunk(origin, &registry, height).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+
+    //an uncompressed record of a chunk from a later game
+    let dir = save_dir("data_version");
+    let mut record = vec![0];
+    record.extend_from_slice(&(DATA_VERSION + 1).to_le_bytes());
+    record.extend_from_slice(&(-64i32).to_le_bytes());
+    record.extend_from_slice(&0u16.to_le_bytes());
+    write_raw_region(&dir, FORMAT_VERSION, 3, record.len() as u32, &record);
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    let error = storage.load_chunk(origin, &registry, height).unwrap_err();
+    assert!(format!("{:?}", error).contains("newer"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn records_past_the_end_of_the_file_are_errors() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let height = WorldHeight::default();
+    let origin = ChunkPos::new(0, 0);
+    let record = [0, 1, 2, 3];
+    for (sector, length) in [(3, u32::MAX), (3, 5), (1_000_000, 4), (0, 4)] {
+        let dir = save_dir("past_the_end");
+        write_raw_region(&dir, FORMAT_VERSION, sector, length, &record);
+        let mut storage = RegionStorage::open(&dir).unwrap();
+        let error = storage.load_chunk(origin, &registry, height).unwrap_err();
+        assert!(
+            format!("{:?}", error).contains("doesn't fit"),
+            "{:?}",
+            error
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn freed_sectors_are_reused() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let height = WorldHeight::default();
+    let dir = save_dir("reuse");
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    let [a, b, c, d] = [0, 1, 2, 3].map(|x| ChunkPos::new(x, 0));
+    let (small, big) = (small_chunk(&registry), big_chunk(&registry));
+    storage.save_chunk(a, &small, &registry).unwrap();
+    storage.save_chunk(b, &small, &registry).unwrap();
+    //the header and a sector each
+    assert_eq!(file_len(&dir), 5 * 4096);
+
+    //growing moves the chunk past the others, leaving its sector free for the next
+    storage.save_chunk(a, &big, &registry).unwrap();
+    let grown = file_len(&dir);
+    assert!(grown > 6 * 4096);
+    storage.save_chunk(c, &small, &registry).unwrap();
+    assert_eq!(file_len(&dir), grown);
+
+    //shrinking frees the rest of its sectors, for it and others to use
+    storage.save_chunk(a, &small, &registry).unwrap();
+    storage.save_chunk(d, &small, &registry).unwrap();
+    assert_eq!(file_len(&dir), grown);
+    //and saving again in place doesn't grow the file either
+    storage.save_chunk(b, &small, &registry).unwrap();
+    assert_eq!(file_len(&dir), grown);
+
+    let mut storage = RegionStorage::open(&dir).unwrap();
+    for pos in [a, b, c, d] {
+        let loaded = storage.load_chunk(pos, &registry, height).unwrap().unwrap();
+        assert_same(&loaded, &small);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
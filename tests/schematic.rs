@@ -0,0 +1,233 @@
+//! Reads the build written in every format by `fixtures/schematic/make_fixtures.py`,
+//! pastes it and exports it again.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use game_engine::{
+    anvil::BlockMapping,
+    block::{Block, BlockRegistry},
+    chunk::{Chunk, ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos},
+    schematic::{self, BlockVolume, Rotation},
+    world::World,
+};
+
+const FILES: [&str; 4] = [
+    "house_v2.schem",
+    "house_v3.schem",
+    "house.litematic",
+    "house.nbt",
+];
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/schematic")
+        .join(name)
+}
+/// The build in the fixtures, diamond blocks being one we don't have.
+fn expected(x: usize, y: usize, z: usize) -> &'static str {
+    match (x, y, z) {
+        (_, 0, _) => "stone",
+        (1, 1, _) => "oak_log[axis=x]",
+        (0, 2, 0) => "diamond_block",
+        (2, 3, 4) => "grass",
+        _ => "air",
+    }
+}
+fn name_of(registry: &BlockRegistry, block: Option<Block>) -> String {
+    block.map_or("void".to_string(), |b| registry.state_name(b))
+}
+/// World of loaded, empty chunks from -2 to 1 on both axes.
+fn empty_world(registry: Arc<BlockRegistry>) -> World {
+    let height = WorldHeight::new(0, 32);
+    let mut world = World::new(registry, height);
+    for x in -2..2 {
+        for z in -2..2 {
+            world.insert_chunk(ChunkPos::new(x, z), Chunk::new(ChunkBlocks::new(height)));
+        }
+    }
+    world
+}
+
+#[test]
+fn reads_every_format() {
+    let registry = BlockRegistry::load_default().unwrap();
+    for file in FILES {
+        let volume = schematic::load(&fixture(file), &registry, &BlockMapping::vanilla()).unwrap();
+        assert_eq!(
+            (volume.width(), volume.height(), volume.length()),
+            (3, 4, 5),
+            "{}",
+            file
+        );
+        for y in 0..4 {
+            for z in 0..5 {
+                for x in 0..3 {
+                    let expected = match expected(x, y, z) {
+                        //unknown blocks turn into the fallback
+                        "diamond_block" => "air",
+                        //the structure file leaves one block out
+                        "air" if file.ends_with(".nbt") && (x, y, z) == (1, 3, 1) => "void",
+                        other => other,
+                    };
+                    assert_eq!(
+                        name_of(&registry, volume.get(x, y, z)),
+                        expected,
+                        "{} at {} {} {}",
+                        file,
+                        x,
+                        y,
+                        z
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn unknown_blocks_use_the_fallback() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let planks = registry.parse_state("oak_planks").unwrap();
+    let mapping = BlockMapping {
+        fallback: planks,
+        ..BlockMapping::vanilla()
+    };
+    for file in FILES {
+        let volume = schematic::load(&fixture(file), &registry, &mapping).unwrap();
+        assert_eq!(volume.get(0, 2, 0), Some(planks), "{}", file);
+    }
+}
+
+#[test]
+fn broken_files_are_errors() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let mapping = BlockMapping::vanilla();
+    for file in FILES {
+        let data = std::fs::read(fixture(file)).unwrap();
+        let raw = {
+            use std::io::Read;
+            let mut raw = vec![];
+            flate2::read::GzDecoder::new(&data[..])
+                .read_to_end(&mut raw)
+                .unwrap();
+            raw
+        };
+        //every cut of the uncompressed data, read through the format the file is in
+        let read = |bytes: &[u8]| match file.rsplit('.').next().unwrap() {
+            "schem" => schematic::read_schem(bytes, &registry, &mapping),
+            "litematic" => schematic::read_litematic(bytes, &registry, &mapping),
+            _ => schematic::read_structure(bytes, &registry, &mapping),
+        };
+        assert!(read(&raw).is_ok(), "{}", file);
+        for len in 0..raw.len() {
+            assert!(read(&raw[..len]).is_err(), "{} cut at {}", file, len);
+        }
+        assert!(read(&data[..data.len() / 2]).is_err(), "{}", file);
+    }
+}
+
+#[test]
+fn pastes_turned() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let volume =
+        schematic::load(&fixture("house.nbt"), &registry, &BlockMapping::vanilla()).unwrap();
+    let mut world = empty_world(registry.clone());
+    let origin = BlockPos::new(-2, 5, 3);
+    //60 blocks, one of them structure void
+    assert_eq!(world.paste(&volume, origin, Rotation::Clockwise90), 59);
+
+    let at = |x, y, z| name_of(&registry, world.get_block(origin.offset(x, y, z)));
+    //turned clockwise the build is 5 wide and 3 long, what was at z = 4 now at x = 0
+    assert_eq!(at(0, 3, 2), "grass");
+    assert_eq!(at(4, 0, 2), "stone");
+    for x in 0..5 {
+        assert_eq!(at(x, 1, 1), "oak_log[axis=z]");
+    }
+    assert_eq!(at(0, 1, 0), "air");
+    assert_eq!(at(5, 0, 0), "air");
+}
+
+#[test]
+fn turns_back_to_the_start() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let volume =
+        schematic::load(&fixture("house.nbt"), &registry, &BlockMapping::vanilla()).unwrap();
+    let mut turned = volume.clone();
+    for _ in 0..4 {
+        turned = turned.rotated(&registry, Rotation::Clockwise90);
+    }
+    assert_eq!(turned, volume);
+    let half = volume
+        .rotated(&registry, Rotation::Clockwise90)
+        .rotated(&registry, Rotation::Clockwise90);
+    assert_eq!(half, volume.rotated(&registry, Rotation::Clockwise180));
+    assert_eq!(
+        volume
+            .rotated(&registry, Rotation::Clockwise90)
+            .rotated(&registry, Rotation::CounterClockwise90),
+        volume
+    );
+}
+
+#[test]
+fn skips_chunks_that_are_not_loaded() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let volume =
+        schematic::load(&fixture("house.nbt"), &registry, &BlockMapping::vanilla()).unwrap();
+    let mut world = empty_world(registry);
+    //x 30 to 32, the last column is in chunk 2 which isn't loaded, and the structure
+    //void is in the middle one
+    let placed = world.paste(&volume, BlockPos::new(30, 0, 0), Rotation::None);
+    assert_eq!(placed, 39);
+}
+
+#[test]
+fn exported_chunks_read_back_the_same() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let mapping = BlockMapping::vanilla();
+    let volume = schematic::load(&fixture("house_v2.schem"), &registry, &mapping).unwrap();
+    let mut world = empty_world(registry.clone());
+    world.paste(&volume, BlockPos::new(-3, 2, 14), Rotation::Clockwise180);
+    let copy = world.copy_chunks(ChunkPos::new(0, 1), ChunkPos::new(-1, 0));
+    assert_eq!((copy.width(), copy.height(), copy.length()), (32, 32, 32));
+
+    let dir = std::env::temp_dir().join(format!("schematic_export_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in ["copy.schem", "copy.litematic", "copy.nbt"] {
+        let path = dir.join(file);
+        schematic::save(&path, &copy, &registry, &mapping).unwrap();
+        let read: BlockVolume = schematic::load(&path, &registry, &mapping).unwrap();
+        assert!(read == copy, "{} doesn't match", file);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn blocks_left_alone_are_written_as_air() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let mapping = BlockMapping::vanilla();
+    let stone = registry.parse_state("stone").unwrap();
+    let mut volume = BlockVolume::new(3, 2, 2);
+    volume.set(0, 0, 0, Some(Block::AIR));
+    volume.set(1, 0, 0, Some(stone));
+    volume.set(2, 1, 1, Some(Block::AIR));
+    //the rest is left alone
+    let data = schematic::write_schem(&volume, &registry, &mapping).unwrap();
+    let read = schematic::read_schem(&data, &registry, &mapping).unwrap();
+    for y in 0..2 {
+        for z in 0..2 {
+            for x in 0..3 {
+                let expected = if (x, y, z) == (1, 0, 0) {
+                    stone
+                } else {
+                    Block::AIR
+                };
+                assert_eq!(read.get(x, y, z), Some(expected), "at {:?}", (x, y, z));
+            }
+        }
+    }
+}
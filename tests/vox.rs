@@ -0,0 +1,176 @@
+//! Reads the models written by `fixtures/vox/make_fixtures.py` and round trips world
+//! regions through `.vox`.
+
+use std::{path::Path, sync::Arc};
+
+use game_engine::{
+    block::{Block, BlockRegistry},
+    chunk::{Chunk, ChunkBlocks, WorldHeight},
+    coords::{BlockPos, ChunkPos},
+    schematic::BlockVolume,
+    vox::{self, BlockColors},
+    world::World,
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/vox")
+            .join(name),
+    )
+    .unwrap()
+}
+fn blocks(volume: &BlockVolume) -> Vec<((usize, usize, usize), Block)> {
+    let mut blocks = vec![];
+    for y in 0..volume.height() {
+        for z in 0..volume.length() {
+            for x in 0..volume.width() {
+                if let Some(block) = volume.get(x, y, z) {
+                    blocks.push(((x, y, z), block));
+                }
+            }
+        }
+    }
+    blocks
+}
+/// Every block type but air, in its default state.
+fn block_types(registry: &BlockRegistry) -> Vec<Block> {
+    registry
+        .iter()
+        .filter(|def| def.id != 0)
+        .map(|def| registry.default_state(registry.expect(&def.name)))
+        .collect()
+}
+
+#[test]
+fn places_models_like_the_scene() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let colors = BlockColors::load_default(&registry).unwrap();
+    let volume = vox::read_vox(&fixture("scene.vox"), &colors).unwrap();
+    //5 along x, 6 along MagicaVoxel's y which is our z, and 3 up
+    assert_eq!(
+        (volume.width(), volume.height(), volume.length()),
+        (5, 3, 6)
+    );
+    //colors of the default palette, MagicaVoxel's y turned around
+    assert_eq!(
+        blocks(&volume),
+        vec![
+            ((0, 0, 0), colors.nearest([0x00, 0xee, 0x00])),
+            ((3, 0, 5), colors.nearest([0xff, 0xff, 0xcc])),
+            ((4, 2, 5), colors.nearest([0x88, 0x88, 0x88])),
+        ]
+    );
+}
+
+#[test]
+fn uses_the_palette_in_the_file() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let colors = BlockColors::load_default(&registry).unwrap();
+    let volume = vox::read_vox(&fixture("palette.vox"), &colors).unwrap();
+    assert_eq!(
+        blocks(&volume),
+        vec![
+            ((0, 0, 0), colors.nearest([0x10, 0x20, 0x30])),
+            ((0, 1, 0), colors.nearest([0xf0, 0xe0, 0xd0])),
+        ]
+    );
+}
+
+#[test]
+fn block_colors_match_back_to_their_block() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let colors = BlockColors::load_default(&registry).unwrap();
+    for block in block_types(&registry) {
+        assert_eq!(
+            colors.nearest(colors.color(&registry, block)),
+            block,
+            "{}",
+            registry.state_name(block)
+        );
+    }
+}
+
+#[test]
+fn broken_files_are_errors() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let colors = BlockColors::load_default(&registry).unwrap();
+    for file in ["scene.vox", "palette.vox"] {
+        let data = fixture(file);
+        for len in 0..data.len() {
+            assert!(
+                vox::read_vox(&data[..len], &colors).is_err(),
+                "{} cut at {}",
+                file,
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn exported_regions_read_back_the_same() {
+    let registry = Arc::new(BlockRegistry::load_default().unwrap());
+    let colors = BlockColors::load_default(&registry).unwrap();
+    let height = WorldHeight::new(0, 32);
+    let mut world = World::new(registry.clone(), height);
+    for x in -1..1 {
+        for z in -1..1 {
+            world.insert_chunk(ChunkPos::new(x, z), Chunk::new(ChunkBlocks::new(height)));
+        }
+    }
+    //each block type twice, in rows of 16
+    let types = block_types(&registry);
+    for (i, block) in types.iter().enumerate() {
+        let (x, row) = (-8 + i as i32 % 16, i as i32 / 16);
+        world.set_block(BlockPos::new(x, 3 + row, 2), *block);
+        world.set_block(BlockPos::new(x, 9, x - 4 - row), *block);
+    }
+    let region = world.copy(BlockPos::new(7, 12, 4), BlockPos::new(-10, 1, -14));
+    assert_eq!(
+        (region.width(), region.height(), region.length()),
+        (18, 12, 19)
+    );
+
+    let data = vox::write_vox(&region, &registry, &colors).unwrap();
+    let read = vox::read_vox(&data, &colors).unwrap();
+    assert_eq!((read.width(), read.height(), read.length()), (18, 12, 19));
+    assert_eq!(blocks(&read).len(), types.len() * 2);
+    for y in 0..12 {
+        for z in 0..19 {
+            for x in 0..18 {
+                //air isn't written
+                let expected = region.get(x, y, z).filter(|b| !b.is_air());
+                assert_eq!(read.get(x, y, z), expected, "at {} {} {}", x, y, z);
+            }
+        }
+    }
+}
+
+#[test]
+fn large_regions_are_split_into_models() {
+    let registry = BlockRegistry::load_default().unwrap();
+    let colors = BlockColors::load_default(&registry).unwrap();
+    let stone = registry.parse_state("stone").unwrap();
+    let planks = registry.parse_state("oak_planks").unwrap();
+    let mut volume = BlockVolume::new(300, 2, 3);
+    for x in 0..300 {
+        for z in 0..3 {
+            let block = if (x + z) % 7 == 0 { planks } else { stone };
+            volume.set(x, 0, z, Some(block));
+            volume.set(x, 1, z, Some(Block::AIR));
+        }
+    }
+    let data = vox::write_vox(&volume, &registry, &colors).unwrap();
+    let models = data.windows(4).filter(|id| *id == b"SIZE").count();
+    assert_eq!(models, 2);
+
+    let read = vox::read_vox(&data, &colors).unwrap();
+    assert_eq!((read.width(), read.height(), read.length()), (300, 2, 3));
+    for x in 0..300 {
+        for z in 0..3 {
+            assert_eq!(read.get(x, 0, z), volume.get(x, 0, z), "at {} {}", x, z);
+            assert_eq!(read.get(x, 1, z), None);
+        }
+    }
+}
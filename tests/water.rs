@@ -0,0 +1,173 @@
+//! The sea and lakes of the default generator.
+
+use std::collections::HashSet;
+
+use game_engine::{
+    block::BlockRegistry,
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn generator(change: impl Fn(&mut GeneratorSettings)) -> NoiseGenerator {
+    let registry = BlockRegistry::load_default().unwrap();
+    let mut settings = GeneratorSettings::load_default().unwrap();
+    change(&mut settings);
+    NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).unwrap()
+}
+fn no_lakes(settings: &mut GeneratorSettings) {
+    settings.lakes.per_chunk = 0.0;
+}
+fn no_caves(settings: &mut GeneratorSettings) {
+    no_lakes(settings);
+    for biome in &mut settings.biomes {
+        biome.cave_density = 0.0;
+    }
+}
+/// Every water block of the chunk.
+fn water(blocks: &ChunkBlocks) -> Vec<(usize, i32, usize)> {
+    let registry = BlockRegistry::load_default().unwrap();
+    let water = registry.expect("water");
+    let mut found = vec![];
+    for y in -64..320 {
+        for z in 0..16 {
+            for x in 0..16 {
+                let block = blocks.get(LocalBlockPos::new(x, y, z));
+                if registry.block_type(block) == water {
+                    found.push((x, y, z));
+                }
+            }
+        }
+    }
+    found
+}
+/// A chunk out at sea and one on land.
+fn sea_and_land(generator: &NoiseGenerator) -> (ChunkPos, ChunkPos) {
+    let sea_level = generator.settings().sea_level;
+    let chunks: Vec<_> = (-40..40).map(|x| ChunkPos::new(x, x / 2)).collect();
+    let find = |wet: bool| {
+        *chunks
+            .iter()
+            .find(|pos| {
+                let (x, z) = pos.origin();
+                let corners = [(0, 0), (15, 0), (0, 15), (15, 15)];
+                corners
+                    .iter()
+                    .all(|(dx, dz)| (generator.surface_height(x + dx, z + dz) < sea_level) == wet)
+            })
+            .unwrap()
+    };
+    (find(true), find(false))
+}
+
+#[test]
+fn the_sea_fills_everything_below_sea_level() {
+    let generator = generator(no_caves);
+    let sea_level = generator.settings().sea_level;
+    let (sea, land) = sea_and_land(&generator);
+    for pos in [sea, land] {
+        let blocks = generator.generate(pos);
+        for z in 0..16 {
+            for x in 0..16 {
+                for y in -64..sea_level {
+                    assert!(!blocks.get(LocalBlockPos::new(x, y, z)).is_air());
+                }
+            }
+        }
+        assert!(water(&blocks).iter().all(|(_, y, _)| *y < sea_level));
+    }
+    assert!(water(&generator.generate(sea)).len() > 256);
+    assert!(water(&generator.generate(land)).is_empty());
+}
+
+#[test]
+fn sea_level_is_configurable() {
+    let (sea, _) = sea_and_land(&generator(no_lakes));
+    let dry = generator(|settings| {
+        no_lakes(settings);
+        settings.sea_level = -64;
+    });
+    assert!(water(&dry.generate(sea)).is_empty());
+    let normal = water(&generator(no_lakes).generate(sea)).len();
+    let higher = generator(|settings| {
+        no_lakes(settings);
+        settings.sea_level += 10;
+    });
+    assert_eq!(water(&higher.generate(sea)).len(), normal + 10 * 256);
+}
+
+#[test]
+fn caves_dont_drain_the_sea() {
+    let with_caves = generator(no_lakes);
+    let without = generator(no_caves);
+    let (sea, _) = sea_and_land(&with_caves);
+    for pos in [sea, sea.offset(1, 0), sea.offset(0, -1)] {
+        assert_eq!(
+            water(&with_caves.generate(pos)),
+            water(&without.generate(pos))
+        );
+    }
+}
+
+#[test]
+fn lakes_fill_every_chunk_they_reach() {
+    let generator = generator(|_| {});
+    let sea_level = generator.settings().sea_level;
+    //a lake crossing a chunk border
+    let lake = (-16..16)
+        .flat_map(|x| generator.lakes_from(ChunkPos::new(x, 3)))
+        .find(|lake| {
+            let x = lake.center[0] as i32;
+            (x - lake.radius as i32).div_euclid(16) != (x + lake.radius as i32).div_euclid(16)
+        })
+        .unwrap();
+    assert!(lake.level > sea_level);
+    let center = [lake.center[0] as i32, lake.center[1] as i32];
+    let mut columns = 0;
+    for dx in [-lake.radius as i32, lake.radius as i32] {
+        let pos = ChunkPos::new((center[0] + dx).div_euclid(16), center[1].div_euclid(16));
+        let wet: HashSet<_> = water(&generator.generate(pos)).into_iter().collect();
+        let (origin_x, origin_z) = pos.origin();
+        for z in 0..16 {
+            for x in 0..16 {
+                let Some(bottom) = lake.bottom(origin_x + x as i32, origin_z + z as i32) else {
+                    continue;
+                };
+                assert!(bottom < lake.level);
+                for y in bottom..lake.level {
+                    assert!(wet.contains(&(x, y, z)));
+                }
+                columns += 1;
+            }
+        }
+    }
+    assert!(columns > 10);
+
+    //the banks around it are at least as high as the water
+    let reach = lake.radius as i32 + 2;
+    for z in center[1] - reach..=center[1] + reach {
+        for x in center[0] - reach..=center[0] + reach {
+            let dx = x as f64 + 0.5 - lake.center[0];
+            let dz = z as f64 + 0.5 - lake.center[1];
+            let distance = (dx * dx + dz * dz).sqrt();
+            if lake.bottom(x, z).is_none() && distance < lake.radius + 1.5 {
+                assert!(generator.surface_height(x, z) >= lake.level);
+            }
+        }
+    }
+}
+
+#[test]
+fn bad_water_settings_are_errors() {
+    let source = include_str!("../src/worldgen.ron");
+    for (from, to) in [
+        ("sea_level: 12,", ""),
+        ("per_chunk: 0.5", "per_chunk: -1.0"),
+        ("radius: (3.0, 7.0)", "radius: (7.0, 3.0)"),
+        ("depth: 3", "depth: 100"),
+    ] {
+        assert!(source.contains(from), "{}", from);
+        let source = source.replacen(from, to, 1);
+        assert!(GeneratorSettings::from_ron(&source).is_err(), "{}", to);
+    }
+}
@@ -0,0 +1,166 @@
+//! Generated terrain has to depend on nothing but the seed and the settings, so the
+//! tests hash generated chunks and compare the hashes.
+
+use game_engine::{
+    block::BlockRegistry,
+    chunk::{ChunkBlocks, WorldHeight},
+    coords::{ChunkPos, LocalBlockPos},
+    worldgen::{GeneratorSettings, NoiseGenerator, WorldGenerator},
+};
+
+fn generator(seed: u64) -> NoiseGenerator {
+    let registry = BlockRegistry::load_default().unwrap();
+    let settings = GeneratorSettings::load_default().unwrap();
+    NoiseGenerator::new(seed, settings, &registry, WorldHeight::default()).unwrap()
+}
+/// FNV-1a of every block and biome id, so the hash can't change with the standard
+/// library.
+fn hash(blocks: &ChunkBlocks) -> u64 {
+    let height = blocks.height();
+    let mut ids = vec![];
+    for y in height.min_y..height.max_y() {
+        for z in 0..16 {
+            for x in 0..16 {
+                ids.extend(blocks.get(LocalBlockPos::new(x, y, z)).0.to_le_bytes());
+            }
+        }
+    }
+    for z in 0..16 {
+        for x in 0..16 {
+            ids.push(blocks.biome(x, z).0);
+        }
+    }
+    ids.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+fn hashes(generator: &dyn WorldGenerator) -> Vec<u64> {
+    let mut hashes = vec![];
+    for x in -2..2 {
+        for z in -2..2 {
+            hashes.push(hash(&generator.generate(ChunkPos::new(x, z))));
+        }
+    }
+    hashes
+}
+
+#[test]
+fn same_seed_same_chunks() {
+    assert_eq!(hashes(&generator(1)), hashes(&generator(1)));
+    let big = u64::MAX - 12;
+    assert_eq!(hashes(&generator(big)), hashes(&generator(big)));
+}
+
+#[test]
+fn order_doesnt_matter() {
+    let generator = generator(42);
+    let pos = ChunkPos::new(3, -7);
+    let first = hash(&generator.generate(pos));
+    hashes(&generator);
+    assert_eq!(hash(&generator.generate(pos)), first);
+}
+
+#[test]
+fn different_seeds_different_chunks() {
+    let one = hashes(&generator(1));
+    for seed in [0, 2, 1 << 32, u64::MAX] {
+        let other = hashes(&generator(seed));
+        assert!(one.iter().zip(&other).all(|(a, b)| a != b), "seed {}", seed);
+    }
+}
+
+#[test]
+fn terrain_is_the_same_as_before() {
+    //if the generator changes on purpose, these have to be updated
+    let generator = generator(1);
+    assert_eq!(
+        hash(&generator.generate(ChunkPos::new(0, 0))),
+        9862435353845490091
+    );
+    assert_eq!(
+        hash(&generator.generate(ChunkPos::new(-5, 9))),
+        16583286084786441829
+    );
+}
+
+#[test]
+fn columns_follow_their_biome() {
+    let registry = BlockRegistry::load_default().unwrap();
+    //without caves, overhangs, water and ores, which have tests of their own
+    let mut settings = GeneratorSettings::load_default().unwrap();
+    settings.caves.overhang_depth = 0.0;
+    settings.sea_level = -64;
+    settings.lakes.per_chunk = 0.0;
+    for biome in &mut settings.biomes {
+        biome.cave_density = 0.0;
+    }
+    let generator = NoiseGenerator::new(7, settings, &registry, WorldHeight::default())
+        .unwrap()
+        .with_ores(vec![], &registry)
+        .unwrap();
+    for pos in [ChunkPos::new(1, 1), ChunkPos::new(-40, 25)] {
+        let blocks = generator.generate(pos);
+        let (origin_x, origin_z) = pos.origin();
+        for z in 0..16 {
+            for x in 0..16 {
+                let (column_x, column_z) = (origin_x + x as i32, origin_z + z as i32);
+                let biome = generator.biome_at(column_x, column_z);
+                assert_eq!(blocks.biome(x, z), biome);
+                let def = generator.biomes().get(biome).unwrap();
+                let top = generator.surface_height(column_x, column_z);
+                let at = |y| registry.state_name(blocks.get(LocalBlockPos::new(x, y, z)));
+                assert_eq!(at(top), "air");
+                assert_eq!(at(top - 1), def.surface_block);
+                let depth = def.subsurface_depth as i32;
+                if depth > 0 {
+                    assert_eq!(at(top - 1 - depth), def.subsurface_block);
+                }
+                assert_eq!(at(top - 2 - depth), def.stone_block);
+                assert_eq!(at(-64), def.stone_block);
+            }
+        }
+    }
+}
+
+#[test]
+fn height_curve_is_linear_between_points() {
+    let mut settings = GeneratorSettings::load_default().unwrap();
+    settings.height_curve = vec![(-0.5, 10.0), (0.0, 20.0), (0.5, 60.0)];
+    assert_eq!(settings.height_at(-1.0), 10.0);
+    assert_eq!(settings.height_at(-0.25), 15.0);
+    assert_eq!(settings.height_at(0.0), 20.0);
+    assert_eq!(settings.height_at(0.25), 40.0);
+    assert_eq!(settings.height_at(1.0), 60.0);
+}
+
+#[test]
+fn bad_settings_are_errors() {
+    let source = include_str!("../src/worldgen.ron");
+    assert!(GeneratorSettings::from_ron(source).is_ok());
+    for (from, to) in [
+        ("octaves: 4", "octaves: 0"),
+        ("scale: 0.03", "scale: 0.0"),
+        ("persistence: 0.5", "persistence: -1.0"),
+        ("height_curve: [", "height_curve: [(2.0, 0.0), "),
+        ("biome_blend: 0.06", "biome_blend: 0.0"),
+        ("biomes: [", "colour: 3, biomes: ["),
+        ("biome_blend", "biomes: [], biome_blend"),
+        ("id: 3,", "id: 0,"),
+        ("humidity: (0.33, 1.0)", "humidity: (1.0, 0.33)"),
+        ("overhang_depth: 6.0", "overhang_depth: -1.0"),
+        ("overhang_stretch: 4.0", "overhang_stretch: 0.0"),
+        ("worm_length: (40, 110)", "worm_length: (110, 40)"),
+        ("worm_radius: (1.5, 3.0)", "worm_radius: (0.0, 3.0)"),
+        ("cave_density: 0.5", "cave_density: 20.0"),
+        ("cave_size: 1.4", "cave_size: -1.0"),
+    ] {
+        assert!(source.contains(from), "{}", from);
+        let source = source.replacen(from, to, 1);
+        assert!(GeneratorSettings::from_ron(&source).is_err(), "{}", to);
+    }
+
+    let registry = BlockRegistry::load_default().unwrap();
+    let source = source.replacen("\"gravel\"", "\"bedrock\"", 1);
+    let settings = GeneratorSettings::from_ron(&source).unwrap();
+    assert!(NoiseGenerator::new(1, settings, &registry, WorldHeight::default()).is_err());
+}